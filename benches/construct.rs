@@ -0,0 +1,49 @@
+//! Measure the time it takes to build an index over a large generated corpus.
+//! Compare `cargo bench --bench construct` with `cargo bench --bench construct --features rayon`.
+
+use std::time::Instant;
+
+use zearch::Index;
+
+fn main() {
+    let nb_documents = std::env::var("NB_DOCUMENTS")
+        .ok()
+        .and_then(|nb| nb.parse().ok())
+        .unwrap_or(1_000_000);
+
+    // a xorshift is enough to get a corpus that doesn't compress too well
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let vocabulary: Vec<String> = (0..50_000)
+        .map(|_| {
+            let len = 3 + random() % 8;
+            (0..len)
+                .map(|_| (b'a' + (random() % 26) as u8) as char)
+                .collect()
+        })
+        .collect();
+    let documents: Vec<String> = (0..nb_documents)
+        .map(|_| {
+            let len = 4 + random() % 12;
+            (0..len)
+                .map(|_| vocabulary[(random() % vocabulary.len() as u64) as usize].as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+
+    let now = Instant::now();
+    let index = Index::new_in_memory(&documents);
+    let elapsed = now.elapsed();
+    std::hint::black_box(index);
+
+    println!(
+        "built an index over {nb_documents} documents in {elapsed:.2?} (rayon: {})",
+        cfg!(feature = "rayon")
+    );
+}
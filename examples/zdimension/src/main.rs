@@ -39,7 +39,7 @@ fn main() {
         }
 
         let now = std::time::Instant::now();
-        let ret = index.search(&Search::new(&input));
+        let ret = index.search_ids(&Search::new(&input));
 
         println!("Found (in {:?}):", now.elapsed());
         for id in ret {
@@ -0,0 +1,106 @@
+//! A batch of insertions and deletions applied at once, see [`Index::batch`].
+//! Inserting a document one by one may rebuild the fst whenever the pending words grow
+//! too big, a batch only rebuilds it once when it's committed.
+use crate::{Id, Index};
+
+enum Operation<D> {
+    Insert(D),
+    Delete(Id),
+}
+
+/// The operations are kept in order and nothing touches the index until [`Batch::commit`]
+#[must_use = "the batch is discarded unless it's committed"]
+pub struct Batch<'i, 'a, T: ?Sized + ToOwned = str> {
+    index: &'i mut Index<'a, T>,
+    operations: Vec<Operation<T::Owned>>,
+    // the id of the next inserted document
+    next_id: Id,
+}
+
+impl<'i, 'a, T: ?Sized + ToOwned + AsRef<str>> Batch<'i, 'a, T> {
+    pub(crate) fn new(index: &'i mut Index<'a, T>) -> Self {
+        Self {
+            next_id: index.documents.len() as Id,
+            index,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Queue the insertion of a document and returns the id it will get once committed.
+    /// It can be deleted in the same batch with this id.
+    pub fn insert(&mut self, document: T::Owned) -> Id {
+        self.operations.push(Operation::Insert(document));
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Queue the deletion of a document, the ids that don't exist or are already deleted
+    /// when the deletion is applied are ignored like with [`Index::delete`].
+    pub fn delete(&mut self, id: Id) -> &mut Self {
+        self.operations.push(Operation::Delete(id));
+        self
+    }
+
+    /// Apply all the operations in order then merge the new words into the fst.
+    /// Returns the number of documents that were actually deleted.
+    pub fn commit(self) -> usize {
+        if self.operations.is_empty() {
+            return 0;
+        }
+
+        let mut deleted = 0;
+        for operation in self.operations {
+            match operation {
+                Operation::Insert(document) => {
+                    self.index.insert_pending(document);
+                }
+                Operation::Delete(id) => deleted += self.index.delete(id) as usize,
+            }
+        }
+        // it also drops the words the deleted documents were the last to contain
+        self.index.merge_pending();
+
+        deleted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, Search};
+
+    #[test]
+    fn test_batch() {
+        let mut index = Index::new_in_memory(&["tamo le plus beau", "kefir le chien"]);
+
+        let mut batch = index.batch();
+        let poulpe = batch.insert(String::from("tamo le poulpe"));
+        let poney = batch.insert(String::from("kefir le poney"));
+        batch.delete(0).delete(poney).delete(1000);
+        assert_eq!((poulpe, poney), (2, 3));
+        assert_eq!(batch.commit(), 2);
+
+        // everything was merged into the fst at once
+        assert!(index.pending.is_empty());
+        assert!(index.fst.get("poulpe").is_some());
+        // and the words of the deleted documents were dropped
+        assert!(index.fst.get("poney").is_none());
+        insta::assert_debug_snapshot!(index.search(&Search::new("tamo")), @r#"
+        [
+            "tamo le poulpe",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search(&Search::new("poney")), @"[]");
+
+        // a dropped batch changes nothing
+        let mut batch = index.batch();
+        batch.insert(String::from("tamo le retour"));
+        batch.delete(1);
+        drop(batch);
+        insta::assert_debug_snapshot!(index.search(&Search::new("")), @r#"
+        [
+            "kefir le chien",
+            "tamo le poulpe",
+        ]
+        "#);
+    }
+}
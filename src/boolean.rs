@@ -0,0 +1,280 @@
+//! The boolean operators of the query, see [`Search::with_boolean_operators`](crate::Search::with_boolean_operators).
+//! The words are grouped with `AND`, `OR` and parentheses, and a `-` excludes a word or a group.
+//! Two words next to each other are joined by an implicit `AND`, which binds tighter than `OR`.
+//! The query is never rejected: a missing closing parenthesis spans until the end of the query,
+//! and the stray parentheses and operators are ignored.
+//!
+//! The expression only decides which documents match, the words outside of the `-` are then
+//! searched and ranked as usual.
+use roaring::{MultiOps, RoaringBitmap};
+
+use crate::{QueryPart, Tokenizer};
+
+pub(crate) enum Expr<'a> {
+    /// A word or a phrase
+    Part(QueryPart<'a>),
+    And(Vec<Expr<'a>>),
+    Or(Vec<Expr<'a>>),
+    Not(Box<Expr<'a>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Phrase(&'a str),
+    Text(&'a str),
+}
+
+fn lex(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            // an unclosed quote spans until the end of the query
+            '"' => {
+                let text = &input[start + 1..];
+                let end = text.find('"').unwrap_or(text.len());
+                tokens.push(Token::Phrase(&text[..end]));
+                while chars
+                    .next_if(|(offset, _)| *offset <= start + end + 1)
+                    .is_some()
+                {}
+            }
+            // a lone `-` isn't an operator
+            '-' if chars.peek().is_some_and(|(_, next)| !next.is_whitespace()) => {
+                tokens.push(Token::Not)
+            }
+            _ => {
+                let is_end = |c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"');
+                let end = input[start..]
+                    .find(is_end)
+                    .map_or(input.len(), |end| start + end);
+                tokens.push(match &input[start..end] {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    text => Token::Text(text),
+                });
+                while chars.next_if(|(offset, _)| *offset < end).is_some() {}
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser<'a, 't> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token<'a>>>,
+    tokenizer: &'t dyn Tokenizer,
+}
+
+impl<'a> Parser<'a, '_> {
+    fn or(&mut self) -> Option<Expr<'a>> {
+        let mut operands = Vec::new();
+        operands.extend(self.and());
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            operands.extend(self.and());
+        }
+        Self::group(operands, Expr::Or)
+    }
+
+    fn and(&mut self) -> Option<Expr<'a>> {
+        let mut operands = Vec::new();
+        loop {
+            match self.tokens.peek() {
+                None | Some(Token::Or | Token::Close) => break,
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                Some(_) => operands.extend(self.unary()),
+            }
+        }
+        Self::group(operands, Expr::And)
+    }
+
+    fn unary(&mut self) -> Option<Expr<'a>> {
+        match self.tokens.next()? {
+            Token::Not => Some(Expr::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let expr = self.or();
+                self.tokens.next_if_eq(&Token::Close);
+                expr
+            }
+            Token::Phrase(original) => {
+                let words: Vec<String> = self.words(original).map(|(_, word)| word).collect();
+                (!words.is_empty()).then(|| {
+                    Expr::Part(QueryPart::Phrase {
+                        original: original.trim(),
+                        words,
+                    })
+                })
+            }
+            // a text can contain multiple words for the tokenizer, like "l'arbre"
+            Token::Text(text) => {
+                let words = self
+                    .words(text)
+                    .map(|(original, normalized)| {
+                        Expr::Part(QueryPart::Word {
+                            original,
+                            normalized,
+                        })
+                    })
+                    .collect();
+                Self::group(words, Expr::And)
+            }
+            Token::And | Token::Or | Token::Close => None,
+        }
+    }
+
+    fn words(&self, text: &'a str) -> impl Iterator<Item = (&'a str, String)> + '_ {
+        self.tokenizer
+            .tokenize(text)
+            .into_iter()
+            .map(|word| (word, self.tokenizer.normalize(word)))
+            .filter(|(_, normalized)| !normalized.is_empty())
+    }
+
+    fn group(
+        mut operands: Vec<Expr<'a>>,
+        operator: fn(Vec<Expr<'a>>) -> Expr<'a>,
+    ) -> Option<Expr<'a>> {
+        match operands.len() {
+            0 => None,
+            1 => operands.pop(),
+            _ => Some(operator(operands)),
+        }
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Returns `None` if the query doesn't contain any word
+    pub fn parse(input: &'a str, tokenizer: &dyn Tokenizer) -> Option<Self> {
+        let mut parser = Parser {
+            tokens: lex(input).into_iter().peekable(),
+            tokenizer,
+        };
+        // the stray closing parentheses are skipped
+        let mut operands = Vec::new();
+        while parser.tokens.peek().is_some() {
+            operands.extend(parser.or());
+            parser.tokens.next_if_eq(&Token::Close);
+        }
+        Parser::group(operands, Expr::And)
+    }
+
+    /// The words and phrases that aren't excluded, in the order of the query, they're searched and ranked
+    pub fn parts(&self) -> Vec<QueryPart<'a>> {
+        let mut parts = Vec::new();
+        self.collect_parts(false, &mut parts);
+        parts
+    }
+
+    fn collect_parts(&self, negated: bool, parts: &mut Vec<QueryPart<'a>>) {
+        match self {
+            Expr::Part(part) if !negated => parts.push(part.clone()),
+            Expr::Part(_) => {}
+            Expr::And(operands) | Expr::Or(operands) => {
+                for operand in operands {
+                    operand.collect_parts(negated, parts);
+                }
+            }
+            Expr::Not(operand) => operand.collect_parts(!negated, parts),
+        }
+    }
+
+    /// Returns the documents matching the expression out of `universe`. `documents` returns the
+    /// documents of a word or a phrase, the documents of the excluded words are `negated`.
+    /// The words it returns `None` for, like the stop words, are left out of the expression,
+    /// and an expression made only of them doesn't filter anything thus returns `None` as well.
+    pub fn evaluate(
+        &self,
+        universe: &RoaringBitmap,
+        documents: &impl Fn(&QueryPart, bool) -> Option<RoaringBitmap>,
+    ) -> Option<RoaringBitmap> {
+        self.evaluate_in(universe, false, documents)
+    }
+
+    fn evaluate_in(
+        &self,
+        universe: &RoaringBitmap,
+        negated: bool,
+        documents: &impl Fn(&QueryPart, bool) -> Option<RoaringBitmap>,
+    ) -> Option<RoaringBitmap> {
+        let operands = |operands: &[Expr]| -> Vec<RoaringBitmap> {
+            operands
+                .iter()
+                .filter_map(|operand| operand.evaluate_in(universe, negated, documents))
+                .collect()
+        };
+        match self {
+            Expr::Part(part) => documents(part, negated),
+            Expr::And(expr) => Some(operands(expr))
+                .filter(|o| !o.is_empty())
+                .map(MultiOps::intersection),
+            Expr::Or(expr) => Some(operands(expr))
+                .filter(|o| !o.is_empty())
+                .map(MultiOps::union),
+            Expr::Not(operand) => operand
+                .evaluate_in(universe, !negated, documents)
+                .map(|excluded| universe - excluded),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::WhitespaceTokenizer;
+
+    impl std::fmt::Debug for Expr<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let join = |f: &mut std::fmt::Formatter<'_>, operator: &str, operands: &[Expr]| {
+                write!(f, "(")?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " {operator} ")?;
+                    }
+                    write!(f, "{operand:?}")?;
+                }
+                write!(f, ")")
+            };
+            match self {
+                Expr::Part(QueryPart::Phrase { words, .. }) => write!(f, "\"{}\"", words.join(" ")),
+                Expr::Part(part) => write!(f, "{}", part.original()),
+                Expr::And(operands) => join(f, "AND", operands),
+                Expr::Or(operands) => join(f, "OR", operands),
+                Expr::Not(operand) => write!(f, "-{operand:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_boolean() {
+        let parsed: Vec<String> = [
+            "(kefir OR tamo) AND beau",
+            "kefir tamo OR beau chien",
+            "-(kefir OR \"le chien\") tamo",
+            "kefir AND (tamo OR (beau -chien",
+            ") OR kefir ( ) AND tamo OR",
+            "jean-pierre - chat",
+        ]
+        .into_iter()
+        .map(|input| format!("{:?}", Expr::parse(input, &WhitespaceTokenizer)))
+        .collect();
+        insta::assert_debug_snapshot!(parsed, @r#"
+        [
+            "Some(((kefir OR tamo) AND beau))",
+            "Some(((kefir AND tamo) OR (beau AND chien)))",
+            "Some((-(kefir OR \"le chien\") AND tamo))",
+            "Some((kefir AND (tamo OR (beau AND -chien))))",
+            "Some((kefir AND tamo))",
+            "Some((jean-pierre AND chat))",
+        ]
+        "#);
+    }
+}
@@ -0,0 +1,252 @@
+//! The restricted Damerau-Levenshtein distance, also called the optimal string alignment distance.
+//! It counts the insertions, deletions, substitutions and transpositions of adjacent characters,
+//! but a substring can't be edited more than once.
+//! It's computed for every word matched by the levenshtein automatons, thus it works on borrowed
+//! strings and reuses the same buffers for all the computations of a thread.
+//!
+//! The Jaro-Winkler similarity is the alternative, see [`DistanceMetric`](crate::DistanceMetric).
+
+use std::cell::RefCell;
+
+#[derive(Default)]
+struct Scratch {
+    src: Vec<char>,
+    tar: Vec<char>,
+    // the last three rows of the matrix
+    rows: Vec<usize>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+/// Returns the number of edits needed to go from `src` to `tar`, counted in characters
+pub(crate) fn damerau_levenshtein(src: &str, tar: &str) -> usize {
+    last_row(src, tar, |row| row[row.len() - 1])
+}
+
+/// Returns the number of edits needed to go from `src` to the closest prefix of `tar`,
+/// it's the distance the prefix levenshtein automatons match words with
+pub(crate) fn damerau_levenshtein_prefix(src: &str, tar: &str) -> usize {
+    last_row(src, tar, |row| {
+        row.iter().copied().min().unwrap_or_default()
+    })
+}
+
+/// Computes the matrix of the distances and gives its last row to `f`,
+/// its n-th cell is the distance between `src` and the n first characters of `tar`
+fn last_row<R>(src: &str, tar: &str, f: impl FnOnce(&[usize]) -> R) -> R {
+    SCRATCH.with(|scratch| {
+        let Scratch {
+            src: a,
+            tar: b,
+            rows,
+        } = &mut *scratch.borrow_mut();
+        a.clear();
+        a.extend(src.chars());
+        b.clear();
+        b.extend(tar.chars());
+
+        let width = b.len() + 1;
+        rows.clear();
+        rows.resize(width * 3, 0);
+        // rows[0..width] is the row before the previous one, then the previous one and the current one
+        for (j, cell) in rows[width * 2..].iter_mut().enumerate() {
+            *cell = j;
+        }
+
+        for i in 1..=a.len() {
+            // shift the rows up
+            rows.copy_within(width.., 0);
+            let (before, current) = rows.split_at_mut(width * 2);
+            let (before_previous, previous) = before.split_at(width);
+            current[0] = i;
+            for j in 1..width {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                let mut distance = (previous[j] + 1)
+                    .min(current[j - 1] + 1)
+                    .min(previous[j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    distance = distance.min(before_previous[j - 2] + 1);
+                }
+                current[j] = distance;
+            }
+        }
+
+        f(&rows[width * 2..])
+    })
+}
+
+/// Returns the Jaro-Winkler similarity between `src` and `tar`, from 0 when they have nothing
+/// in common to 1 when they're equal. The characters in common count more when they're at the
+/// start of the words, up to four of them.
+pub(crate) fn jaro_winkler(src: &str, tar: &str) -> f64 {
+    SCRATCH.with(|scratch| {
+        let Scratch {
+            src: a,
+            tar: b,
+            rows: matched,
+        } = &mut *scratch.borrow_mut();
+        a.clear();
+        a.extend(src.chars());
+        b.clear();
+        b.extend(tar.chars());
+        if a.is_empty() && b.is_empty() {
+            return 1.0;
+        }
+
+        // the characters of `b` matched by the ones of `a`, they're only searched around their position
+        let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+        matched.clear();
+        matched.resize(b.len(), usize::MAX);
+        let mut matches = 0;
+        for (i, c) in a.iter().enumerate() {
+            let range = i.saturating_sub(window)..(i + window + 1).min(b.len());
+            if let Some(j) = range
+                .into_iter()
+                .find(|j| matched[*j] == usize::MAX && b[*j] == *c)
+            {
+                matched[j] = i;
+                matches += 1;
+            }
+        }
+        if matches == 0 {
+            return 0.0;
+        }
+
+        // the matched characters that are not in the same order in both words
+        let mut in_a: Vec<usize> = matched
+            .iter()
+            .copied()
+            .filter(|i| *i != usize::MAX)
+            .collect();
+        let in_b = in_a.clone();
+        in_a.sort_unstable();
+        let transpositions = in_a
+            .iter()
+            .zip(&in_b)
+            .filter(|(i, j)| a[**i] != a[**j])
+            .count()
+            / 2;
+
+        let matches = matches as f64;
+        let jaro = (matches / a.len() as f64
+            + matches / b.len() as f64
+            + (matches - transpositions as f64) / matches)
+            / 3.0;
+        let prefix = a
+            .iter()
+            .zip(b.iter())
+            .take(4)
+            .take_while(|(a, b)| a == b)
+            .count();
+        jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_damerau_levenshtein() {
+        let distances: Vec<_> = [
+            ("kefir", "kefir"),
+            ("kefir", "kefri"),
+            ("kefir", "kfeir"),
+            ("kefir", "kef"),
+            ("", "kef"),
+            ("kef", ""),
+            ("tamo", "tomo"),
+            ("ca", "abc"),
+            ("très", "tres"),
+            ("chien", "niche"),
+        ]
+        .into_iter()
+        .map(|(src, tar)| (src, tar, damerau_levenshtein(src, tar)))
+        .collect();
+        insta::assert_debug_snapshot!(distances, @r#"
+        [
+            (
+                "kefir",
+                "kefir",
+                0,
+            ),
+            (
+                "kefir",
+                "kefri",
+                1,
+            ),
+            (
+                "kefir",
+                "kfeir",
+                1,
+            ),
+            (
+                "kefir",
+                "kef",
+                2,
+            ),
+            (
+                "",
+                "kef",
+                3,
+            ),
+            (
+                "kef",
+                "",
+                3,
+            ),
+            (
+                "tamo",
+                "tomo",
+                1,
+            ),
+            (
+                "ca",
+                "abc",
+                3,
+            ),
+            (
+                "très",
+                "tres",
+                1,
+            ),
+            (
+                "chien",
+                "niche",
+                4,
+            ),
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_jaro_winkler() {
+        let similarities: Vec<_> = [
+            ("martha", "marhta"),
+            ("dixon", "dicksonx"),
+            ("kfe", "kef"),
+            ("kfe", "kez"),
+            ("kef", "kef"),
+            ("", ""),
+            ("kef", ""),
+            ("abc", "xyz"),
+        ]
+        .into_iter()
+        .map(|(src, tar)| format!("{src} {tar} {:.3}", jaro_winkler(src, tar)))
+        .collect();
+        insta::assert_debug_snapshot!(similarities, @r#"
+        [
+            "martha marhta 0.961",
+            "dixon dicksonx 0.813",
+            "kfe kef 0.600",
+            "kfe kez 0.600",
+            "kef kef 1.000",
+            "  1.000",
+            "kef  0.000",
+            "abc xyz 0.000",
+        ]
+        "#);
+    }
+}
@@ -0,0 +1,221 @@
+//! The storage of the documents of an [`Index`](crate::Index).
+//! By default every document is its own allocation, borrowed or owned. A `str` index can store
+//! them in an arena instead, all the texts are concatenated in a single buffer and a document is
+//! sliced out of it by its id, see [`Index::new_in_memory_arena`](crate::Index::new_in_memory_arena).
+//! It costs one offset per document instead of a `String` and its allocation.
+//! An index loaded with [`Index::load_mmap_documents`](crate::Index::load_mmap_documents) slices
+//! them out of the mapped file instead, the OS only reads the pages of the documents being used.
+use std::{
+    borrow::{Borrow, Cow},
+    ops::Range,
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+use roaring::RoaringBitmap;
+
+use crate::Id;
+
+pub(crate) enum Documents<'a, T: ?Sized + ToOwned> {
+    Separate(Vec<Cow<'a, T>>),
+    Arena(Arena<T>),
+    Mapped(Mapped<T>),
+}
+
+pub(crate) struct Arena<T: ?Sized> {
+    text: String,
+    // where every document ends in the text, a document starts where the previous one ends
+    ends: Vec<usize>,
+    // the arena only stores strings, it's how they're returned as the documents of the index
+    document: fn(&str) -> &T,
+}
+
+impl Arena<str> {
+    pub fn new<'b>(documents: impl IntoIterator<Item = &'b str>) -> Self {
+        let mut arena = Arena {
+            text: String::new(),
+            ends: Vec::new(),
+            document: |text| text,
+        };
+        for document in documents {
+            arena.push(document);
+        }
+        arena
+    }
+}
+
+impl<T: ?Sized> Arena<T> {
+    fn get(&self, id: usize) -> Option<&T> {
+        let end = *self.ends.get(id)?;
+        let start = id.checked_sub(1).map_or(0, |previous| self.ends[previous]);
+        Some((self.document)(&self.text[start..end]))
+    }
+
+    fn push(&mut self, document: &str) {
+        self.text.push_str(document);
+        self.ends.push(self.text.len());
+    }
+
+    /// Copy the documents whose id is kept in a new arena
+    fn filter(&self, keep: impl Fn(usize) -> bool) -> Self {
+        let mut arena = Arena {
+            text: String::new(),
+            ends: Vec::new(),
+            document: self.document,
+        };
+        for id in (0..self.ends.len()).filter(|id| keep(*id)) {
+            let start = id.checked_sub(1).map_or(0, |previous| self.ends[previous]);
+            arena.push(&self.text[start..self.ends[id]]);
+        }
+        arena
+    }
+}
+
+pub(crate) struct Mapped<T: ?Sized> {
+    mmap: Arc<Mmap>,
+    // where the text of every document lives in the file, it's valid utf-8
+    ranges: Vec<Range<usize>>,
+    // the file is read-only, the documents inserted after loading the index are kept in memory
+    inserted: Arena<T>,
+}
+
+impl Mapped<str> {
+    /// # Safety
+    ///
+    /// Every range must be valid utf-8 in the file, the documents are sliced without checking it again.
+    pub unsafe fn new(mmap: Arc<Mmap>, ranges: Vec<Range<usize>>) -> Self {
+        Mapped {
+            mmap,
+            ranges,
+            inserted: Arena::new([]),
+        }
+    }
+}
+
+impl<T: ?Sized> Mapped<T> {
+    fn len(&self) -> usize {
+        self.ranges.len() + self.inserted.ends.len()
+    }
+
+    fn get(&self, id: usize) -> Option<&T> {
+        match self.ranges.get(id) {
+            Some(range) => {
+                let bytes = self.mmap.get(range.clone())?;
+                // SAFETY: the ranges were checked when the index was loaded, see `Mapped::new`, and the
+                // file can't be modified while it's mapped
+                let text = unsafe { std::str::from_utf8_unchecked(bytes) };
+                Some((self.inserted.document)(text))
+            }
+            None => self.inserted.get(id - self.ranges.len()),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + ToOwned + AsRef<str>> Documents<'a, T> {
+    pub fn len(&self) -> usize {
+        match self {
+            Documents::Separate(documents) => documents.len(),
+            Documents::Arena(arena) => arena.ends.len(),
+            Documents::Mapped(mapped) => mapped.len(),
+        }
+    }
+
+    pub fn get(&self, id: Id) -> Option<&T> {
+        match self {
+            Documents::Separate(documents) => {
+                documents.get(id as usize).map(|document| &**document)
+            }
+            Documents::Arena(arena) => arena.get(id as usize),
+            Documents::Mapped(mapped) => mapped.get(id as usize),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len() as Id).map(|id| &self[id])
+    }
+
+    pub fn push(&mut self, document: T::Owned) {
+        match self {
+            Documents::Separate(documents) => documents.push(Cow::Owned(document)),
+            Documents::Arena(arena) => arena.push(document.borrow().as_ref()),
+            Documents::Mapped(mapped) => mapped.inserted.push(document.borrow().as_ref()),
+        }
+    }
+
+    /// Append the documents of `other`, they're stored like the documents of `self`
+    pub fn extend(&mut self, other: Documents<'a, T>) {
+        match (self, other) {
+            (Documents::Separate(documents), Documents::Separate(other)) => documents.extend(other),
+            (Documents::Arena(arena), other) => {
+                for document in other.iter() {
+                    arena.push(document.as_ref());
+                }
+            }
+            (Documents::Mapped(mapped), other) => {
+                for document in other.iter() {
+                    mapped.inserted.push(document.as_ref());
+                }
+            }
+            (Documents::Separate(documents), other) => {
+                documents.extend(other.iter().map(|document| Cow::Owned(document.to_owned())))
+            }
+        }
+    }
+
+    /// Drop the deleted documents, the other ones keep their order
+    pub fn without(self, deleted: &RoaringBitmap) -> Documents<'a, T> {
+        let kept = |id: usize| !deleted.contains(id as Id);
+        match self {
+            Documents::Separate(documents) => Documents::Separate(
+                documents
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(id, _)| kept(*id))
+                    .map(|(_, document)| document)
+                    .collect(),
+            ),
+            Documents::Arena(arena) => Documents::Arena(arena.filter(kept)),
+            Documents::Mapped(Mapped {
+                mmap,
+                ranges,
+                inserted,
+            }) => {
+                let len = ranges.len();
+                Documents::Mapped(Mapped {
+                    mmap,
+                    inserted: inserted.filter(|id| kept(len + id)),
+                    ranges: ranges
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(id, _)| kept(*id))
+                        .map(|(_, range)| range)
+                        .collect(),
+                })
+            }
+        }
+    }
+
+    pub fn into_owned(self) -> Documents<'static, T>
+    where
+        T: 'static,
+    {
+        match self {
+            Documents::Separate(documents) => Documents::Separate(
+                documents
+                    .into_iter()
+                    .map(|document| Cow::Owned(document.into_owned()))
+                    .collect(),
+            ),
+            Documents::Arena(arena) => Documents::Arena(arena),
+            Documents::Mapped(mapped) => Documents::Mapped(mapped),
+        }
+    }
+}
+
+impl<T: ?Sized + ToOwned + AsRef<str>> std::ops::Index<Id> for Documents<'_, T> {
+    type Output = T;
+
+    fn index(&self, id: Id) -> &T {
+        self.get(id).expect("the document doesn't exist")
+    }
+}
@@ -0,0 +1,220 @@
+//! The wildcards of the query, see [`Search::with_wildcards`](crate::Search::with_wildcards).
+//! A `*` matches any sequence of characters, including an empty one, and a `?` matches
+//! exactly one character. The pattern is ran on the fst as an automaton.
+//! It also runs the infix search, see [`Search::with_infix`](crate::Search::with_infix).
+use fst::Automaton;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Byte(u8),
+    AnyChar,
+    AnyString,
+}
+
+pub(crate) struct Glob {
+    parts: Vec<Part>,
+}
+
+impl Glob {
+    /// Returns true if the word should be searched as a pattern
+    pub fn is_pattern(word: &str) -> bool {
+        word.contains(['*', '?'])
+    }
+
+    /// The text between the wildcards is normalized like the words of the index
+    pub fn new(pattern: &str, normalize: impl Fn(&str) -> String) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        for c in pattern.chars() {
+            let wildcard = match c {
+                '*' => Part::AnyString,
+                '?' => Part::AnyChar,
+                c => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            parts.extend(normalize(&literal).bytes().map(Part::Byte));
+            literal.clear();
+            // two stars in a row are the same as one
+            if !(wildcard == Part::AnyString && parts.last() == Some(&Part::AnyString)) {
+                parts.push(wildcard);
+            }
+        }
+        parts.extend(normalize(&literal).bytes().map(Part::Byte));
+
+        Self { parts }
+    }
+
+    /// Matches the words containing `word`, it's already normalized
+    pub fn infix(word: &str) -> Self {
+        let mut parts = vec![Part::AnyString];
+        parts.extend(word.bytes().map(Part::Byte));
+        parts.push(Part::AnyString);
+
+        Self { parts }
+    }
+
+    /// Add the states reachable without consuming anything, a star can match nothing
+    fn close(&self, mut state: Vec<State>) -> Vec<State> {
+        let mut i = 0;
+        while i < state.len() {
+            if let State { part, remaining: 0 } = state[i] {
+                if self.parts.get(part) == Some(&Part::AnyString) {
+                    state.push(State {
+                        part: part + 1,
+                        remaining: 0,
+                    });
+                }
+            }
+            i += 1;
+        }
+        state.sort_unstable();
+        state.dedup();
+        state
+    }
+}
+
+/// A position in the pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct State {
+    // the next part of the pattern to match
+    part: usize,
+    // the number of bytes left in the character matched by a `?`
+    remaining: u8,
+}
+
+impl Automaton for Glob {
+    type State = Vec<State>;
+
+    fn start(&self) -> Self::State {
+        self.close(vec![State {
+            part: 0,
+            remaining: 0,
+        }])
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state
+            .iter()
+            .any(|state| state.remaining == 0 && state.part == self.parts.len())
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        !state.is_empty()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let mut next = Vec::new();
+        for &State { part, remaining } in state {
+            if remaining > 0 {
+                next.push(State {
+                    part,
+                    remaining: remaining - 1,
+                });
+                continue;
+            }
+            match self.parts.get(part) {
+                Some(Part::Byte(expected)) if *expected == byte => next.push(State {
+                    part: part + 1,
+                    remaining: 0,
+                }),
+                Some(Part::AnyChar) => next.push(State {
+                    part: part + 1,
+                    // the number of continuation bytes is encoded in the first byte of the character
+                    remaining: byte.leading_ones().saturating_sub(1) as u8,
+                }),
+                Some(Part::AnyString) => next.push(State { part, remaining: 0 }),
+                _ => (),
+            }
+        }
+        self.close(next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::normalize;
+
+    fn matches(pattern: &str, word: &str) -> bool {
+        let glob = Glob::new(pattern, normalize);
+        let mut state = glob.start();
+        for byte in word.bytes() {
+            state = glob.accept(&state, byte);
+        }
+        glob.is_match(&state)
+    }
+
+    #[test]
+    fn test_glob() {
+        let words = ["kefirounet", "kefnet", "kef", "tamo", "tamé", "tam"];
+        let patterns = ["kef*net", "kef*", "*o", "Tam?", "t**", "?", "*"];
+        let matched: Vec<(&str, Vec<&str>)> = patterns
+            .iter()
+            .map(|pattern| {
+                let words = words
+                    .iter()
+                    .copied()
+                    .filter(|word| matches(pattern, word))
+                    .collect();
+                (*pattern, words)
+            })
+            .collect();
+        insta::assert_debug_snapshot!(matched, @r#"
+        [
+            (
+                "kef*net",
+                [
+                    "kefirounet",
+                    "kefnet",
+                ],
+            ),
+            (
+                "kef*",
+                [
+                    "kefirounet",
+                    "kefnet",
+                    "kef",
+                ],
+            ),
+            (
+                "*o",
+                [
+                    "tamo",
+                ],
+            ),
+            (
+                "Tam?",
+                [
+                    "tamo",
+                    "tamé",
+                ],
+            ),
+            (
+                "t**",
+                [
+                    "tamo",
+                    "tamé",
+                    "tam",
+                ],
+            ),
+            (
+                "?",
+                [],
+            ),
+            (
+                "*",
+                [
+                    "kefirounet",
+                    "kefnet",
+                    "kef",
+                    "tamo",
+                    "tamé",
+                    "tam",
+                ],
+            ),
+        ]
+        "#);
+    }
+}
@@ -1,46 +1,531 @@
+mod batch;
+mod boolean;
+mod distance;
+mod documents;
+mod glob;
+mod ngram;
 mod ranking_rules;
+mod session;
+mod tokenizer;
 
-use std::{borrow::Cow, ops::ControlFlow, sync::OnceLock};
+use std::{
+    borrow::{Borrow, Cow},
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    fs::File,
+    hash::Hash,
+    io::{BufWriter, Write},
+    ops::{ControlFlow, Range},
+    path::Path,
+    sync::{Arc, OnceLock},
+};
 
-use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+pub use batch::Batch;
+use boolean::Expr;
+use distance::{damerau_levenshtein, damerau_levenshtein_prefix, jaro_winkler};
+use documents::{Arena, Documents, Mapped};
+use fst::{automaton::Str, Automaton, IntoStreamer, Map, MapBuilder, Streamer};
+use glob::Glob;
 use levenshtein_automata::LevenshteinAutomatonBuilder;
-use ranking_rules::{typo::Typo, word::Word, RankingRule, RankingRuleImpl};
-use roaring::RoaringBitmap;
-use text_distance::DamerauLevenshtein;
+use memmap2::Mmap;
+use ngram::NGrams;
+use ranking_rules::{
+    boost::Boost, field::Field, frequency::Frequency, length::Length, position::Position,
+    proximity::Proximity, typo::Typo, verbatim::Verbatim, weighted::Weighted,
+    whole_word::WholeWord, word::Word,
+};
+pub use ranking_rules::{RankingRule, RankingRuleImpl};
+pub use roaring::RoaringBitmap;
+use roaring::{MultiOps, RoaringTreemap};
+pub use session::SearchSession;
+use session::WordCache;
+pub use tokenizer::{
+    KeepPunctuationTokenizer, KeepSymbolsTokenizer, MinLengthTokenizer, NormalizeTokenizer,
+    PreTokenizedTokenizer, SplitDigitsTokenizer, StopWordsTokenizer, Tokenizer,
+    WhitespaceTokenizer,
+};
+#[cfg(feature = "stemming")]
+pub use tokenizer::{Language, StemmingTokenizer};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
 use crate::ranking_rules::exact::Exact;
 
-pub struct Index<'a> {
-    documents: Vec<Cow<'a, str>>,
+/// The documents can be any type exposing their searchable text through [`AsRef<str>`],
+/// the searches then return them as is. By default they're simple strings.
+///
+/// The index is [`Send`] and [`Sync`] as long as its documents are. The searches only borrow
+/// it and keep their working buffers to themselves, thus an index can be shared between threads,
+/// in an [`Arc`] for example, and searched concurrently without any lock.
+pub struct Index<'a, T: ?Sized + ToOwned = str> {
+    documents: Documents<'a, T>,
     // we cannot work on serialized bitmap yet thus we're going to load everything in RAM
     bitmaps: Vec<RoaringBitmap>,
-    fst: Map<Cow<'a, [u8]>>,
+    // for every bitmap, the positions of the word in the documents, see `position`
+    positions: Vec<RoaringTreemap>,
+    fst: Map<FstData<'a>>,
+    // the words inserted since the fst was last built, they're merged into the fst once there is too many of them
+    pending: BTreeMap<String, (RoaringBitmap, RoaringTreemap)>,
+    // the ids of the deleted documents, they've already been removed from the bitmaps
+    deleted: RoaringBitmap,
+    // the number of words of every document, 4 bytes per document used by the length ranking rule
+    lengths: Vec<u32>,
+    // the boost of every document, empty when no document is boosted, see `Index::with_boosts`
+    boosts: Vec<f32>,
+    tokenizer: Arc<dyn Tokenizer>,
+    // the n-grams of the words of the fst, see `Index::with_ngrams`
+    ngrams: Option<NGrams>,
 }
 
-type Id = u32;
+/// The bitmaps, the positions, the fst and the lengths of the documents built all at once
+type IndexParts = (Vec<RoaringBitmap>, Vec<RoaringTreemap>, Vec<u8>, Vec<u32>);
+
+/// Where the bytes of the fst lives.
+enum FstData<'a> {
+    Borrowed(&'a [u8]),
+    Owned(Vec<u8>),
+    /// The whole file is mapped, the fst only lives in the range
+    Mmap(Arc<Mmap>, Range<usize>),
+}
+
+impl AsRef<[u8]> for FstData<'_> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            FstData::Borrowed(data) => data,
+            FstData::Owned(data) => data,
+            FstData::Mmap(mmap, range) => &mmap[range.clone()],
+        }
+    }
+}
+
+/// The id of a document, it's its index in the list of documents the index was built from
+pub type Id = u32;
+
+/// The first bytes of a serialized index, see [`Index::write_into`]
+const MAGIC: &[u8; 6] = b"zearch";
+/// The version of the format of the serialized indexes, written right after [`MAGIC`].
+/// It's bumped every time the layout changes, the indexes of another version can't be loaded.
+const FORMAT_VERSION: u32 = 1;
+
+/// Why an index couldn't be built, see [`Index::construct`]
+#[derive(Debug, thiserror::Error)]
+pub enum IndexError {
+    /// The index couldn't be written
+    #[error("can't write the index: {0}")]
+    Io(#[from] std::io::Error),
+    /// The fst couldn't be built
+    #[error("can't build the fst: {0}")]
+    Fst(#[source] fst::Error),
+    /// There are more documents than [`Id`] can number
+    #[error("an index can't contain more than {} documents", Id::MAX)]
+    TooManyDocuments,
+}
+
+// the io errors of the fst are the errors of the writer
+impl From<fst::Error> for IndexError {
+    fn from(err: fst::Error) -> Self {
+        match err {
+            fst::Error::Io(err) => IndexError::Io(err),
+            err => IndexError::Fst(err),
+        }
+    }
+}
+
+/// What the ranking rules can read from an [`Index`], whatever the type of its documents.
+pub trait IndexView {
+    /// The searchable text of a document
+    fn text(&self, id: Id) -> &str;
+    fn tokenizer(&self) -> &dyn Tokenizer;
+    /// The positions of a normalized word in all the documents, see [`Index::new_in_memory`]
+    fn word_positions(&self, word: &str) -> Option<&RoaringTreemap>;
+    /// The number of words of the document
+    fn document_length(&self, id: Id) -> u32;
+    /// The documents containing exactly a normalized word
+    fn word_documents(&self, word: &str) -> Option<&RoaringBitmap>;
+    /// The boost of the document, 1 unless it was set with [`Index::with_boosts`]
+    fn document_boost(&self, id: Id) -> f32;
+}
+
+/// Encode the position of a word in a document for the positions treemaps.
+/// The document id lives in the high bits thus the positions of a document are contiguous.
+fn position(id: Id, position: u32) -> u64 {
+    ((id as u64) << 32) | position as u64
+}
+
+/// Separates the fields of a document built with [`Index::new_in_memory_fields`]
+pub const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// Separates the words of a document or a query that was already tokenized, see [`join_tokens`]
+pub const TOKEN_SEPARATOR: char = '\u{1e}';
+
+/// Join words that were already tokenized and normalized, to build an index with
+/// [`Index::new_in_memory_tokens`] or to search it. The words must not contain [`TOKEN_SEPARATOR`].
+///
+/// ```
+/// use zearch::{join_tokens, Index, Search};
+///
+/// let index = Index::new_in_memory_tokens(&[["kefir", "chien"], ["tamo", "chat"]]);
+/// let query = join_tokens(&["chien"]);
+/// assert_eq!(index.search(&Search::new(&query)), [join_tokens(&["kefir", "chien"])]);
+/// ```
+pub fn join_tokens(tokens: &[impl AsRef<str>]) -> String {
+    let mut joined = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i != 0 {
+            joined.push(TOKEN_SEPARATOR);
+        }
+        joined.push_str(token.as_ref());
+    }
+    joined
+}
+
+/// The position of a word in a document is made of its field in the high bits
+/// and its position in the field in the low bits.
+/// A document thus has at most 256 fields of about 16M words: the next fields are merged in
+/// the last one, and the next words of a field all share its last position. A phrase can then
+/// match across the merged fields, but never across two other fields or two documents.
+const FIELD_SHIFT: u32 = 24;
+/// The last field of a document, see [`FIELD_SHIFT`]
+const MAX_FIELD: u32 = u32::MAX >> FIELD_SHIFT;
+/// The last position in a field, the one after it is never used thus the next word of a
+/// phrase can't be looked up in the next field
+const MAX_FIELD_POSITION: u32 = (1 << FIELD_SHIFT) - 2;
+
+/// The position of a word in its field, in the document, see [`FIELD_SHIFT`]
+fn field_position(field: usize, position: usize) -> u32 {
+    let field = field.min(MAX_FIELD as usize) as u32;
+    let position = position.min(MAX_FIELD_POSITION as usize) as u32;
+    (field << FIELD_SHIFT) | position
+}
+
+/// The positions of a field of the document, the fields past the last one are merged in it
+fn field_positions(id: Id, field: usize) -> Range<u64> {
+    let start = position(id, field_position(field, 0));
+    match field >= MAX_FIELD as usize {
+        true => start..position(id + 1, 0),
+        false => start..position(id, field_position(field + 1, 0)),
+    }
+}
+
+/// Split a document in normalized words along with their position in the document
+fn tokenize<'t>(
+    tokenizer: &'t dyn Tokenizer,
+    document: &'t str,
+) -> impl Iterator<Item = (u32, String)> + 't {
+    tokens(tokenizer, document).map(|(position, _span, word)| (position, tokenizer.normalize(word)))
+}
+
+/// Split a document in words along with their position and byte range in the document
+fn tokens<'t>(
+    tokenizer: &'t dyn Tokenizer,
+    document: &'t str,
+) -> impl Iterator<Item = (u32, Range<usize>, &'t str)> + 't {
+    // all the words are sub-slices of the document, we can get their offset back
+    let offset = move |word: &str| word.as_ptr() as usize - document.as_ptr() as usize;
+    document
+        .split(FIELD_SEPARATOR)
+        .enumerate()
+        .flat_map(move |(field, text)| {
+            tokenizer
+                .tokenize(text)
+                .into_iter()
+                .enumerate()
+                .map(move |(position, word)| {
+                    let start = offset(word);
+                    (
+                        field_position(field, position),
+                        start..start + word.len(),
+                        word,
+                    )
+                })
+        })
+}
 
 impl<'a> Index<'a> {
+    /// Build the index in memory then write it in the format expected by [`Index::from_bytes`].
     pub fn construct(
         documents: &[impl AsRef<str>],
         writer: &mut impl std::io::Write,
-    ) -> std::io::Result<()> {
-        let (documents, bitmaps, fst) = Self::new_parts_in_memory(documents);
+    ) -> Result<(), IndexError> {
+        Index::construct_with_tokenizer(documents, WhitespaceTokenizer, writer)
+    }
+
+    /// Same as [`Index::construct`] but split the documents with a custom tokenizer. The tokenizer
+    /// isn't serialized, it must be set again with [`Index::with_tokenizer`] after loading the index.
+    pub fn construct_with_tokenizer(
+        documents: &[impl AsRef<str>],
+        tokenizer: impl Tokenizer + 'static,
+        writer: &mut impl std::io::Write,
+    ) -> Result<(), IndexError> {
+        let documents = documents
+            .iter()
+            .map(|document| Cow::Borrowed(document.as_ref()))
+            .collect();
+        Index::try_build(Documents::Separate(documents), tokenizer)?.write_into(writer)?;
+        Ok(())
+    }
+
+    /// Same as [`Index::construct`] but the documents are consumed and written one by one, only
+    /// their words are kept in memory to build the index once they've all been read.
+    /// The fst is written while it's built instead of being built in memory first.
+    /// The number of documents and the size of the fst are written at the very end,
+    /// that's why the writer must be seekable.
+    pub fn construct_from_iter(
+        documents: impl IntoIterator<Item = impl AsRef<str>>,
+        writer: &mut (impl std::io::Write + std::io::Seek),
+    ) -> Result<(), IndexError> {
+        Self::write_header(writer)?;
+        let start = writer.stream_position()?;
+        // the number of documents isn't known yet
+        writer.write_all(0u32.to_be_bytes().as_slice())?;
 
-        writer.write_all((documents.len() as u32).to_be_bytes().as_slice())?;
+        let mut nb_documents: u32 = 0;
+        let mut words = Vec::new();
         for document in documents {
-            Self::write_slice(writer, document.as_ref().as_bytes())?;
+            let document = document.as_ref();
+            words.extend(
+                tokenize(&WhitespaceTokenizer, document)
+                    .map(|(pos, word)| (nb_documents as Id, pos, word)),
+            );
+            Self::write_slice(writer, document.as_bytes())?;
+            nb_documents = nb_documents
+                .checked_add(1)
+                .ok_or(IndexError::TooManyDocuments)?;
+        }
+
+        let (bitmaps, positions, lengths) =
+            Self::bitmaps_from_words(&mut words, nb_documents as usize);
+        Self::write_bitmaps(writer, &bitmaps, &positions)?;
+        drop((bitmaps, positions));
+
+        // the fst is written as it's built, its size is only known at the end as well
+        let fst_start = writer.stream_position()?;
+        writer.write_all(0u32.to_be_bytes().as_slice())?;
+        Self::build_fst(&words, &mut *writer)?;
+        let fst_end = writer.stream_position()?;
+        writer.seek(std::io::SeekFrom::Start(fst_start))?;
+        writer.write_all(((fst_end - fst_start - 4) as u32).to_be_bytes().as_slice())?;
+        writer.seek(std::io::SeekFrom::Start(fst_end))?;
+        drop(words);
+
+        Self::write_tail(
+            writer,
+            &BTreeMap::new(),
+            &RoaringBitmap::new(),
+            &lengths,
+            &[],
+        )?;
+
+        let end = writer.stream_position()?;
+        writer.seek(std::io::SeekFrom::Start(start))?;
+        writer.write_all(nb_documents.to_be_bytes().as_slice())?;
+        writer.seek(std::io::SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    /// Serialize the index in the format expected by [`Index::from_bytes`].
+    pub fn write_into(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        Self::write_header(writer)?;
+        writer.write_all((self.documents.len() as u32).to_be_bytes().as_slice())?;
+        for (id, document) in self.documents.iter().enumerate() {
+            // the deleted documents are only kept in memory
+            let document = if self.deleted.contains(id as Id) {
+                ""
+            } else {
+                document
+            };
+            Self::write_slice(writer, document.as_bytes())?;
+        }
+
+        Self::write_bitmaps(writer, &self.bitmaps, &self.positions)?;
+        Self::write_slice(writer, self.fst.as_fst().as_bytes())?;
+        Self::write_tail(
+            writer,
+            &self.pending,
+            &self.deleted,
+            &self.lengths,
+            &self.boosts,
+        )
+    }
+
+    /// Every serialized index starts with the magic bytes and the version of its format
+    fn write_header(writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(FORMAT_VERSION.to_be_bytes().as_slice())
+    }
+
+    /// Check the header written by [`Index::write_header`], the indexes of another version
+    /// of the format are rejected instead of being read as garbage
+    fn read_header(bytes: &mut &[u8]) -> std::io::Result<()> {
+        let invalid =
+            |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        *bytes = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| invalid("not a zearch index".to_string()))?;
+        let version = Self::read_size_from_bytes(bytes)
+            .ok_or_else(|| invalid("not a zearch index".to_string()))?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "unsupported zearch index version {version}, expected version {FORMAT_VERSION}"
+            )));
         }
+        Ok(())
+    }
 
+    /// Serialize the bitmaps and the positions, they come right before the fst
+    fn write_bitmaps(
+        writer: &mut impl std::io::Write,
+        bitmaps: &[RoaringBitmap],
+        positions: &[RoaringTreemap],
+    ) -> std::io::Result<()> {
         writer.write_all((bitmaps.len() as u32).to_be_bytes().as_slice())?;
-        for bitmap in bitmaps {
+        for bitmap in bitmaps.iter() {
+            bitmap.serialize_into(&mut *writer)?;
+        }
+        for positions in positions.iter() {
+            positions.serialize_into(&mut *writer)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize everything that comes after the fst
+    fn write_tail(
+        writer: &mut impl std::io::Write,
+        pending: &BTreeMap<String, (RoaringBitmap, RoaringTreemap)>,
+        deleted: &RoaringBitmap,
+        lengths: &[u32],
+        boosts: &[f32],
+    ) -> std::io::Result<()> {
+        writer.write_all((pending.len() as u32).to_be_bytes().as_slice())?;
+        for (word, (bitmap, positions)) in pending.iter() {
+            Self::write_slice(writer, word.as_bytes())?;
             bitmap.serialize_into(&mut *writer)?;
+            positions.serialize_into(&mut *writer)?;
+        }
+
+        deleted.serialize_into(&mut *writer)?;
+
+        for length in lengths {
+            writer.write_all(length.to_be_bytes().as_slice())?;
         }
 
-        Self::write_slice(writer, &fst)?;
+        // nothing is written when no document is boosted
+        for boost in boosts {
+            writer.write_all(boost.to_be_bytes().as_slice())?;
+        }
 
         Ok(())
     }
 
+    /// Save the index in a file that can be loaded back with [`Index::load`].
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_into(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Load an index previously saved with [`Index::save`] in memory.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Index<'static>> {
+        let bytes = std::fs::read(path)?;
+        Index::read_header(&mut bytes.as_slice())?;
+        let index = Index::from_bytes(&bytes).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid zearch index")
+        })?;
+        Ok(index.move_in_memory())
+    }
+
+    /// Load an index previously saved with [`Index::save`] but keep the fst on disk.
+    /// The file is memory-mapped and only the pages of the fst that are used by the
+    /// searches are loaded by the OS. The documents and bitmaps are still loaded in memory,
+    /// [`Index::load_mmap_documents`] keeps the documents on disk as well.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the index is alive, see [`Mmap::map`].
+    pub unsafe fn load_mmap(path: impl AsRef<Path>) -> std::io::Result<Index<'static>> {
+        Index::map(path, false)
+    }
+
+    /// Load an index previously saved with [`Index::save`] but keep the fst and the documents on disk.
+    /// Like [`Index::load_mmap`], except the documents are read from the file when they're returned
+    /// or ranked, only their offsets are loaded in memory. The documents inserted once the index
+    /// is loaded are kept in memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the index is alive, see [`Mmap::map`].
+    pub unsafe fn load_mmap_documents(path: impl AsRef<Path>) -> std::io::Result<Index<'static>> {
+        Index::map(path, true)
+    }
+
+    unsafe fn map(path: impl AsRef<Path>, map_documents: bool) -> std::io::Result<Index<'static>> {
+        let file = File::open(path)?;
+        let mmap = Arc::new(Mmap::map(&file)?);
+
+        Index::read_header(&mut &mmap[..])?;
+        let index = Index::from_bytes(&mmap).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid zearch index")
+        })?;
+        // everything read from the file borrows the mapped bytes
+        let range = |bytes: &[u8]| {
+            let start = bytes.as_ptr() as usize - mmap.as_ptr() as usize;
+            start..start + bytes.len()
+        };
+        let fst_range = range(index.fst.as_fst().as_bytes());
+
+        let documents = if map_documents {
+            let ranges = index
+                .documents
+                .iter()
+                .map(|document| range(document.as_bytes()))
+                .collect();
+            // SAFETY: the ranges are the documents `from_bytes` read as `str`, thus checked as utf-8
+            Documents::Mapped(unsafe { Mapped::new(mmap.clone(), ranges) })
+        } else {
+            index.documents.into_owned()
+        };
+        let (bitmaps, positions, pending, deleted, lengths, boosts, tokenizer) = (
+            index.bitmaps,
+            index.positions,
+            index.pending,
+            index.deleted,
+            index.lengths,
+            index.boosts,
+            index.tokenizer,
+        );
+
+        Ok(Index {
+            documents,
+            bitmaps,
+            positions,
+            fst: Map::new(FstData::Mmap(mmap, fst_range))
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?,
+            pending,
+            deleted,
+            lengths,
+            boosts,
+            tokenizer,
+            ngrams: None,
+        })
+    }
+
+    /// Build the index straight into a file with [`Index::construct_from_iter`] then load it with
+    /// [`Index::load_mmap`]. The fst is never entirely in memory, neither while it's built nor once
+    /// it's loaded, the peak memory is mostly the sorted words of the documents.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the index is alive, see [`Mmap::map`].
+    pub unsafe fn construct_to_path(
+        documents: impl IntoIterator<Item = impl AsRef<str>>,
+        path: impl AsRef<Path>,
+    ) -> Result<Index<'static>, IndexError> {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        Index::construct_from_iter(documents, &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        Ok(Index::load_mmap(path)?)
+    }
+
     fn write_slice(writer: &mut impl std::io::Write, slice: &[u8]) -> std::io::Result<()> {
         writer.write_all((slice.len() as u32).to_be_bytes().as_slice())?;
         writer.write_all(slice)?;
@@ -65,7 +550,12 @@ impl<'a> Index<'a> {
         Some(ret)
     }
 
+    /// Read an index serialized by [`Index::write_into`], the documents and the fst borrow the bytes.
+    /// Returns `None` if the bytes aren't an index of the current version of the format, or if its
+    /// words point to bitmaps or documents it doesn't contain.
     pub fn from_bytes(mut bytes: &'a [u8]) -> Option<Self> {
+        Self::read_header(&mut bytes).ok()?;
+
         // 1. Read the documents
         let mut documents = Vec::new();
         let nb_documents = Self::read_size_from_bytes(&mut bytes)?;
@@ -78,368 +568,5939 @@ impl<'a> Index<'a> {
         let nb_bitmaps = Self::read_size_from_bytes(&mut bytes)?;
         let mut bitmaps = Vec::new();
         for _ in 0..nb_bitmaps {
-            let bitmap = RoaringBitmap::deserialize_from(&mut bytes).unwrap();
+            let bitmap = RoaringBitmap::deserialize_from(&mut bytes).ok()?;
             bitmaps.push(bitmap);
         }
+        let mut positions = Vec::new();
+        for _ in 0..nb_bitmaps {
+            positions.push(RoaringTreemap::deserialize_from(&mut bytes).ok()?);
+        }
 
         // 3. Read the fst
         let fst = Self::read_slice_from_bytes(&mut bytes)?;
-        let fst = Map::new(Cow::Borrowed(fst)).ok()?;
+        let fst = Map::new(FstData::Borrowed(fst)).ok()?;
+        // the fst only checks its header, a corrupted node would make it panic once it's read
+        fst.as_fst().verify().ok()?;
+
+        // 4. Read the words inserted since the fst was built
+        let nb_pending = Self::read_size_from_bytes(&mut bytes)?;
+        let mut pending = BTreeMap::new();
+        for _ in 0..nb_pending {
+            let word = Self::read_slice_from_bytes(&mut bytes)?;
+            let word = std::str::from_utf8(word).ok()?.to_string();
+            let bitmap = RoaringBitmap::deserialize_from(&mut bytes).ok()?;
+            let positions = RoaringTreemap::deserialize_from(&mut bytes).ok()?;
+            pending.insert(word, (bitmap, positions));
+        }
+
+        // 5. Read the deleted documents
+        let deleted = RoaringBitmap::deserialize_from(&mut bytes).ok()?;
+
+        // 6. Read the length of the documents
+        let lengths = (0..nb_documents)
+            .map(|_| Self::read_size_from_bytes(&mut bytes))
+            .collect::<Option<_>>()?;
+
+        // 7. Read the boosts of the documents, nothing is written when no document is boosted
+        let boosts = if bytes.is_empty() {
+            Vec::new()
+        } else {
+            (0..nb_documents)
+                .map(|_| Self::read_size_from_bytes(&mut bytes).map(f32::from_bits))
+                .collect::<Option<_>>()?
+        };
+
+        // 8. Check that the words and the documents refer to each other, the searches index
+        // the bitmaps and the documents without checking them again
+        let mut values = fst.stream();
+        while let Some((_, bitmap)) = values.next() {
+            if bitmap >= nb_bitmaps as u64 {
+                return None;
+            }
+        }
+        let in_bounds = |bitmap: &RoaringBitmap| bitmap.max().is_none_or(|id| id < nb_documents);
+        let positions_in_bounds = |positions: &RoaringTreemap| {
+            positions
+                .max()
+                .is_none_or(|max| max < position(nb_documents, 0))
+        };
+        if !bitmaps.iter().all(in_bounds)
+            || !positions.iter().all(positions_in_bounds)
+            || !pending
+                .values()
+                .all(|(bitmap, positions)| in_bounds(bitmap) && positions_in_bounds(positions))
+            || !in_bounds(&deleted)
+        {
+            return None;
+        }
 
         Some(Self {
-            documents,
+            documents: Documents::Separate(documents),
             bitmaps,
+            positions,
             fst,
+            pending,
+            deleted,
+            lengths,
+            boosts,
+            tokenizer: Arc::new(WhitespaceTokenizer),
+            ngrams: None,
         })
     }
 
-    pub fn move_in_memory(self) -> Index<'static> {
-        Index {
-            documents: self
-                .documents
-                .into_iter()
-                .map(|document| Cow::Owned(document.into_owned()))
-                .collect(),
-            bitmaps: self.bitmaps,
-            fst: self
-                .fst
-                .map_data(|data| Cow::Owned(data.into_owned()))
-                .unwrap(),
+    fn cleanup(used: &RoaringBitmap, candidates: &mut [WordCandidate]) {
+        for candidate in candidates.iter_mut() {
+            for typo in candidate.typos.iter_mut() {
+                *typo -= used;
+            }
         }
     }
 
-    fn new_parts_in_memory<'b>(documents: &'b [impl AsRef<str>]) -> (&'b [impl AsRef<str>],
-                                                                     Vec<RoaringBitmap>,
-                                                                     Vec<u8>) {
-        let mut words = documents
+    /// Build the index in memory.
+    ///
+    /// On top of the documents containing every word, the index stores the position
+    /// of every word in every document to support the phrase search. It costs roughly
+    /// 8 bytes per word of the corpus before compression by the roaring treemaps.
+    pub fn new_in_memory<'b>(documents: &'b [impl AsRef<str>]) -> Index<'b> {
+        Index::new_in_memory_with_tokenizer(documents, WhitespaceTokenizer)
+    }
+
+    /// Same as [`Index::new_in_memory`] but split the documents and the queries with a custom tokenizer.
+    pub fn new_in_memory_with_tokenizer<'b>(
+        documents: &'b [impl AsRef<str>],
+        tokenizer: impl Tokenizer + 'static,
+    ) -> Index<'b> {
+        let documents = documents
+            .iter()
+            .map(|document| Cow::Borrowed(document.as_ref()))
+            .collect();
+        Index::build(Documents::Separate(documents), tokenizer)
+    }
+
+    /// Same as [`Index::new_in_memory`] but the documents are copied in a single buffer, their
+    /// text is sliced out of it when they're returned by the searches. It saves an allocation
+    /// and the size of a [`String`] per document, which matters for millions of short documents.
+    /// The inserted documents are appended to the buffer as well.
+    pub fn new_in_memory_arena(documents: &[impl AsRef<str>]) -> Index<'static> {
+        let documents = Arena::new(documents.iter().map(AsRef::as_ref));
+        Index::build(Documents::Arena(documents), WhitespaceTokenizer)
+    }
+
+    /// Build the index in memory from documents made of multiple fields, like a title and a body.
+    /// The fields are stored joined by [`FIELD_SEPARATOR`] and can be retrieved with [`Index::get_fields`].
+    /// Use the [`RankingRule::Field`] ranking rule and [`Search::with_field_weights`] to boost the
+    /// documents matching in their most important fields.
+    pub fn new_in_memory_fields<F: AsRef<str>>(documents: &[impl AsRef<[F]>]) -> Index<'static> {
+        let documents: Vec<String> = documents
+            .iter()
+            .map(|fields| join_fields(fields.as_ref()))
+            .collect();
+        Index::new_in_memory(&documents).move_in_memory()
+    }
+
+    /// Build the index in memory from documents along with their boost, see [`Index::with_boosts`].
+    pub fn new_in_memory_boosted<'b>(documents: &'b [(impl AsRef<str>, f32)]) -> Index<'b> {
+        let boosts = documents.iter().map(|(_, boost)| *boost).collect();
+        let documents = documents
+            .iter()
+            .map(|(document, _)| Cow::Borrowed(document.as_ref()))
+            .collect();
+        Index::build(Documents::Separate(documents), WhitespaceTokenizer).with_boosts(boosts)
+    }
+
+    /// Same as [`Index::construct`] for documents along with their boost, see [`Index::with_boosts`].
+    pub fn construct_boosted(
+        documents: &[(impl AsRef<str>, f32)],
+        writer: &mut impl std::io::Write,
+    ) -> Result<(), IndexError> {
+        let boosts = documents.iter().map(|(_, boost)| *boost).collect();
+        let documents = documents
+            .iter()
+            .map(|(document, _)| Cow::Borrowed(document.as_ref()))
+            .collect();
+        Index::try_build(Documents::Separate(documents), WhitespaceTokenizer)?
+            .with_boosts(boosts)
+            .write_into(writer)?;
+        Ok(())
+    }
+
+    /// Build the index in memory from documents that were already tokenized and normalized,
+    /// every document is the list of its words. The words are indexed as is and the documents are
+    /// stored and returned as their words joined by [`TOKEN_SEPARATOR`], see [`join_tokens`].
+    /// The index uses the [`PreTokenizedTokenizer`], thus the queries must be joined as well
+    /// and their words are searched without normalization nor query syntax.
+    pub fn new_in_memory_tokens<W: AsRef<str>>(documents: &[impl AsRef<[W]>]) -> Index<'static> {
+        let documents: Vec<String> = documents
+            .iter()
+            .map(|tokens| join_tokens(tokens.as_ref()))
+            .collect();
+        Index::new_in_memory_with_tokenizer(&documents, PreTokenizedTokenizer).move_in_memory()
+    }
+
+    /// Same as [`Index::construct`] for documents that were already tokenized, see
+    /// [`Index::new_in_memory_tokens`]. The tokenizer isn't serialized, the index must be
+    /// loaded with [`Index::with_tokenizer`] and the [`PreTokenizedTokenizer`].
+    pub fn construct_tokens<W: AsRef<str>>(
+        documents: &[impl AsRef<[W]>],
+        writer: &mut impl std::io::Write,
+    ) -> Result<(), IndexError> {
+        let documents = documents
+            .iter()
+            .map(|tokens| Cow::Owned(join_tokens(tokens.as_ref())))
+            .collect();
+        Index::try_build(Documents::Separate(documents), PreTokenizedTokenizer)?
+            .write_into(writer)?;
+        Ok(())
+    }
+
+    /// Same as [`Index::construct`] for documents made of multiple fields, see [`Index::new_in_memory_fields`].
+    pub fn construct_fields<F: AsRef<str>>(
+        documents: &[impl AsRef<[F]>],
+        writer: &mut impl std::io::Write,
+    ) -> Result<(), IndexError> {
+        let documents: Vec<String> = documents
+            .iter()
+            .map(|fields| join_fields(fields.as_ref()))
+            .collect();
+        Index::construct(&documents, writer)
+    }
+}
+
+impl<'a, T: ?Sized + ToOwned + AsRef<str>> Index<'a, T> {
+    /// Build the index in memory from any type of documents, they're returned as is by the searches.
+    /// See [`Index::new_in_memory`] for plain strings.
+    pub fn from_documents(documents: &'a [T]) -> Self
+    where
+        T: Sized,
+    {
+        Index::from_documents_with_tokenizer(documents, WhitespaceTokenizer)
+    }
+
+    /// Same as [`Index::from_documents`] but split the documents and the queries with a custom tokenizer.
+    pub fn from_documents_with_tokenizer(
+        documents: &'a [T],
+        tokenizer: impl Tokenizer + 'static,
+    ) -> Self
+    where
+        T: Sized,
+    {
+        Index::build(
+            Documents::Separate(documents.iter().map(Cow::Borrowed).collect()),
+            tokenizer,
+        )
+    }
+
+    fn new_parts_in_memory(
+        documents: &[&str],
+        tokenizer: &dyn Tokenizer,
+    ) -> fst::Result<IndexParts> {
+        // only the fst must be built in order, the tokenization and the sort can be done on all the cores
+        #[cfg(feature = "rayon")]
+        let words = {
+            use rayon::prelude::*;
+
+            documents
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(|(id, document)| {
+                    tokenize(tokenizer, document).map(move |(pos, word)| (id as Id, pos, word))
+                })
+                .collect::<Vec<(Id, u32, String)>>()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let words = documents
             .iter()
             .enumerate()
             .flat_map(|(id, document)| {
-                document
-                    .as_ref()
-                    .split_whitespace()
-                    .map(move |word| (id as Id, normalize(word)))
+                tokenize(tokenizer, document).map(move |(pos, word)| (id as Id, pos, word))
             })
-            .collect::<Vec<(Id, String)>>();
-        words.sort_unstable_by(|(_, left), (_, right)| left.cmp(right));
+            .collect::<Vec<(Id, u32, String)>>();
 
-        let mut build = MapBuilder::memory();
+        Self::parts_from_words(words, documents.len())
+    }
+
+    /// Build the bitmaps, the positions, the fst and the lengths from all the words of the documents
+    fn parts_from_words(
+        mut words: Vec<(Id, u32, String)>,
+        nb_documents: usize,
+    ) -> fst::Result<IndexParts> {
+        let (bitmaps, positions, lengths) = Self::bitmaps_from_words(&mut words, nb_documents);
+        let fst = Self::build_fst(&words, Vec::new())?;
+
+        Ok((bitmaps, positions, fst, lengths))
+    }
+
+    /// Sort the words then build the bitmaps, the positions and the lengths,
+    /// the n-th bitmap belongs to the n-th distinct word.
+    /// The words are sorted along with their ids and positions, thus both can be appended to
+    /// the bitmaps of their word, whatever the order the documents were tokenized in.
+    fn bitmaps_from_words(
+        words: &mut [(Id, u32, String)],
+        nb_documents: usize,
+    ) -> (Vec<RoaringBitmap>, Vec<RoaringTreemap>, Vec<u32>) {
+        let mut lengths = vec![0; nb_documents];
+        for (id, _, _) in words.iter() {
+            lengths[*id as usize] += 1;
+        }
+
+        let order = |(left_id, left_pos, left): &(Id, u32, String),
+                     (right_id, right_pos, right): &(Id, u32, String)| {
+            (left, left_id, left_pos).cmp(&(right, right_id, right_pos))
+        };
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            words.par_sort_unstable_by(order);
+        }
+        #[cfg(not(feature = "rayon"))]
+        words.sort_unstable_by(order);
 
         let mut last_word = None;
         let mut bitmaps = Vec::new();
+        let mut positions = Vec::new();
 
-        for (id, word) in words.iter() {
+        for (id, pos, word) in words.iter() {
             if Some(word) != last_word {
                 bitmaps.push(RoaringBitmap::from_sorted_iter(Some(*id)).unwrap());
-                build.insert(word, (bitmaps.len() - 1) as u64).unwrap();
+                positions.push(RoaringTreemap::from_iter([position(*id, *pos)]));
             } else {
-                bitmaps.last_mut().unwrap().insert(*id);
+                // a word present multiple times in a document pushes its id again, it's ignored
+                bitmaps.last_mut().unwrap().push(*id);
+                positions.last_mut().unwrap().push(position(*id, *pos));
             }
 
             last_word = Some(word);
         }
 
-        let fst = build.into_inner().unwrap();
+        (bitmaps, positions, lengths)
+    }
 
-        (
+    /// Write the fst of the sorted words in `writer`, every word points to its bitmap.
+    /// The fst only accepts strictly increasing keys, the words are sorted by their bytes
+    /// like the fst compares them and the duplicates are skipped.
+    fn build_fst<W: std::io::Write>(words: &[(Id, u32, String)], writer: W) -> fst::Result<W> {
+        let mut build = MapBuilder::new(writer)?;
+        let mut last_word = None;
+        let mut bitmap = 0;
+        for (_, _, word) in words.iter() {
+            if Some(word) != last_word {
+                build.insert(word, bitmap)?;
+                bitmap += 1;
+            }
+            last_word = Some(word);
+        }
+        build.into_inner()
+    }
+
+    fn build(documents: Documents<'a, T>, tokenizer: impl Tokenizer + 'static) -> Self {
+        Self::try_build(documents, tokenizer).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn try_build(
+        documents: Documents<'a, T>,
+        tokenizer: impl Tokenizer + 'static,
+    ) -> Result<Self, IndexError> {
+        // the ids and the number of documents are stored on 32 bits
+        if documents.len() > Id::MAX as usize {
+            return Err(IndexError::TooManyDocuments);
+        }
+        let texts: Vec<&str> = documents.iter().map(AsRef::as_ref).collect();
+        let (bitmaps, positions, fst, lengths) = Self::new_parts_in_memory(&texts, &tokenizer)?;
+
+        Ok(Index {
             documents,
             bitmaps,
-            fst
-       )
+            positions,
+            fst: Map::new(FstData::Owned(fst))?,
+            pending: BTreeMap::new(),
+            deleted: RoaringBitmap::new(),
+            lengths,
+            boosts: Vec::new(),
+            tokenizer: Arc::new(tokenizer),
+            ngrams: None,
+        })
     }
 
-    pub fn new_in_memory<'b>(documents: &'b [impl AsRef<str>]) -> Index<'b> {
-        let (documents, bitmaps, fst) = Self::new_parts_in_memory(documents);
-
+    pub fn move_in_memory(self) -> Index<'static, T>
+    where
+        T: 'static,
+    {
         Index {
-            documents: documents
-                .into_iter()
-                .map(|s| Cow::Borrowed(s.as_ref()))
-                .collect(),
-            bitmaps,
-            fst: Map::new(Cow::Owned(fst)).unwrap(),
+            documents: self.documents.into_owned(),
+            bitmaps: self.bitmaps,
+            positions: self.positions,
+            fst: self
+                .fst
+                .map_data(|data| match data {
+                    FstData::Borrowed(data) => FstData::Owned(data.to_vec()),
+                    FstData::Owned(data) => FstData::Owned(data),
+                    FstData::Mmap(mmap, range) => FstData::Mmap(mmap, range),
+                })
+                .unwrap(),
+            pending: self.pending,
+            deleted: self.deleted,
+            lengths: self.lengths,
+            boosts: self.boosts,
+            tokenizer: self.tokenizer,
+            ngrams: self.ngrams,
         }
     }
 
-    pub fn get_document(&self, id: u32) -> Option<&str> {
-        self.documents.get(id as usize).map(|s| s.as_ref())
+    /// Load the fst of an index loaded with [`Index::load_mmap`] in memory, so the first searches
+    /// don't wait for its pages to be read from the disk. The bitmaps are never mapped, thus it's
+    /// a no-op for the other indexes. The OS can still evict the pages under memory pressure.
+    pub fn preload(&self) {
+        if let FstData::Mmap(mmap, range) = self.fst.as_fst().as_inner() {
+            // let the OS read everything at once instead of one page at a time
+            #[cfg(unix)]
+            let _ = mmap.advise_range(memmap2::Advice::WillNeed, range.start, range.len());
+            // reading a byte of every page faults it in
+            const PAGE_SIZE: usize = 4096;
+            for byte in mmap[range.clone()].iter().step_by(PAGE_SIZE) {
+                std::hint::black_box(*byte);
+            }
+        }
     }
 
-    pub fn search(&self, search: &Search) -> Vec<u32> {
-        // contains all the buckets
-        let mut res: Vec<RoaringBitmap> = Vec::new();
-        let mut candidates = self.get_candidates(&search);
+    /// Replace the tokenizer of the index. It must be the tokenizer that was used to build
+    /// the index, it's not serialized thus it needs to be set again after loading an index.
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
 
-        // TODO: returns random results maybe?
-        if candidates.len() == 0 {
-            return Vec::new();
-        }
+    /// Split the words of the index in n-grams of 3 characters and use them to find the words of the
+    /// queries with typos instead of the levenshtein automatons. A word of the index is matched when
+    /// it shares enough n-grams with the word of the query, every typo changes at most 3 of them, and
+    /// its number of typos is estimated from the n-grams that differ. The typos allowed still follow
+    /// the [`TypoConfig`]. It's more tolerant with the long words and noisy texts, like OCR, but costs
+    /// a copy of every word and its n-grams in memory.
+    ///
+    /// The words searched as prefixes still go through the automatons, the n-grams of a prefix don't
+    /// match the end of the words. The n-grams aren't serialized, they need to be built again after
+    /// loading an index.
+    pub fn with_ngrams(mut self) -> Self {
+        self.ngrams = Some(NGrams::new(&self.fst));
+        self
+    }
 
-        let mut ranking_rules: Vec<Box<dyn RankingRuleImpl>> = search
-            .ranking_rules
-            .iter()
-            .map(|ranking_rule| match ranking_rule {
-                RankingRule::Word => {
-                    Box::new(Word::new(&mut candidates)) as Box<dyn RankingRuleImpl>
-                }
-                RankingRule::Typo => Box::new(Typo::new(&candidates)) as Box<dyn RankingRuleImpl>,
-                RankingRule::Exact => Box::new(Exact::new()) as Box<dyn RankingRuleImpl>,
-            })
-            .collect();
-        let ranking_rules_len = ranking_rules.len();
+    /// Set a static boost on every document, the n-th boost belongs to the n-th document and the
+    /// documents without one have a boost of 1. The boosts are saved with the index and used by
+    /// the [`RankingRule::Boost`] ranking rule, the documents with the highest boost come first.
+    pub fn with_boosts(mut self, mut boosts: Vec<f32>) -> Self {
+        boosts.resize(self.documents.len(), 1.0);
+        self.boosts = boosts;
+        self
+    }
 
-        let mut current_ranking_rule = 0;
+    /// Insert a new document in the index and returns its id.
+    /// The ids are stable, it's always the number of documents present before the insertion.
+    ///
+    /// Since the fst can't be updated, the unknown words are first stored in a pending map
+    /// that's searched alongside the fst. Once the pending map becomes as big as the fst
+    /// (or reach a minimum size), they're merged in a new fst.
+    /// Rebuilding the fst costs `O(number of words)` but since its size doubles every time
+    /// the amortized cost of an insertion stays `O(number of words in the document)`.
+    pub fn insert(&mut self, document: T::Owned) -> Id {
+        const MIN_PENDING: usize = 1024;
 
-        macro_rules! next {
-            () => {
-                {
-                // we cannot borrow twice the list of ranking rules thus we'll cheat a little
-                let current = &mut ranking_rules[current_ranking_rule];
-                // we detach the lifetime from the vec, this allow us to borrow the previous element safely
-                let current: &'static mut Box<dyn RankingRuleImpl> = unsafe { std::mem::transmute(current) };
-                current.next(
-                    current_ranking_rule.checked_sub(1).and_then(|prev| ranking_rules.get(prev)).map(|rr| &**rr),
-                    &mut candidates,
-                    self
-                )
-                }
-            };
+        let id = self.insert_pending(document);
+        if self.pending.len() >= MIN_PENDING.max(self.fst.len()) {
+            self.merge_pending();
         }
 
-        while res.iter().map(|bucket| bucket.len()).sum::<u64>() < search.limit as u64 {
-            let next = next!();
-            let ranking_rule = &mut ranking_rules[current_ranking_rule];
+        id
+    }
 
-            match next {
-                // We want to advance
-                ControlFlow::Continue(()) => {
-                    if current_ranking_rule == ranking_rules_len - 1 {
-                        // there is no ranking rule to continue, get the bucket of the current one and call it again
-                        let bucket = ranking_rule.current_results(&candidates);
-                        Self::cleanup(&bucket, &mut candidates);
-                        ranking_rules.iter_mut().for_each(|rr| rr.cleanup(&bucket));
-                        res.push(bucket);
-                    } else {
-                        // we advance and do nothing
-                        current_ranking_rule += 1;
-                    }
-                }
-                // We want to get back one ranking rule behind
-                ControlFlow::Break(bucket) if bucket.is_empty() => {
-                    // if we're at the first ranking rule and there is nothing left to sort, exit
-                    if current_ranking_rule == 0 {
-                        break;
-                    }
-                    current_ranking_rule -= 1;
-                    res.push(bucket);
+    /// Insert a document without ever merging the pending words, see [`Index::batch`]
+    fn insert_pending(&mut self, document: T::Owned) -> Id {
+        let id = self.documents.len() as Id;
+
+        let mut length = 0;
+        for (pos, word) in tokenize(&*self.tokenizer, document.borrow().as_ref()) {
+            length += 1;
+            match self.fst.get(&word) {
+                Some(bitmap) => {
+                    self.bitmaps[bitmap as usize].insert(id);
+                    self.positions[bitmap as usize].insert(position(id, pos));
                 }
-                // We want to push that bucket and continue our life with the next ranking rule if there is one
-                ControlFlow::Break(bucket) => {
-                    Self::cleanup(&bucket, &mut candidates);
-                    ranking_rules.iter_mut().for_each(|rr| rr.cleanup(&bucket));
-                    res.push(bucket);
+                None => {
+                    let (bitmap, positions) = self.pending.entry(word).or_default();
+                    bitmap.insert(id);
+                    positions.insert(position(id, pos));
                 }
             }
         }
+        self.documents.push(document);
+        self.lengths.push(length);
+        if !self.boosts.is_empty() {
+            self.boosts.push(1.0);
+        }
 
-        res.iter()
-            .flat_map(|bitmap| bitmap.iter())
-            .take(search.limit)
-            .collect()
+        id
     }
 
-    fn cleanup(used: &RoaringBitmap, candidates: &mut [WordCandidate]) {
-        for candidate in candidates.iter_mut() {
-            for typo in candidate.typos.iter_mut() {
-                *typo -= used;
-            }
-        }
+    /// Start a batch of insertions and deletions, they're applied together by [`Batch::commit`]
+    /// and the fst is rebuilt once at the end instead of whenever the pending words grow too big.
+    /// Dropping the batch without committing it discards it.
+    ///
+    /// The batch borrows the index mutably, thus no search can run until it's committed or dropped
+    /// and a search never sees half of a batch. To keep searching while a batch is prepared, apply it
+    /// on a copy of the index and swap the two once it's committed.
+    pub fn batch(&mut self) -> Batch<'_, 'a, T> {
+        Batch::new(self)
     }
 
-    fn get_candidates(&self, search: &Search) -> Vec<WordCandidate> {
-        static LEVENSHTEINS: OnceLock<[LevenshteinAutomatonBuilder; 4]> = OnceLock::new();
-        let levenshtein = LEVENSHTEINS.get_or_init(|| {
-            core::array::from_fn(|nb_typo| LevenshteinAutomatonBuilder::new(nb_typo as u8, true))
-        });
-
-        let words: Vec<_> = search
-            .input
-            .split_whitespace()
-            .map(|word| (word, normalize(word)))
-            .filter(|(_word, normalized)| !normalized.is_empty())
-            .collect();
-        let mut ret = Vec::with_capacity(words.len());
-
-        for (index, (word, normalized)) in words.iter().enumerate() {
-            let mut candidates =
-                WordCandidate::new(word.to_string(), normalized.to_string(), index);
-
-            // enable 1 typo every 3 letters maxed at 3 typos
-            let typo = (normalized.len() / 3).min(3);
-            let lev = &levenshtein[typo];
+    /// Delete a document from the index, its id won't be reused and won't be returned by any search.
+    /// Returns `false` if there was no document with this id.
+    ///
+    /// This doesn't rebuild the fst, the words that are not contained
+    /// in any document anymore simply point to an empty bitmap until the next merge.
+    /// The document itself stays in memory but isn't written by [`Index::write_into`].
+    pub fn delete(&mut self, id: Id) -> bool {
+        if self.documents.len() <= id as usize || self.deleted.contains(id) {
+            return false;
+        }
+        let document = self.documents[id].as_ref();
 
-            // if we're at the last word we should also run a prefix search
-            if index == words.len() - 1 {
-                let lev = lev.build_prefix_dfa(&normalized);
-                let mut stream = self.fst.search(lev).into_stream();
-                while let Some((matched, id)) = stream.next() {
-                    candidates.insert_with_maybe_typo(
-                        std::str::from_utf8(matched).unwrap(),
-                        &self.bitmaps[id as usize],
-                    );
-                }
-            } else {
-                let lev = lev.build_dfa(&normalized);
-                let mut stream = self.fst.search(lev).into_stream();
-                while let Some((matched, id)) = stream.next() {
-                    candidates.insert_with_maybe_typo(
-                        std::str::from_utf8(matched).unwrap(),
-                        &self.bitmaps[id as usize],
-                    );
+        // only the words of the document can contains its id
+        let document_positions = position(id, 0)..position(id + 1, 0);
+        for (_, word) in tokenize(&*self.tokenizer, document) {
+            if let Some(bitmap) = self.fst.get(&word) {
+                self.bitmaps[bitmap as usize].remove(id);
+                self.positions[bitmap as usize].remove_range(document_positions.clone());
+            } else if let Some((bitmap, positions)) = self.pending.get_mut(&word) {
+                bitmap.remove(id);
+                positions.remove_range(document_positions.clone());
+                if bitmap.is_empty() {
+                    self.pending.remove(&word);
                 }
             }
-
-            ret.push(candidates);
         }
+        self.deleted.insert(id);
 
-        ret
+        true
+    }
+
+    /// Rebuild the fst with all the pending words.
+    /// The words that are not contained in any document anymore are dropped.
+    fn merge_pending(&mut self) {
+        let mut build = MapBuilder::memory();
+
+        let mut stream = self.fst.stream();
+        let mut pending = std::mem::take(&mut self.pending).into_iter().peekable();
+
+        // the fst and the pending words are both sorted and disjoint, we only need to merge them
+        while let Some((word, bitmap)) = stream.next() {
+            while let Some((pending_word, (pending_bitmap, pending_positions))) =
+                pending.next_if(|(pending_word, _)| pending_word.as_bytes() < word)
+            {
+                self.bitmaps.push(pending_bitmap);
+                self.positions.push(pending_positions);
+                let id = self.bitmaps.len() - 1;
+                if let Some(ngrams) = &mut self.ngrams {
+                    ngrams.insert(id as u32, &pending_word);
+                }
+                build.insert(pending_word, id as u64).unwrap();
+            }
+            if !self.bitmaps[bitmap as usize].is_empty() {
+                build.insert(word, bitmap).unwrap();
+            }
+        }
+        for (pending_word, (pending_bitmap, pending_positions)) in pending {
+            self.bitmaps.push(pending_bitmap);
+            self.positions.push(pending_positions);
+            let id = self.bitmaps.len() - 1;
+            if let Some(ngrams) = &mut self.ngrams {
+                ngrams.insert(id as u32, &pending_word);
+            }
+            build.insert(pending_word, id as u64).unwrap();
+        }
+
+        let fst = build.into_inner().unwrap();
+        self.fst = Map::new(FstData::Owned(fst)).unwrap();
+    }
+
+    /// Merge two indexes, typically built in parallel on shards of the corpus. The documents of
+    /// `other` are appended after the documents of `self`, thus their ids are shifted by the number
+    /// of documents of `self`. Both indexes must have been built with the same tokenizer,
+    /// the tokenizer of `self` is kept.
+    pub fn merge(mut self, mut other: Index<'a, T>) -> Index<'a, T> {
+        // everything must be in the fsts before we can union them
+        self.merge_pending();
+        other.merge_pending();
+
+        let offset = self.documents.len() as Id;
+        let shift = |bitmap: &RoaringBitmap| {
+            RoaringBitmap::from_sorted_iter(bitmap.iter().map(|id| id + offset)).unwrap()
+        };
+        let shift_positions = |positions: &RoaringTreemap| {
+            RoaringTreemap::from_sorted_iter(positions.iter().map(|pos| pos + position(offset, 0)))
+                .unwrap()
+        };
+
+        let mut build = MapBuilder::memory();
+        let mut bitmaps = Vec::new();
+        let mut positions = Vec::new();
+
+        let mut stream = self.fst.op().add(&other.fst).union();
+        while let Some((word, values)) = stream.next() {
+            let mut bitmap = RoaringBitmap::new();
+            let mut word_positions = RoaringTreemap::new();
+            for value in values {
+                let id = value.value as usize;
+                if value.index == 0 {
+                    bitmap |= &self.bitmaps[id];
+                    word_positions |= &self.positions[id];
+                } else {
+                    bitmap |= shift(&other.bitmaps[id]);
+                    word_positions |= shift_positions(&other.positions[id]);
+                }
+            }
+            bitmaps.push(bitmap);
+            positions.push(word_positions);
+            build.insert(word, (bitmaps.len() - 1) as u64).unwrap();
+        }
+        let fst = build.into_inner().unwrap();
+
+        // an index without boosts doesn't store them
+        if !self.boosts.is_empty() || !other.boosts.is_empty() {
+            self.boosts.resize(self.documents.len(), 1.0);
+            other.boosts.resize(other.documents.len(), 1.0);
+            self.boosts.extend(other.boosts);
+        }
+
+        self.deleted |= shift(&other.deleted);
+        self.documents.extend(other.documents);
+        self.lengths.extend(other.lengths);
+
+        let fst = Map::new(FstData::Owned(fst)).unwrap();
+        Index {
+            documents: self.documents,
+            bitmaps,
+            positions,
+            // the ids of the words changed
+            ngrams: self.ngrams.map(|_| NGrams::new(&fst)),
+            fst,
+            pending: BTreeMap::new(),
+            deleted: self.deleted,
+            lengths: self.lengths,
+            boosts: self.boosts,
+            tokenizer: self.tokenizer,
+        }
+    }
+
+    /// Drop the deleted documents for good, the other ones get contiguous ids in the same order.
+    /// The fst is rebuilt without the words that are not contained in any document anymore.
+    /// Returns the compacted index along with the new id of every document, the n-th value is
+    /// the new id of the document n or `None` if it was deleted.
+    pub fn compact(mut self) -> (Index<'a, T>, Vec<Option<Id>>) {
+        self.merge_pending();
+
+        let mut next = 0;
+        let ids: Vec<Option<Id>> = (0..self.documents.len() as Id)
+            .map(|id| {
+                (!self.deleted.contains(id)).then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+        // the ids keep their order thus everything stays sorted
+        let remap = |bitmap: &RoaringBitmap| {
+            RoaringBitmap::from_sorted_iter(bitmap.iter().filter_map(|id| ids[id as usize]))
+                .unwrap()
+        };
+        let remap_positions = |positions: &RoaringTreemap| {
+            RoaringTreemap::from_sorted_iter(positions.iter().filter_map(|pos| {
+                let id = ids[(pos >> 32) as usize]?;
+                Some(position(id, pos as u32))
+            }))
+            .unwrap()
+        };
+
+        let mut build = MapBuilder::memory();
+        let mut bitmaps = Vec::new();
+        let mut positions = Vec::new();
+        let mut stream = self.fst.stream();
+        while let Some((word, bitmap)) = stream.next() {
+            let bitmap = bitmap as usize;
+            if self.bitmaps[bitmap].is_empty() {
+                continue;
+            }
+            bitmaps.push(remap(&self.bitmaps[bitmap]));
+            positions.push(remap_positions(&self.positions[bitmap]));
+            build.insert(word, (bitmaps.len() - 1) as u64).unwrap();
+        }
+        let fst = build.into_inner().unwrap();
+
+        let kept = |id: &usize| ids[*id].is_some();
+        let lengths = self
+            .lengths
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| kept(id))
+            .map(|(_, length)| *length)
+            .collect();
+        let boosts = self
+            .boosts
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| kept(id))
+            .map(|(_, boost)| *boost)
+            .collect();
+
+        let fst = Map::new(FstData::Owned(fst)).unwrap();
+        let index = Index {
+            documents: self.documents.without(&self.deleted),
+            bitmaps,
+            positions,
+            // the ids of the words changed
+            ngrams: self.ngrams.map(|_| NGrams::new(&fst)),
+            fst,
+            pending: BTreeMap::new(),
+            deleted: RoaringBitmap::new(),
+            lengths,
+            boosts,
+            tokenizer: self.tokenizer,
+        };
+        (index, ids)
+    }
+
+    /// Returns every normalized word of the index in lexicographic order along with the number of
+    /// documents containing it. The fst doesn't store its keys contiguously, thus they're decoded
+    /// one by one while iterating.
+    pub fn terms(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        Terms {
+            stream: self.fst.stream(),
+            pending: self.pending.iter().peekable(),
+            bitmaps: &self.bitmaps,
+            next_word: None,
+        }
+        .filter(|(_, count)| *count > 0)
+    }
+
+    /// Returns true if a document contains the word exactly, once normalized by the tokenizer
+    /// like the documents. The fst is looked up directly, without typos nor prefix.
+    pub fn contains(&self, word: &str) -> bool {
+        self.document_frequency(word) > 0
+    }
+
+    /// Returns the number of documents containing the word exactly, once normalized by the
+    /// tokenizer like the documents. The rare words are the most discriminating ones.
+    pub fn document_frequency(&self, word: &str) -> u64 {
+        self.word_documents(&self.tokenizer.normalize(word))
+            .map_or(0, RoaringBitmap::len)
+    }
+
+    /// Returns the size of the index, to plan its capacity or monitor its growth.
+    /// The sizes are the ones the bitmaps would take once serialized.
+    pub fn stats(&self) -> IndexStats {
+        let pending = self.pending.values();
+        IndexStats {
+            documents: self.documents.len() - self.deleted.len() as usize,
+            terms: self.fst.len() + self.pending.len(),
+            fst_bytes: self.fst.as_fst().size(),
+            bitmaps_bytes: self
+                .bitmaps
+                .iter()
+                .chain(pending.clone().map(|(bitmap, _)| bitmap))
+                .map(RoaringBitmap::serialized_size)
+                .sum(),
+            positions_bytes: self
+                .positions
+                .iter()
+                .chain(pending.map(|(_, positions)| positions))
+                .map(RoaringTreemap::serialized_size)
+                .sum(),
+        }
+    }
+
+    /// Returns the fields of a document, a document built without fields has a single field.
+    pub fn get_fields(&self, id: Id) -> Option<impl Iterator<Item = &str>> {
+        self.get_document(id)
+            .map(|document| document.as_ref().split(FIELD_SEPARATOR))
+    }
+
+    pub fn get_document(&self, id: Id) -> Option<&T> {
+        if self.deleted.contains(id) {
+            return None;
+        }
+        self.documents.get(id)
+    }
+
+    /// Run the search and return the matching documents in the ranked order.
+    pub fn search(&self, search: &Search) -> Vec<&T> {
+        self.search_iter(search).collect()
+    }
+
+    /// Same as [`Index::search`] but the documents are cloned, the results outlive the borrow
+    /// of the index, to release a lock on it before using them for example. It costs an
+    /// allocation and a copy of every returned document, a `String` for the text documents.
+    pub fn search_owned(&self, search: &Search) -> Vec<T::Owned> {
+        self.search_iter(search).map(ToOwned::to_owned).collect()
+    }
+
+    /// Same as [`Index::search`] but the ranking rules are ran lazily, bucket by bucket,
+    /// while the results are consumed. Stopping early avoids sorting the remaining documents.
+    pub fn search_iter(&self, search: &Search) -> impl Iterator<Item = &T> + '_ {
+        let tiebreak = search.tiebreak.clone();
+        self.ranked_buckets(search)
+            .flat_map(move |bucket| bucket_ids(tiebreak.as_ref(), bucket))
+            .skip(search.offset)
+            .take(search.limit())
+            .map(|id| &self.documents[id])
+    }
+
+    /// A cheap search for type-ahead, returns the documents containing a word starting with
+    /// `prefix`. There is no typo tolerance and no ranking rules, the documents of the most
+    /// frequent words come first and are sorted by id. An empty prefix returns nothing.
+    pub fn autocomplete(&self, prefix: &str, limit: usize) -> Vec<&T> {
+        let prefix = self.tokenizer.normalize(prefix);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let mut words: Vec<&RoaringBitmap> = Vec::new();
+        let mut stream = self
+            .fst
+            .search(Str::new(&prefix).starts_with())
+            .into_stream();
+        while let Some((_, bitmap)) = stream.next() {
+            words.push(&self.bitmaps[bitmap as usize]);
+        }
+        words.extend(
+            self.pending
+                .range(prefix.clone()..)
+                .take_while(|(word, _)| word.starts_with(&prefix))
+                .map(|(_, (bitmap, _))| bitmap),
+        );
+        words.sort_by_key(|bitmap| Reverse(bitmap.len()));
+
+        let mut seen = RoaringBitmap::new();
+        let mut documents = Vec::new();
+        for bitmap in words {
+            for id in bitmap - &seen {
+                if documents.len() >= limit {
+                    return documents;
+                }
+                documents.push(&self.documents[id]);
+            }
+            seen |= bitmap;
+        }
+        documents
+    }
+
+    /// Returns the total number of documents matching the search, ignoring the limit and the offset.
+    /// It doesn't run the ranking rules, it's the number of documents containing at least one word of
+    /// the query, or all of them if the [`RankingRule::Word`] ranking rule isn't used.
+    /// The buckets are never computed, thus [`Search::with_max_buckets`] doesn't change the count.
+    pub fn count(&self, search: &Search) -> usize {
+        let candidates = self.get_candidates(search);
+        if candidates.is_empty() {
+            return match search.placeholder {
+                true => self.placeholder_documents(search).len() as usize,
+                false => 0,
+            };
+        }
+        let documents = candidates
+            .iter()
+            .map(|candidate| candidate.typos.as_slice().union());
+        let documents = if search.ranking_rules.contains(&RankingRule::Word) {
+            documents.union()
+        } else {
+            documents.intersection()
+        };
+        documents.len() as usize
+    }
+
+    /// Same as [`Index::search`] but the documents are grouped by the bucket of the ranking rules they
+    /// were found in, the documents of a bucket are equally relevant and sorted by [`Search::with_tiebreak`]. With the
+    /// [`RankingRule::Typo`] ranking rule last, the first bucket contains the matches without typos.
+    /// The limit and the offset apply to the documents, a bucket can thus be cut in the middle.
+    pub fn search_buckets(&self, search: &Search) -> Vec<Vec<&T>> {
+        let (mut offset, mut limit) = (search.offset, search.limit());
+        let mut buckets = Vec::new();
+        for bucket in self.ranked_buckets(search) {
+            if limit == 0 {
+                break;
+            }
+            let len = bucket.len() as usize;
+            if offset >= len {
+                offset -= len;
+                continue;
+            }
+            let bucket: Vec<&T> = bucket_ids(search.tiebreak.as_ref(), bucket)
+                .skip(offset)
+                .take(limit)
+                .map(|id| &self.documents[id])
+                .collect();
+            offset = 0;
+            limit -= bucket.len();
+            buckets.push(bucket);
+        }
+        buckets
+    }
+
+    /// Same as [`Index::search`] but every document comes with the rank of the bucket it was found in,
+    /// see [`Index::search_buckets`]. The documents of the first bucket have the rank 0, the rank
+    /// doesn't depend on the offset.
+    pub fn search_ranked(&self, search: &Search) -> Vec<(usize, &T)> {
+        self.ranked_buckets(search)
+            .enumerate()
+            .flat_map(|(rank, bucket)| {
+                bucket_ids(search.tiebreak.as_ref(), bucket).map(move |id| (rank, id))
+            })
+            .skip(search.offset)
+            .take(search.limit())
+            .map(|(rank, id)| (rank, &self.documents[id]))
+            .collect()
+    }
+
+    /// Same as [`Index::search`] but returns the ids of the documents instead of their content.
+    /// The ids are the position of the documents in the list the index was built from.
+    pub fn search_ids(&self, search: &Search) -> Vec<Id> {
+        self.ranked_buckets(search)
+            .flat_map(|bucket| bucket_ids(search.tiebreak.as_ref(), bucket))
+            .skip(search.offset)
+            .take(search.limit())
+            .collect()
+    }
+
+    /// Sort documents coming from elsewhere, like a recommender, with the ranking rules of the search.
+    /// The words of the query are still looked up in the fst to know what every candidate contains,
+    /// but only the candidates go through the ranking rules, like with [`Search::with_scope`].
+    /// The candidates matching none of the words are not dropped, they come last as a single bucket.
+    /// The ones matching some words but discarded by the search, like with [`MatchMode::All`], are.
+    /// The words of the index past [`Search::with_max_candidate_terms`] are never looked at, the
+    /// candidates only matching them count as matching none of the words.
+    /// The deleted and filtered out documents are never returned, the limit and the offset apply.
+    pub fn rank(&self, search: &Search, candidates: &RoaringBitmap) -> Vec<Id> {
+        let mut scoped = search.clone();
+        scoped.with_scope(match &search.scope {
+            Some(scope) => scope & candidates,
+            None => candidates.clone(),
+        });
+
+        let wanted = search.offset.saturating_add(search.limit());
+        let mut ids = Vec::new();
+        let mut buckets = 0;
+        let mut matched = RoaringBitmap::new();
+        let mut ranked_buckets =
+            self.ranked_buckets_in(&scoped, SearchContext::default(), None, Some(&mut matched));
+        for bucket in ranked_buckets.by_ref() {
+            buckets += 1;
+            ids.extend(bucket_ids(search.tiebreak.as_ref(), bucket));
+            if ids.len() >= wanted {
+                break;
+            }
+        }
+        // the candidates matching no word are the last bucket, the ones dropped for matching
+        // too few words or with too many typos are not returned
+        if ids.len() < wanted && buckets < search.max_buckets {
+            let rest = self.placeholder_documents(&scoped) - matched;
+            // the keys of the ranked documents were already returned
+            let rest = ranked_buckets.dedup(rest);
+            ids.extend(bucket_ids(search.tiebreak.as_ref(), rest));
+        }
+
+        ids.into_iter()
+            .skip(search.offset)
+            .take(search.limit())
+            .collect()
+    }
+
+    /// Run all the searches, it's the same as calling [`Index::search`] on each of them
+    /// but the working buffers are reused from one search to the next.
+    pub fn search_many(&self, searches: &[Search]) -> Vec<Vec<&T>> {
+        let mut context = SearchContext::default();
+        searches
+            .iter()
+            .map(|search| {
+                let mut buckets =
+                    self.ranked_buckets_in(search, std::mem::take(&mut context), None, None);
+                let documents = buckets
+                    .by_ref()
+                    .flat_map(|bucket| bucket_ids(search.tiebreak.as_ref(), bucket))
+                    .skip(search.offset)
+                    .take(search.limit())
+                    .map(|id| &self.documents[id])
+                    .collect();
+                context = buckets.into_context();
+                documents
+            })
+            .collect()
+    }
+
+    /// Same as [`Index::search`] but every document comes with its relevance score.
+    /// The score is in `[0, 1]`, every word of the query the document matched adds
+    /// `1 / (1 + typos)` and the sum is divided by the number of words of the query,
+    /// thus 1 is a match of every word without typo. It only depends on the query and
+    /// the document, not on the other results nor the size of the index, it can be used
+    /// as a relevance cutoff. The ranking rules can still order the documents differently.
+    pub fn search_scored(&self, search: &Search) -> Vec<(f32, &T)> {
+        let mut buckets = self.ranked_buckets(search);
+        // the ranking rules consume the candidates, we keep a copy to count the typos
+        let candidates = buckets.candidates.clone();
+
+        buckets
+            .by_ref()
+            .flat_map(|bucket| bucket_ids(search.tiebreak.as_ref(), bucket))
+            .skip(search.offset)
+            .take(search.limit())
+            .map(|id| (Self::score(&candidates, id), &self.documents[id]))
+            .collect()
+    }
+
+    /// The score of [`Index::search_scored`], a search without any word matches everything
+    fn score(candidates: &[WordCandidate], id: Id) -> f32 {
+        if candidates.is_empty() {
+            return 1.0;
+        }
+        let matched: f32 = candidates
+            .iter()
+            .filter_map(|candidate| candidate.typos_for(id))
+            .map(|typos| 1.0 / (1.0 + typos as f32))
+            .sum();
+        matched / candidates.len() as f32
+    }
+
+    /// Same as [`Index::search`] but every document comes with the byte ranges of the words that
+    /// matched the query, including the ones matched with typos. When a word was matched
+    /// as a prefix only the prefix is highlighted.
+    pub fn search_highlighted(&self, search: &Search) -> Vec<HighlightedDoc<'_>> {
+        // for every matched term, the length of its normalized prefix to highlight if it's only a prefix
+        let mut terms: HashMap<&str, Option<usize>> = HashMap::new();
+        let candidates = self.get_candidates(search);
+        for candidate in candidates.iter() {
+            for (term, _) in candidate.terms.iter() {
+                let prefix = (candidate.prefix && candidate.normalized.len() < term.len())
+                    .then_some(candidate.normalized.len());
+                // if another word matched the whole term we want to highlight all of it
+                terms
+                    .entry(term.as_str())
+                    .and_modify(|current| {
+                        *current = current.zip(prefix).map(|(left, right)| left.max(right))
+                    })
+                    .or_insert(prefix);
+            }
+        }
+
+        self.search_ids(search)
+            .into_iter()
+            .map(|id| {
+                let document = self.text(id);
+                let highlights = tokens(&*self.tokenizer, document)
+                    .filter_map(|(_, span, word)| {
+                        let prefix = terms.get(self.tokenizer.normalize(word).as_str())?;
+                        Some(match prefix {
+                            Some(len) => {
+                                span.start..span.start + original_len(&*self.tokenizer, word, *len)
+                            }
+                            None => span,
+                        })
+                    })
+                    .collect();
+                HighlightedDoc {
+                    id,
+                    document,
+                    highlights,
+                }
+            })
+            .collect()
+    }
+
+    /// Same as [`Index::search`] but every document is cut to at most `max_len` characters around
+    /// its best match, the matched word it's centered on has the most highlighted words of
+    /// [`Index::search_highlighted`] around it. The snippet never cuts a word at its edges nor
+    /// spans multiple fields, and the fields shorter than `max_len` are returned whole.
+    pub fn search_snippets(&self, search: &Search, max_len: usize) -> Vec<String> {
+        self.search_highlighted(search)
+            .into_iter()
+            .map(|doc| snippet(doc.document, &doc.highlights, max_len).to_string())
+            .collect()
+    }
+
+    /// Same as [`Index::search`] but every document comes with the words of the index it matched,
+    /// for every word of the query, along with their number of typos. It's meant to understand
+    /// why a document was returned.
+    pub fn search_explained(&self, search: &Search) -> Vec<ExplainedDoc<'_, T>> {
+        // the ranking rules consume the candidates, we need a fresh copy to know what matched
+        let candidates = self.get_candidates(search);
+
+        self.search_ids(search)
+            .into_iter()
+            .map(|id| {
+                let matches = candidates
+                    .iter()
+                    .filter(|candidate| candidate.typos_for(id).is_some())
+                    .flat_map(|candidate| {
+                        candidate
+                            .terms
+                            .iter()
+                            .filter(|(term, _)| {
+                                self.word_documents(term)
+                                    .is_some_and(|documents| documents.contains(id))
+                            })
+                            .map(|(term, typos)| TermMatch {
+                                query_word: candidate.original.clone(),
+                                term: term.clone(),
+                                typos: *typos,
+                            })
+                    })
+                    .collect();
+                ExplainedDoc {
+                    id,
+                    document: &self.documents[id],
+                    matches,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns how many documents every word of the query matched with each number of typos,
+    /// before any ranking rule runs. It's meant to tune the ranking rules and the typo tolerance.
+    /// The filter, the quorum and the typo limits of the search are already applied.
+    pub fn candidate_stats(&self, search: &Search) -> Vec<WordCandidateStats> {
+        self.get_candidates(search)
+            .into_iter()
+            .map(|candidate| WordCandidateStats {
+                documents: candidate.typos.iter().map(RoaringBitmap::len).collect(),
+                original: candidate.original,
+                normalized: candidate.normalized,
+                prefix: candidate.prefix,
+            })
+            .collect()
+    }
+
+    /// Returns the words of the query, as they were written, that didn't match any document even
+    /// with typos or as a prefix, like "xyzzy" in "beau xyzzy". The results then only come from
+    /// the other words. The documents out of the scope or filtered out don't count.
+    /// The stop words are not searched thus never returned. It runs the matching
+    /// again, it's meant to be called next to a search only to show the unmatched words.
+    pub fn unmatched_words(&self, search: &Search) -> Vec<String> {
+        self.get_candidates(search)
+            .into_iter()
+            .filter(|candidate| candidate.typos.iter().all(RoaringBitmap::is_empty))
+            .map(|candidate| candidate.original)
+            .collect()
+    }
+
+    /// Prepare the ranking rules, the non-empty buckets of documents are then computed lazily in the ranked order.
+    /// A document appears in at most one bucket.
+    fn ranked_buckets(&self, search: &Search) -> RankedBuckets<'_> {
+        self.ranked_buckets_in(search, SearchContext::default(), None, None)
+    }
+
+    /// Same as [`Index::ranked_buckets`] but reuse the allocations of a previous search,
+    /// and the documents of its words if there is a cache, see [`SearchSession`].
+    /// `matched` gets the documents matching any word, see [`Index::get_candidates_in`].
+    fn ranked_buckets_in(
+        &self,
+        search: &Search,
+        context: SearchContext,
+        cache: Option<&mut WordCache>,
+        matched: Option<&mut RoaringBitmap>,
+    ) -> RankedBuckets<'_> {
+        let SearchContext {
+            candidates,
+            mut ranking_rules,
+            seen,
+        } = context;
+        let mut candidates = self.get_candidates_in(search, candidates, cache, matched);
+
+        ranking_rules.clear();
+        ranking_rules.extend(
+            search
+                .ranking_rules
+                .iter()
+                .map(|ranking_rule| build_ranking_rule(ranking_rule, search, &mut candidates)),
+        );
+        // the default ranking rules can't split the documents containing a single word much,
+        // the ones mentioning it the most times are more likely to be about it. The exact ranking
+        // rule stays last to break the ties.
+        if candidates.len() == 1 && search.ranking_rules == DEFAULT_RANKING_RULES {
+            ranking_rules.insert(ranking_rules.len() - 1, Box::new(Frequency::new()));
+        }
+
+        // a query without any word doesn't give anything to sort to the ranking rules, and
+        // without any ranking rule the documents containing every word are returned unsorted
+        let placeholder = if candidates.is_empty() {
+            search
+                .placeholder
+                .then(|| self.placeholder_documents(search))
+        } else if ranking_rules.is_empty() {
+            Some(
+                candidates
+                    .iter()
+                    .map(|candidate| candidate.typos.as_slice().union())
+                    .intersection(),
+            )
+        } else {
+            None
+        };
+
+        RankedBuckets {
+            index: self,
+            done: candidates.is_empty()
+                || ranking_rules.is_empty()
+                || search.limit() == 0
+                || search.max_buckets == 0,
+            placeholder: placeholder.filter(|_| search.max_buckets > 0),
+            max_stalled_steps: STALLED_STEPS_PER_WORD * (candidates.len() + ranking_rules.len()),
+            stalled_steps: 0,
+            candidates,
+            ranking_rules,
+            current_ranking_rule: 0,
+            seen,
+            remaining: search.offset.saturating_add(search.limit()),
+            remaining_buckets: search.max_buckets,
+            tiebreak: search.tiebreak.clone(),
+            dedup: search.dedup.as_ref().map(|Dedup(dedup)| dedup()),
+            trace: search.trace.clone(),
+        }
+    }
+
+    /// Returns all the documents allowed by the filter of the search
+    fn placeholder_documents(&self, search: &Search) -> RoaringBitmap {
+        let mut documents = RoaringBitmap::new();
+        documents.insert_range(0..self.documents.len() as Id);
+        documents -= &self.deleted;
+        if let Some(scope) = &search.scope {
+            documents &= scope;
+        }
+        if self.uses_boolean_operators(search) {
+            let expr = Expr::parse(search.input, &*self.tokenizer);
+            if let Some(allowed) = expr.and_then(|expr| self.boolean_documents(&expr, &[])) {
+                documents &= allowed;
+            }
+        } else {
+            documents -= self.excluded_documents(&QueryPart::parse(search.input, &*self.tokenizer));
+        }
+        match &search.filter {
+            Some(Filter(filter)) => documents.into_iter().filter(|id| filter(*id)).collect(),
+            None => documents,
+        }
+    }
+
+    /// Returns the documents containing any of the negative words of the query
+    fn excluded_documents(&self, parts: &[QueryPart]) -> RoaringBitmap {
+        parts
+            .iter()
+            .filter_map(|part| match part {
+                QueryPart::Negative { normalized, .. } => self.word_documents(normalized),
+                _ => None,
+            })
+            .union()
+    }
+
+    /// The boolean operators need the query syntax of the tokenizer, see [`Tokenizer::query_syntax`]
+    fn uses_boolean_operators(&self, search: &Search) -> bool {
+        search.boolean_operators && self.tokenizer.query_syntax()
+    }
+
+    /// Returns the documents matching the boolean operators of the query, the words match the
+    /// documents of their candidate and the excluded words only match exactly.
+    /// The words without candidate, like the stop words, are left out.
+    fn boolean_documents(
+        &self,
+        expr: &Expr,
+        candidates: &[WordCandidate],
+    ) -> Option<RoaringBitmap> {
+        let mut universe = RoaringBitmap::new();
+        universe.insert_range(0..self.documents.len() as Id);
+        universe -= &self.deleted;
+        expr.evaluate(&universe, &|part, negated| match part {
+            QueryPart::Word { normalized, .. } if negated => {
+                Some(self.word_documents(normalized).cloned().unwrap_or_default())
+            }
+            QueryPart::Phrase { words, .. } if negated => Some(self.phrase_documents(words)),
+            part => candidates
+                .iter()
+                .find(|candidate| candidate.original == part.original())
+                .map(|candidate| candidate.typos.as_slice().union()),
+        })
+    }
+
+    /// Returns the documents containing all the words contiguously and in order
+    fn phrase_documents(&self, words: &[String]) -> RoaringBitmap {
+        // no need to go through the positions
+        if let [word] = words {
+            return self.word_documents(word).cloned().unwrap_or_default();
+        }
+
+        let mut matches: Option<RoaringTreemap> = None;
+        for word in words {
+            let Some(positions) = self.word_positions(word) else {
+                return RoaringBitmap::new();
+            };
+            matches = Some(match matches {
+                None => positions.clone(),
+                // only keep the positions directly following the previous word
+                Some(previous) => {
+                    previous
+                        .iter()
+                        .map(|pos| pos + 1)
+                        .collect::<RoaringTreemap>()
+                        & positions
+                }
+            });
+        }
+
+        matches
+            .unwrap_or_default()
+            .iter()
+            .map(|pos| (pos >> 32) as Id)
+            .collect()
+    }
+
+    /// Insert the words of the index matching the word of the candidates with at most `typo` typos,
+    /// or starting with it if it's a prefix.
+    fn insert_word_matches(
+        &self,
+        candidates: &mut WordCandidate,
+        typo: usize,
+        max_terms: Option<usize>,
+    ) {
+        let normalized = candidates.normalized.clone();
+        match (typo, candidates.prefix) {
+            // without typos there is no need to pay for a levenshtein automaton
+            (0, false) => {
+                if let (Some(bitmap), false) =
+                    (self.word_documents(&normalized), max_terms == Some(0))
+                {
+                    candidates.insert_with_maybe_typo(&normalized, bitmap);
+                }
+            }
+            (0, true) => {
+                let prefix = Str::new(&normalized).starts_with();
+                self.insert_matches(candidates, &prefix, max_terms);
+            }
+            (typo, false) => match &self.ngrams {
+                Some(ngrams) => self.insert_ngram_matches(ngrams, candidates, typo, max_terms),
+                None => {
+                    let lev = levenshtein(typo).build_dfa(&normalized);
+                    self.insert_matches(candidates, &lev, max_terms);
+                }
+            },
+            (typo, true) => {
+                let lev = levenshtein(typo).build_prefix_dfa(&normalized);
+                self.insert_matches(candidates, &lev, max_terms);
+            }
+        }
+    }
+
+    /// Insert every word of the fst and of the pending words matched by `lev` in the candidates.
+    /// With `max_terms` only the first [`SCANNED_TERMS_PER_KEPT_TERM`] times `max_terms` matching
+    /// words are read, in the order of the keys, and the best `max_terms` of them are kept.
+    fn insert_matches(
+        &self,
+        candidates: &mut WordCandidate,
+        lev: &impl Automaton,
+        max_terms: Option<usize>,
+    ) {
+        let Some(max_terms) = max_terms else {
+            return self.for_each_match(lev, usize::MAX, |matched, bitmap| {
+                candidates.insert_with_maybe_typo(matched, bitmap)
+            });
+        };
+
+        // the worst word we kept is at the top of the heap, the words are compared by number of typos then frequency
+        let mut best: BinaryHeap<(usize, Reverse<u64>, String)> = BinaryHeap::new();
+        let scan_limit = max_terms.saturating_mul(SCANNED_TERMS_PER_KEPT_TERM);
+        self.for_each_match(lev, scan_limit, |matched, bitmap| {
+            let (distance, frequency) = (candidates.distance(matched), Reverse(bitmap.len()));
+            if best.len() < max_terms {
+                best.push((distance, frequency, matched.to_string()));
+            } else if best.peek().is_some_and(|worst| {
+                (distance, frequency, matched) < (worst.0, worst.1, worst.2.as_str())
+            }) {
+                best.pop();
+                best.push((distance, frequency, matched.to_string()));
+            }
+        });
+        for (_, _, matched) in best.into_sorted_vec() {
+            if let Some(bitmap) = self.word_documents(&matched) {
+                candidates.insert_with_maybe_typo(&matched, bitmap);
+            }
+        }
+    }
+
+    /// Insert the words of the index sharing enough n-grams with the word of the candidates
+    /// to have at most `typo` typos, see [`Index::with_ngrams`]
+    fn insert_ngram_matches(
+        &self,
+        ngrams: &NGrams,
+        candidates: &mut WordCandidate,
+        typo: usize,
+        max_terms: Option<usize>,
+    ) {
+        let pending = self.pending.iter().filter_map(|(word, (bitmap, _))| {
+            ngram::typos_between(&candidates.normalized, word, typo)
+                .map(|typos| (typos, word.as_str(), bitmap))
+        });
+        let mut matches: Vec<(usize, Reverse<u64>, &str, &RoaringBitmap)> = ngrams
+            .matches(&candidates.normalized, typo)
+            .into_iter()
+            .map(|(word, id, typos)| (typos, word, &self.bitmaps[id as usize]))
+            .chain(pending)
+            // the words of the deleted documents stay in the n-grams
+            .filter(|(_, _, bitmap)| !bitmap.is_empty())
+            .map(|(typos, word, bitmap)| (typos, Reverse(bitmap.len()), word, bitmap))
+            .collect();
+        // like with the automatons the best words are the ones with the fewest typos then the most frequent
+        matches.sort_unstable_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+        matches.truncate(max_terms.unwrap_or(usize::MAX));
+        for (typos, _, word, bitmap) in matches {
+            candidates.insert_with_typos(word, bitmap, typos);
+        }
+    }
+
+    /// Returns the documents containing exactly `word`
+    fn word_documents(&self, word: &str) -> Option<&RoaringBitmap> {
+        match self.fst.get(word) {
+            Some(bitmap) => Some(&self.bitmaps[bitmap as usize]),
+            None => self.pending.get(word).map(|(bitmap, _)| bitmap),
+        }
+    }
+
+    /// Call `f` on every word of the fst and of the pending words matched by `lev` along with its documents
+    fn for_each_match(
+        &self,
+        lev: &impl Automaton,
+        limit: usize,
+        mut f: impl FnMut(&str, &RoaringBitmap),
+    ) {
+        let mut stream = self.fst.search(lev).into_stream();
+        let mut matches = 0;
+        while matches < limit {
+            let Some((matched, id)) = stream.next() else {
+                break;
+            };
+            // an index built elsewhere may contain keys that aren't valid utf-8, we skip them
+            // instead of crashing the search
+            if let Ok(matched) = std::str::from_utf8(matched) {
+                f(matched, &self.bitmaps[id as usize]);
+                matches += 1;
+            }
+        }
+        // the words inserted since the last fst build
+        for (matched, (bitmap, _)) in self.pending.iter() {
+            if matches >= limit {
+                break;
+            }
+            if automaton_matches(lev, matched) {
+                f(matched, bitmap);
+                matches += 1;
+            }
+        }
+    }
+
+    /// Suggest a corrected query, every word of the query that isn't contained in the index is
+    /// replaced by the closest word of the index, the most frequent one if there is a tie.
+    /// The number of typos allowed follows the default [`TypoConfig`] and the phrases are kept as is.
+    /// Returns `None` if all the words are in the index or if no correction was found.
+    pub fn suggest(&self, query: &str) -> Option<String> {
+        let mut corrected = false;
+        let parts: Vec<String> = QueryPart::parse(query, &*self.tokenizer)
+            .into_iter()
+            .map(|part| match part {
+                QueryPart::Phrase { original, .. } => format!("\"{original}\""),
+                QueryPart::Negative { original, .. } => original.to_string(),
+                QueryPart::Word {
+                    original,
+                    normalized,
+                } => {
+                    if self.fst.contains_key(&normalized) || self.pending.contains_key(&normalized)
+                    {
+                        return original.to_string();
+                    }
+                    let lev = levenshtein(TypoConfig::default().typos_for(&normalized))
+                        .build_dfa(&normalized);
+                    let mut best: Option<(usize, Reverse<u64>, String)> = None;
+                    self.for_each_match(&lev, usize::MAX, |matched, bitmap| {
+                        // a word that isn't contained in any document anymore
+                        if bitmap.is_empty() {
+                            return;
+                        }
+                        let distance = damerau_levenshtein(&normalized, matched);
+                        let candidate = (distance, Reverse(bitmap.len()), matched.to_string());
+                        if best.as_ref().is_none_or(|best| candidate < *best) {
+                            best = Some(candidate);
+                        }
+                    });
+                    match best {
+                        Some((_, _, word)) => {
+                            corrected = true;
+                            word
+                        }
+                        None => original.to_string(),
+                    }
+                }
+            })
+            .collect();
+
+        corrected.then(|| parts.join(" "))
+    }
+
+    /// Returns the documents containing all the words exactly as they're written
+    fn verbatim_documents(&self, documents: &RoaringBitmap, words: &[&str]) -> RoaringBitmap {
+        documents
+            .iter()
+            .filter(|id| {
+                let tokens: HashSet<&str> = self
+                    .tokenizer
+                    .tokenize(self.text(*id))
+                    .into_iter()
+                    .collect();
+                words.iter().all(|word| tokens.contains(word))
+            })
+            .collect()
+    }
+
+    fn get_candidates(&self, search: &Search) -> Vec<WordCandidate> {
+        self.get_candidates_in(search, Vec::new(), None, None)
+    }
+
+    /// Same as [`Index::get_candidates`] but reuse the allocation of `ret`, and the words of
+    /// the cache that were already matched. `matched` gets the documents of the scope matching
+    /// any word, before the search discards the ones matching too few words or with too many typos.
+    fn get_candidates_in(
+        &self,
+        search: &Search,
+        mut ret: Vec<WordCandidate>,
+        cache: Option<&mut WordCache>,
+        matched: Option<&mut RoaringBitmap>,
+    ) -> Vec<WordCandidate> {
+        ret.clear();
+        let boolean = self.uses_boolean_operators(search);
+        let expr = boolean
+            .then(|| Expr::parse(search.input, &*self.tokenizer))
+            .flatten();
+        let mut words = match boolean {
+            true => expr.as_ref().map(Expr::parts).unwrap_or_default(),
+            false => QueryPart::parse(search.input, &*self.tokenizer),
+        };
+        // the negative words only filter the documents, they're not ranked
+        let excluded = self.excluded_documents(&words);
+        words.retain(|part| !matches!(part, QueryPart::Negative { .. }));
+        // the stop words and the synonyms must be normalized by the tokenizer of the index
+        let stop_words: HashSet<String> = search
+            .stop_words
+            .iter()
+            .map(|word| self.tokenizer.normalize(word))
+            .collect();
+        let synonyms: HashMap<String, &[String]> = search
+            .synonyms
+            .iter()
+            .map(|(word, synonyms)| (self.tokenizer.normalize(word), synonyms.as_slice()))
+            .collect();
+        let word_typos: HashMap<String, usize> = search
+            .word_typos
+            .iter()
+            .map(|(word, typos)| (self.tokenizer.normalize(word), *typos))
+            .collect();
+        // if the query only contains stop words we'd rather search them than return nothing
+        let is_stop_word = |part: &QueryPart| matches!(part, QueryPart::Word { normalized, .. } if stop_words.contains(normalized));
+        if !words.iter().all(is_stop_word) {
+            words.retain(|part| !is_stop_word(part));
+        }
+        ret.reserve(words.len());
+
+        for (index, part) in words.iter().enumerate() {
+            let cached = cache
+                .as_deref()
+                .and_then(|cache| cache.get(part, index, index == words.len() - 1));
+            if let Some(cached) = cached {
+                ret.push(cached.clone());
+                continue;
+            }
+            let (word, normalized) = match part {
+                QueryPart::Word {
+                    original,
+                    normalized,
+                } => (original, normalized),
+                QueryPart::Negative { .. } => unreachable!("the negative words were removed"),
+                QueryPart::Phrase { original, words } => {
+                    let mut candidates =
+                        WordCandidate::new(original.to_string(), words.join(" "), index, 0);
+                    candidates.typos[0] = self.phrase_documents(words);
+                    if search.verbatim {
+                        let raw = self.tokenizer.tokenize(original);
+                        candidates.typos[0] = self.verbatim_documents(&candidates.typos[0], &raw);
+                    }
+                    candidates.terms = words.iter().map(|word| (word.clone(), 0)).collect();
+                    ret.push(candidates);
+                    continue;
+                }
+            };
+            if search.wildcards && Glob::is_pattern(word) {
+                // the pattern replaces both the typo tolerance and the prefix search
+                let mut candidates =
+                    WordCandidate::new(word.to_string(), normalized.to_string(), index, 0);
+                let glob = Glob::new(word, |text| self.tokenizer.normalize(text));
+                self.insert_matches(&mut candidates, &glob, search.max_candidate_terms);
+                ret.push(candidates);
+                continue;
+            }
+
+            let typo = match (search.verbatim, word_typos.get(normalized)) {
+                (true, _) => 0,
+                (false, Some(typos)) => (*typos).min(MAX_TYPOS),
+                (false, None) => search.typo_tolerance.typos_for(normalized),
+            };
+            let mut candidates =
+                WordCandidate::new(word.to_string(), normalized.to_string(), index, typo);
+
+            if search.verbatim {
+                // the fst only contains normalized words, it gives us the documents to check
+                let documents = self.phrase_documents(std::slice::from_ref(normalized));
+                candidates.typos[0] = self.verbatim_documents(&documents, &[word]);
+                candidates.terms.push((normalized.to_string(), 0));
+                ret.push(candidates);
+                continue;
+            }
+            candidates.prefix = match search.prefix_mode {
+                PrefixMode::Last => index == words.len() - 1,
+                PrefixMode::All => true,
+                PrefixMode::None => false,
+            };
+            candidates.metric = search.distance_metric;
+
+            // if we're at the last word we should also run a prefix search
+            self.insert_word_matches(&mut candidates, typo, search.max_candidate_terms);
+            // while typing, an earlier word matching nothing is most probably a prefix as well,
+            // otherwise it would simply be dropped by the word ranking rule
+            if search.prefix_mode == PrefixMode::Last
+                && !candidates.prefix
+                && candidates.typos.iter().all(|typo| typo.is_empty())
+            {
+                candidates.prefix = true;
+                self.insert_word_matches(&mut candidates, typo, search.max_candidate_terms);
+            }
+
+            if search.infix {
+                // the words containing the query word rank after all its typos
+                let mut infix =
+                    WordCandidate::new(word.to_string(), normalized.to_string(), index, 0);
+                self.insert_matches(
+                    &mut infix,
+                    &Glob::infix(normalized),
+                    search.max_candidate_terms,
+                );
+                let typo = candidates.typos.len();
+                candidates.typos.push(infix.typos.swap_remove(0));
+                for (term, _) in infix.terms {
+                    if !candidates.terms.iter().any(|(matched, _)| *matched == term) {
+                        candidates.terms.push((term, typo));
+                    }
+                }
+            }
+
+            // the synonyms are searched without typos, and as a phrase if they contain multiple words
+            for synonym in synonyms.get(normalized).copied().into_iter().flatten() {
+                let words: Vec<String> = self
+                    .tokenizer
+                    .tokenize(synonym)
+                    .into_iter()
+                    .map(|word| self.tokenizer.normalize(word))
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                candidates.typos[0] |= self.phrase_documents(&words);
+                candidates
+                    .terms
+                    .extend(words.into_iter().map(|word| (word, 0)));
+            }
+
+            ret.push(candidates);
+        }
+
+        // the cache keeps the words before they're filtered, the filters depend on all the words
+        if let Some(cache) = cache {
+            cache.store(&words, &ret);
+        }
+
+        // the documents out of the scope are dropped first, everything else has less to go through
+        if let Some(scope) = &search.scope {
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo &= scope;
+                }
+            }
+        }
+        if let Some(matched) = matched {
+            *matched = ret
+                .iter()
+                .flat_map(|candidate| candidate.typos.iter())
+                .union();
+        }
+
+        if !excluded.is_empty() {
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo -= &excluded;
+                }
+            }
+        }
+
+        // the boolean operators decide which documents match, instead of the number of words
+        if let Some(allowed) = expr.and_then(|expr| self.boolean_documents(&expr, &ret)) {
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo &= &allowed;
+                }
+            }
+        }
+
+        // the documents missing too many words can't be returned
+        let min_matching_words = match search.match_mode {
+            MatchMode::All => ret.len(),
+            MatchMode::Any => search.min_matching_words.min(ret.len()),
+        };
+        if min_matching_words > 1 && !boolean {
+            // quorum[i] contains the documents matching more than i words
+            let mut quorum = vec![RoaringBitmap::new(); min_matching_words];
+            for candidate in ret.iter() {
+                let documents = candidate.typos.as_slice().union();
+                for i in (1..min_matching_words).rev() {
+                    let matching = &quorum[i - 1] & &documents;
+                    quorum[i] |= matching;
+                }
+                quorum[0] |= documents;
+            }
+            let allowed = quorum.pop().unwrap();
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo &= &allowed;
+                }
+            }
+        }
+
+        // the documents with too many typos are discarded
+        if let Some(max_typos) = search.max_typos_per_result {
+            let allowed = Self::documents_within_typos(&ret, max_typos);
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo &= &allowed;
+                }
+            }
+        }
+
+        // the filtered out documents are removed before any ranking rule sees them
+        if let Some(Filter(filter)) = &search.filter {
+            let candidates: RoaringBitmap = ret
+                .iter()
+                .flat_map(|candidate| candidate.typos.iter())
+                .union()
+                .into_iter()
+                .filter(|id| filter(*id))
+                .collect();
+            for candidate in ret.iter_mut() {
+                for typo in candidate.typos.iter_mut() {
+                    *typo &= &candidates;
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Returns the documents matching the candidates with at most `max_typos` typos in total,
+    /// the words they don't contain don't count.
+    fn documents_within_typos(candidates: &[WordCandidate], max_typos: usize) -> RoaringBitmap {
+        // no document can have more typos than all the words together
+        let max_typos = max_typos.min(
+            candidates
+                .iter()
+                .map(|candidate| candidate.typos.len() - 1)
+                .sum(),
+        );
+        // typos[i] contains the documents with exactly i typos on the words we went through
+        let mut typos = vec![RoaringBitmap::new(); max_typos + 1];
+        typos[0] = candidates
+            .iter()
+            .flat_map(|candidate| candidate.typos.iter())
+            .union();
+
+        for candidate in candidates {
+            let matched = candidate.typos.as_slice().union();
+            let mut next: Vec<RoaringBitmap> = typos.iter().map(|docs| docs - &matched).collect();
+            // a document is only counted in the bucket with the fewest typos
+            let mut seen = RoaringBitmap::new();
+            for (distance, documents) in candidate.typos.iter().enumerate() {
+                let documents = documents - &seen;
+                for total in distance..=max_typos {
+                    next[total] |= &typos[total - distance] & &documents;
+                }
+                seen |= documents;
+            }
+            typos = next;
+        }
+        typos.union()
+    }
+}
+
+impl<T: ?Sized + ToOwned + AsRef<str>> IndexView for Index<'_, T> {
+    fn text(&self, id: Id) -> &str {
+        self.documents[id].as_ref()
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        &*self.tokenizer
+    }
+
+    fn word_positions(&self, word: &str) -> Option<&RoaringTreemap> {
+        match self.fst.get(word) {
+            Some(bitmap) => Some(&self.positions[bitmap as usize]),
+            None => self.pending.get(word).map(|(_, positions)| positions),
+        }
+    }
+
+    fn document_length(&self, id: Id) -> u32 {
+        self.lengths[id as usize]
+    }
+
+    fn word_documents(&self, word: &str) -> Option<&RoaringBitmap> {
+        Index::word_documents(self, word)
+    }
+
+    fn document_boost(&self, id: Id) -> f32 {
+        self.boosts.get(id as usize).copied().unwrap_or(1.0)
+    }
+}
+
+/// The words of the fst and the pending words merged in order, see [`Index::terms`]
+struct Terms<'i> {
+    stream: fst::map::Stream<'i>,
+    pending: std::iter::Peekable<
+        std::collections::btree_map::Iter<'i, String, (RoaringBitmap, RoaringTreemap)>,
+    >,
+    bitmaps: &'i [RoaringBitmap],
+    // the next word of the fst, if it comes after the next pending word
+    next_word: Option<(String, u64)>,
+}
+
+impl Iterator for Terms<'_> {
+    type Item = (String, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Terms {
+            stream,
+            pending,
+            bitmaps,
+            next_word,
+        } = self;
+        // the keys that aren't valid utf-8 are skipped, see `Index::for_each_match`
+        while next_word.is_none() {
+            let Some((word, bitmap)) = stream.next() else {
+                break;
+            };
+            if let Ok(word) = std::str::from_utf8(word) {
+                *next_word = Some((word.to_string(), bitmaps[bitmap as usize].len()));
+            }
+        }
+
+        // the fst and the pending words are disjoint
+        match pending
+            .next_if(|(pending, _)| next_word.as_ref().is_none_or(|(word, _)| *pending < word))
+        {
+            Some((word, (bitmap, _))) => Some((word.clone(), bitmap.len())),
+            None => next_word.take(),
+        }
+    }
+}
+
+/// Create a fresh implementation of the ranking rule for a search
+fn build_ranking_rule(
+    ranking_rule: &RankingRule,
+    search: &Search,
+    candidates: &mut [WordCandidate],
+) -> Box<dyn RankingRuleImpl> {
+    match ranking_rule {
+        RankingRule::Word => Box::new(Word::new(candidates)),
+        RankingRule::Typo => Box::new(Typo::new(candidates)),
+        RankingRule::Exact => Box::new(Exact::new()),
+        RankingRule::Proximity => Box::new(Proximity::new()),
+        RankingRule::Field => Box::new(Field::new(search.field_weights.clone())),
+        RankingRule::Frequency => Box::new(Frequency::new()),
+        RankingRule::Position => Box::new(Position::new()),
+        RankingRule::Length => Box::new(Length::new()),
+        RankingRule::WholeWord => Box::new(WholeWord::new()),
+        RankingRule::Verbatim => Box::new(Verbatim::new()),
+        RankingRule::Boost => Box::new(Boost::new()),
+        RankingRule::Weighted(rules) => Box::new(Weighted::new(
+            rules
+                .iter()
+                .map(|(rule, weight)| (build_ranking_rule(rule, search, candidates), *weight))
+                .collect(),
+        )),
+        RankingRule::Custom(builder) => builder(candidates),
+    }
+}
+
+/// The buckets of documents in the ranked order, every call to `next` runs the
+/// ranking rules until they return a new bucket.
+struct RankedBuckets<'i> {
+    index: &'i dyn IndexView,
+    candidates: Vec<WordCandidate>,
+    ranking_rules: Vec<Box<dyn RankingRuleImpl>>,
+    current_ranking_rule: usize,
+    // the documents already returned, to check that none of them is returned twice
+    seen: RoaringBitmap,
+    done: bool,
+    // returned as is when there is nothing to sort, see `Search::with_placeholder`
+    // and `Search::with_ranking_rules`
+    placeholder: Option<RoaringBitmap>,
+    // the number of calls to the ranking rules since the last new document, see `STALLED_STEPS_PER_WORD`
+    stalled_steps: usize,
+    max_stalled_steps: usize,
+    // the number of documents the search still needs, its offset and its limit, once they're
+    // found nothing else is sorted
+    remaining: usize,
+    // the number of buckets the search can still return, see `Search::with_max_buckets`
+    remaining_buckets: usize,
+    // how the documents of a bucket are returned, to know which ones to keep when it's cut
+    tiebreak: Option<Tiebreak>,
+    // returns false for the documents whose key was already returned, see `Search::with_dedup`
+    dedup: Option<Box<dyn FnMut(Id) -> bool>>,
+    trace: Option<Trace>,
+}
+
+/// The ranking rules can take a few steps without returning any new document, every word goes
+/// through all the typos and the buckets of the other rules for example. Past this number of steps
+/// for every word and ranking rule, a ranking rule is considered stuck, like a custom one returning
+/// `Continue` forever, and the search returns the documents it already found instead of hanging.
+const STALLED_STEPS_PER_WORD: usize = 1024;
+
+/// With [`Search::with_max_candidate_terms`], the number of matching words read from the index
+/// for every word kept. The best words are picked among them, reading all the words matched by a
+/// short prefix would cost as much as not limiting them at all.
+const SCANNED_TERMS_PER_KEPT_TERM: usize = 16;
+
+/// The working buffers of a search, see [`Index::search_many`]
+#[derive(Default)]
+struct SearchContext {
+    candidates: Vec<WordCandidate>,
+    ranking_rules: Vec<Box<dyn RankingRuleImpl>>,
+    seen: RoaringBitmap,
+}
+
+impl RankedBuckets<'_> {
+    /// Get back the buffers once we're done with the search
+    fn into_context(self) -> SearchContext {
+        let RankedBuckets {
+            mut candidates,
+            mut ranking_rules,
+            mut seen,
+            ..
+        } = self;
+        candidates.clear();
+        ranking_rules.clear();
+        seen.clear();
+        SearchContext {
+            candidates,
+            ranking_rules,
+            seen,
+        }
+    }
+
+    fn trace(&self, event: TraceEvent) {
+        if let Some(Trace(trace)) = &self.trace {
+            trace(event);
+        }
+    }
+
+    /// Remove the documents of the bucket from all the caches and returns it if it contains new documents.
+    /// A bucket only keeps the documents the words still match, and the documents are removed from
+    /// the words once returned, thus a document can't be returned twice even if a ranking rule
+    /// forgot to cleanup its caches.
+    fn emit(&mut self, mut bucket: RoaringBitmap) -> Option<RoaringBitmap> {
+        let unranked = self
+            .candidates
+            .iter()
+            .map(|candidate| candidate.typos.as_slice().union())
+            .union();
+        Index::cleanup(&bucket, &mut self.candidates);
+        self.ranking_rules
+            .iter_mut()
+            .for_each(|rr| rr.cleanup(&bucket));
+        bucket &= unranked;
+        // the ranking rules putting documents back in the words, like the dropped words, clean them up
+        debug_assert!(
+            bucket.is_disjoint(&self.seen),
+            "a document was returned twice"
+        );
+        self.seen |= &bucket;
+        let bucket = self.dedup(bucket);
+        let bucket = self.truncate(bucket);
+        (!bucket.is_empty()).then_some(bucket)
+    }
+
+    /// Drop the documents whose key was already returned, the documents of the bucket are
+    /// taken in the order they're returned until the search has enough of them.
+    /// With a tiebreak only the best documents are sorted, like in [`RankedBuckets::truncate`],
+    /// and the next ones only if some of them were duplicates.
+    fn dedup(&mut self, bucket: RoaringBitmap) -> RoaringBitmap {
+        let Some(dedup) = &mut self.dedup else {
+            return bucket;
+        };
+        let wanted = self.remaining;
+        let Some(Tiebreak(tiebreak)) = &self.tiebreak else {
+            return bucket
+                .into_iter()
+                .filter(|id| dedup(*id))
+                .take(wanted)
+                .collect();
+        };
+
+        // the ids break the ties like the stable sort of `bucket_ids`
+        let compare = |a: &Id, b: &Id| tiebreak(*a, *b).then(a.cmp(b));
+        let mut ids: Vec<Id> = bucket.into_iter().collect();
+        let mut rest = ids.as_mut_slice();
+        let mut kept = RoaringBitmap::new();
+        while (kept.len() as usize) < wanted && !rest.is_empty() {
+            let missing = (wanted - kept.len() as usize).min(rest.len());
+            if missing < rest.len() {
+                rest.select_nth_unstable_by(missing, compare);
+            }
+            let (best, next) = rest.split_at_mut(missing);
+            best.sort_unstable_by(compare);
+            kept.extend(best.iter().copied().filter(|id| dedup(*id)));
+            rest = next;
+        }
+        kept
+    }
+
+    /// Only keep the documents of the bucket that will be returned if it's bigger than what's needed.
+    /// With a tiebreak they're selected like a top-k instead of sorting the whole bucket.
+    fn truncate(&mut self, mut bucket: RoaringBitmap) -> RoaringBitmap {
+        let wanted = self.remaining;
+        if bucket.len() > wanted as u64 {
+            bucket = match &self.tiebreak {
+                Some(Tiebreak(tiebreak)) => {
+                    let mut ids: Vec<Id> = bucket.into_iter().collect();
+                    // the ids break the ties like the stable sort of `bucket_ids`
+                    ids.select_nth_unstable_by(wanted, |a, b| tiebreak(*a, *b).then(a.cmp(b)));
+                    ids.truncate(wanted);
+                    ids.into_iter().collect()
+                }
+                None => {
+                    // without a tiebreak the documents are returned by ascending id
+                    if let Some(end) = bucket.select(wanted as u32) {
+                        bucket.remove_range(end..);
+                    }
+                    bucket
+                }
+            };
+        }
+        self.remaining -= bucket.len() as usize;
+        if !bucket.is_empty() {
+            self.remaining_buckets -= 1;
+        }
+        if self.remaining == 0 || self.remaining_buckets == 0 {
+            self.done = true;
+        }
+        bucket
+    }
+}
+
+impl Iterator for RankedBuckets<'_> {
+    type Item = RoaringBitmap;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(placeholder) = self.placeholder.take() {
+            let placeholder = self.dedup(placeholder);
+            let placeholder = self.truncate(placeholder);
+            return (!placeholder.is_empty()).then_some(placeholder);
+        }
+        while !self.done {
+            self.stalled_steps += 1;
+            if self.stalled_steps > self.max_stalled_steps {
+                self.done = true;
+                self.trace(TraceEvent::Stalled);
+                break;
+            }
+
+            // split the list so we can borrow the previous ranking rule while mutating the current one
+            let (previous, current) = self.ranking_rules.split_at_mut(self.current_ranking_rule);
+            let is_last = current.len() == 1;
+            let ranking_rule = &mut current[0];
+            let next = ranking_rule.next(
+                previous.last().map(|rr| &**rr),
+                &mut self.candidates,
+                self.index,
+            );
+
+            let (index, name) = (self.current_ranking_rule, ranking_rule.name());
+
+            let bucket = match next {
+                // We want to advance
+                ControlFlow::Continue(()) => {
+                    if is_last {
+                        // there is no ranking rule to continue, get the bucket of the current one and call it again
+                        ranking_rule.current_results(&self.candidates)
+                    } else {
+                        // we advance and do nothing
+                        self.current_ranking_rule += 1;
+                        self.trace(TraceEvent::Continue {
+                            ranking_rule: index,
+                            name,
+                        });
+                        continue;
+                    }
+                }
+                // We want to get back one ranking rule behind
+                ControlFlow::Break(bucket) if bucket.is_empty() => {
+                    self.trace(TraceEvent::Exhausted {
+                        ranking_rule: index,
+                        name,
+                    });
+                    // if we're at the first ranking rule and there is nothing left to sort, exit
+                    if self.current_ranking_rule == 0 {
+                        self.done = true;
+                    } else {
+                        self.current_ranking_rule -= 1;
+                    }
+                    continue;
+                }
+                // We want to push that bucket and continue our life with the next ranking rule if there is one
+                ControlFlow::Break(bucket) => bucket,
+            };
+
+            let documents = bucket.len();
+            let bucket = self.emit(bucket);
+            self.trace(TraceEvent::Bucket {
+                ranking_rule: index,
+                name,
+                documents,
+                returned: bucket.as_ref().unwrap_or(&RoaringBitmap::new()),
+            });
+            if let Some(bucket) = bucket {
+                self.stalled_steps = 0;
+                return Some(bucket);
+            }
+        }
+        None
+    }
+}
+
+/// A step of the ranking rules, see [`Search::with_trace`]. The ranking rules are identified by
+/// their position in [`Search::with_ranking_rules`] and their [`RankingRuleImpl::name`].
+#[derive(Debug, Clone, Copy)]
+pub enum TraceEvent<'t> {
+    /// The ranking rule split its documents, the next ranking rule sorts its current bucket
+    Continue {
+        ranking_rule: usize,
+        name: &'static str,
+    },
+    /// The ranking rule returned a bucket, the last ranking rule returns its current bucket when it continues
+    Bucket {
+        ranking_rule: usize,
+        name: &'static str,
+        /// The number of documents of the bucket
+        documents: u64,
+        /// The documents of the bucket added to the results, the others were already returned
+        /// or are past the limit
+        returned: &'t RoaringBitmap,
+    },
+    /// The ranking rule has nothing left to sort, the search goes back to the previous one
+    /// or ends if it was the first one
+    Exhausted {
+        ranking_rule: usize,
+        name: &'static str,
+    },
+    /// The ranking rules didn't return any new document for too many steps, the search gave up
+    Stalled,
+}
+
+/// A document returned by [`Index::search_highlighted`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightedDoc<'a> {
+    pub id: Id,
+    pub document: &'a str,
+    /// The sorted byte ranges of the document that matched the query
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// A document returned by [`Index::search_explained`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainedDoc<'a, T: ?Sized = str> {
+    pub id: Id,
+    pub document: &'a T,
+    /// The words of the index the document matched, in the order of the query
+    pub matches: Vec<TermMatch>,
+}
+
+/// A word of the index that matched a word of the query, see [`Index::search_explained`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermMatch {
+    /// The word of the query as it was written, or the whole phrase
+    pub query_word: String,
+    /// The normalized word of the index
+    pub term: String,
+    /// The number of typos between the two, the extra letters of a prefix are not typos
+    pub typos: usize,
+}
+
+/// The size of an index, see [`Index::stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexStats {
+    /// The number of documents that weren't deleted
+    pub documents: usize,
+    /// The number of distinct words, the words of the deleted documents are kept until [`Index::compact`]
+    pub terms: usize,
+    /// The size of the fst in bytes
+    pub fst_bytes: usize,
+    /// The size of the documents of every word in bytes
+    pub bitmaps_bytes: usize,
+    /// The size of the positions of every word in bytes
+    pub positions_bytes: usize,
+}
+
+/// The documents matched by a word of the query, see [`Index::candidate_stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordCandidateStats {
+    /// The word of the query as it was written, or the whole phrase
+    pub original: String,
+    /// The normalized word that was searched in the index
+    pub normalized: String,
+    /// If the word was searched as the prefix of the words of the index
+    pub prefix: bool,
+    /// The n-th value is the number of documents matching the word with n typos,
+    /// there is one value more than the number of typos allowed on the word
+    pub documents: Vec<u64>,
+}
+
+/// A part of the query once parsed and normalized
+#[derive(Clone)]
+enum QueryPart<'a> {
+    Word {
+        original: &'a str,
+        normalized: String,
+    },
+    /// Words written between double quotes that must be matched contiguously and without typos
+    Phrase {
+        original: &'a str,
+        words: Vec<String>,
+    },
+    /// A word preceded by a `-`, the documents containing it exactly are excluded
+    Negative {
+        original: &'a str,
+        normalized: String,
+    },
+}
+
+impl<'a> QueryPart<'a> {
+    /// The part as it was written in the query
+    fn original(&self) -> &'a str {
+        match self {
+            QueryPart::Word { original, .. }
+            | QueryPart::Phrase { original, .. }
+            | QueryPart::Negative { original, .. } => original,
+        }
+    }
+
+    /// Split the query in words and phrases, the words that are empty once normalized are dropped.
+    /// An unclosed quote spans until the end of the query.
+    fn parse(input: &'a str, tokenizer: &dyn Tokenizer) -> Vec<Self> {
+        let mut parts = Vec::new();
+        if !tokenizer.query_syntax() {
+            parts.extend(
+                tokenizer
+                    .tokenize(input)
+                    .into_iter()
+                    .filter_map(|original| {
+                        let normalized = tokenizer.normalize(original);
+                        (!normalized.is_empty()).then_some(QueryPart::Word {
+                            original,
+                            normalized,
+                        })
+                    }),
+            );
+            return parts;
+        }
+        // every odd segment is between quotes
+        for (i, segment) in input.split('"').enumerate() {
+            if i % 2 == 1 {
+                let words: Vec<String> = tokenizer
+                    .tokenize(segment)
+                    .into_iter()
+                    .map(|word| tokenizer.normalize(word))
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                if !words.is_empty() {
+                    parts.push(QueryPart::Phrase {
+                        original: segment.trim(),
+                        words,
+                    });
+                }
+            } else {
+                parts.extend(
+                    tokenizer
+                        .tokenize(segment)
+                        .into_iter()
+                        .filter_map(|original| {
+                            if let Some(negative) = original.strip_prefix('-') {
+                                let normalized = tokenizer.normalize(negative);
+                                return (!normalized.is_empty()).then_some(QueryPart::Negative {
+                                    original,
+                                    normalized,
+                                });
+                            }
+                            let normalized = tokenizer.normalize(original);
+                            (!normalized.is_empty()).then_some(QueryPart::Word {
+                                original,
+                                normalized,
+                            })
+                        }),
+                );
+            }
+        }
+        parts
+    }
+}
+
+/// A word of the query along with all the documents it matched, grouped by number of typos.
+/// The ranking rules receive the list of candidates of the query.
+#[derive(Debug, Clone)]
+pub struct WordCandidate {
+    // the original string
+    original: String,
+    // normalized string
+    normalized: String,
+    // its index in the phrase
+    index: usize,
+    // the number of documuents its contained in
+    typos: Vec<RoaringBitmap>,
+    // the number of typos allowed, the infix matches are stored in an extra bucket past them
+    max_typos: usize,
+    // the normalized words of the index it matched and their number of typos
+    terms: Vec<(String, usize)>,
+    // if the word was searched as a prefix
+    prefix: bool,
+    // how the typos of the terms are counted
+    metric: DistanceMetric,
+}
+
+impl WordCandidate {
+    /// `max_typos` is the number of typos allowed on this word, see [`TypoConfig`]
+    pub(crate) fn new(
+        original: String,
+        normalized: String,
+        index: usize,
+        max_typos: usize,
+    ) -> Self {
+        Self {
+            original,
+            normalized,
+            index,
+            typos: vec![RoaringBitmap::new(); max_typos + 1],
+            max_typos,
+            terms: Vec::new(),
+            prefix: false,
+            metric: DistanceMetric::default(),
+        }
+    }
+
+    // Since the fst::Automaton doesn't tells us which automaton matched and with how many typos or prefixes
+    // we need to recompute the stuff ourselves and insert our shit in the right cell
+    pub(crate) fn insert_with_maybe_typo(&mut self, other: &str, bitmap: &RoaringBitmap) {
+        let distance = self.distance(other);
+        self.insert_with_typos(other, bitmap, distance);
+    }
+
+    /// Insert a word of the index whose number of typos is already known, capped by the typos allowed
+    pub(crate) fn insert_with_typos(&mut self, other: &str, bitmap: &RoaringBitmap, typos: usize) {
+        let typos = typos.min(self.typos.len() - 1);
+        self.typos[typos] |= bitmap;
+        self.terms.push((other.to_string(), typos));
+    }
+
+    /// The number of typos between the word of the query and a word of the index, capped by the typos allowed
+    fn distance(&self, other: &str) -> usize {
+        // distance shouldn't be able to go over the typos allowed but we don't want any crash so let's ensure that
+        self.distance_with(other, self.prefix)
+            .min(self.typos.len() - 1)
+    }
+
+    /// Returns true if the whole word of the index, extra letters included, is within the typos allowed
+    pub(crate) fn matches_whole_word(&self, other: &str) -> bool {
+        self.distance_with(other, false) <= self.max_typos
+    }
+
+    /// The number of typos between the word of the query and a word of the index, following the metric
+    fn distance_with(&self, other: &str, prefix: bool) -> usize {
+        match (self.metric, prefix) {
+            // if we did a prefix query we shouldn't count the extra letters as typo, but a word
+            // matched with deleted letters is compared with a prefix shorter than the query
+            (DistanceMetric::DamerauLevenshtein, true) => {
+                damerau_levenshtein_prefix(&self.normalized, other)
+            }
+            (DistanceMetric::DamerauLevenshtein, false) => {
+                damerau_levenshtein(&self.normalized, other)
+            }
+            (DistanceMetric::JaroWinkler, prefix) => {
+                let len = self.normalized.chars().count();
+                let other = match other.char_indices().nth(len) {
+                    Some((end, _)) if prefix => &other[..end],
+                    _ => other,
+                };
+                // the dissimilarity is spread over the letters of the query word
+                let similarity = jaro_winkler(&self.normalized, other);
+                ((1.0 - similarity) * len as f64).round() as usize
+            }
+        }
+    }
+
+    /// The word as it was written in the query
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// The normalized word that was searched in the index
+    pub fn normalized(&self) -> &str {
+        &self.normalized
+    }
+
+    /// The position of the word in the query
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The documents matching this word, the n-th bitmap contains
+    /// the documents matching it with n typos.
+    pub fn typos(&self) -> &[RoaringBitmap] {
+        &self.typos
+    }
+
+    /// The normalized words of the index this word matched along with their number of typos
+    pub fn terms(&self) -> &[(String, usize)] {
+        &self.terms
+    }
+
+    /// If the word was searched as the prefix of the words of the index
+    pub fn prefix(&self) -> bool {
+        self.prefix
+    }
+
+    /// Returns the smallest number of typos this word was matched with in the document,
+    /// or `None` if the document doesn't contain the word.
+    pub fn typos_for(&self, id: Id) -> Option<usize> {
+        self.typos.iter().position(|bitmap| bitmap.contains(id))
+    }
+}
+
+/// The ranking rules of a new [`Search`]
+const DEFAULT_RANKING_RULES: [RankingRule; 3] =
+    [RankingRule::Word, RankingRule::Typo, RankingRule::Exact];
+
+/// A search request, customized with the chainable `with_*` methods and ran with [`Index::search`].
+///
+/// ```
+/// use zearch::{RankingRule, Search};
+///
+/// let mut search = Search::new("kefir");
+/// search
+///     .with_limit(20)
+///     .with_ranking_rules(vec![RankingRule::Word, RankingRule::Typo]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Search<'a> {
+    input: &'a str,
+    limit: usize,
+    offset: usize,
+    ranking_rules: Vec<RankingRule>,
+    typo_tolerance: TypoConfig,
+    field_weights: Vec<u32>,
+    // normalized
+    stop_words: HashSet<String>,
+    // the keys are normalized
+    synonyms: HashMap<String, Vec<String>>,
+    // the keys are normalized
+    word_typos: HashMap<String, usize>,
+    verbatim: bool,
+    wildcards: bool,
+    boolean_operators: bool,
+    infix: bool,
+    filter: Option<Filter>,
+    scope: Option<RoaringBitmap>,
+    placeholder: bool,
+    exhaustive: bool,
+    max_buckets: usize,
+    match_mode: MatchMode,
+    prefix_mode: PrefixMode,
+    distance_metric: DistanceMetric,
+    max_candidate_terms: Option<usize>,
+    min_matching_words: usize,
+    max_typos_per_result: Option<usize>,
+    tiebreak: Option<Tiebreak>,
+    dedup: Option<Dedup>,
+    trace: Option<Trace>,
+}
+
+/// Whether the documents must contain all the words of the query, see [`Search::with_match_mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// The documents must contain every word of the query
+    All,
+    /// The documents containing the most words are returned first,
+    /// followed by the ones missing some words
+    #[default]
+    Any,
+}
+
+/// Which words of the query also match the words of the index they start, see [`Search::with_prefix`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrefixMode {
+    /// Only the last word, it's probably still being typed. An earlier word matching
+    /// nothing is searched as a prefix as well.
+    #[default]
+    Last,
+    /// Every word of the query
+    All,
+    /// No word, the query has been submitted and "kef" only matches "kef" and its typos
+    None,
+}
+
+/// How the typos of a word matched by the fst are counted, see [`Search::with_distance_metric`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// Every insertion, deletion, substitution or transposition of letters is a typo
+    #[default]
+    DamerauLevenshtein,
+    /// The dissimilarity of the Jaro-Winkler similarity times the number of letters of the
+    /// query word, rounded. The letters in common at the start of the words count more, a
+    /// short word completed or edited at its end can have no typo at all.
+    JaroWinkler,
+}
+
+/// The predicate of [`Search::with_filter`]
+#[derive(Clone)]
+struct Filter(Arc<dyn Fn(Id) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Filter")
+    }
+}
+
+/// The comparator of [`Search::with_tiebreak`]
+#[derive(Clone)]
+struct Tiebreak(Arc<dyn Fn(Id, Id) -> Ordering + Send + Sync>);
+
+impl std::fmt::Debug for Tiebreak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tiebreak")
+    }
+}
+
+/// The key of [`Search::with_dedup`], it creates a predicate for every search which returns true
+/// the first time a key is seen
+#[derive(Clone)]
+struct Dedup(Arc<dyn Fn() -> Box<dyn FnMut(Id) -> bool> + Send + Sync>);
+
+impl std::fmt::Debug for Dedup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dedup")
+    }
+}
+
+/// The callback of [`Search::with_trace`]
+#[derive(Clone)]
+struct Trace(Arc<dyn Fn(TraceEvent) + Send + Sync>);
+
+impl std::fmt::Debug for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Trace")
+    }
+}
+
+/// The maximum number of typos we can build levenshtein automatons for
+pub const MAX_TYPOS: usize = 4;
+
+/// Controls how many typos are allowed on a word of the query depending on its length.
+/// By default 1 typo is allowed every 3 letters with a maximum of 3 typos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypoConfig {
+    /// The minimum length of a word before allowing the first typo
+    pub min_word_len: usize,
+    /// The number of letters needed for every additional typo
+    pub len_per_typo: usize,
+    /// The maximum number of typos, can't go over [`MAX_TYPOS`]. Allowing 4 typos
+    /// makes the first search take a few seconds to build the levenshtein automatons.
+    pub max_typos: usize,
+}
+
+impl Default for TypoConfig {
+    fn default() -> Self {
+        Self {
+            min_word_len: 3,
+            len_per_typo: 3,
+            max_typos: 3,
+        }
+    }
+}
+
+impl TypoConfig {
+    /// Don't allow any typo
+    pub fn disabled() -> Self {
+        Self {
+            max_typos: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of typos allowed for this normalized word
+    pub(crate) fn typos_for(&self, word: &str) -> usize {
+        let len = word.chars().count();
+        if len < self.min_word_len {
+            return 0;
+        }
+        let typos = 1 + (len - self.min_word_len) / self.len_per_typo.max(1);
+        typos.min(self.max_typos).min(MAX_TYPOS)
+    }
+}
+
+impl<'a> Search<'a> {
+    /// Create a new search requests from an input. The words written between double quotes are
+    /// searched as a phrase, and a word starting with a `-` excludes the documents containing it,
+    /// without typos nor prefix. A query made only of negative words is a placeholder search
+    /// that returns all the other documents, see [`Search::with_placeholder`].
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            limit: 10,
+            offset: 0,
+            ranking_rules: DEFAULT_RANKING_RULES.to_vec(),
+            typo_tolerance: TypoConfig::default(),
+            field_weights: Vec::new(),
+            stop_words: HashSet::new(),
+            synonyms: HashMap::new(),
+            word_typos: HashMap::new(),
+            verbatim: false,
+            wildcards: false,
+            boolean_operators: false,
+            infix: false,
+            filter: None,
+            scope: None,
+            placeholder: true,
+            exhaustive: false,
+            max_buckets: usize::MAX,
+            match_mode: MatchMode::default(),
+            prefix_mode: PrefixMode::default(),
+            distance_metric: DistanceMetric::default(),
+            max_candidate_terms: None,
+            min_matching_words: 1,
+            max_typos_per_result: None,
+            tiebreak: None,
+            dedup: None,
+            trace: None,
+        }
+    }
+
+    /// Customize the number of results you want to get back. The ranking rules stop sorting
+    /// the documents as soon as the offset and the limit are reached, thus a small limit
+    /// saves the work on the documents that wouldn't be returned.
+    pub fn with_limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Stop the search once it returned `max_buckets` buckets, the documents of the next
+    /// ones are omitted even if the limit isn't reached. Whichever of the limit and the number
+    /// of buckets is reached first stops the search, and the buckets skipped by the offset count.
+    /// A bucket is a group of documents the ranking rules can't tell apart, thus it bounds
+    /// the work of the ambiguous queries splitting their results in many small buckets.
+    /// [`Index::count`] doesn't run the ranking rules and still counts the omitted documents.
+    pub fn with_max_buckets(&mut self, max_buckets: usize) -> &mut Self {
+        self.max_buckets = max_buckets;
+        self
+    }
+
+    /// Rank and return all the documents matching the query, the limit is ignored but the offset
+    /// still applies. Every ranking rule runs on every document, to evaluate the whole ranking
+    /// of a query for example, it's as slow as the number of matching documents.
+    pub fn with_exhaustive(&mut self, exhaustive: bool) -> &mut Self {
+        self.exhaustive = exhaustive;
+        self
+    }
+
+    /// The number of results to return, there is no limit when the search is exhaustive
+    fn limit(&self) -> usize {
+        match self.exhaustive {
+            true => usize::MAX,
+            false => self.limit,
+        }
+    }
+
+    /// Skip the `offset` first results, combined with the limit it lets you paginate the results
+    pub fn with_offset(&mut self, offset: usize) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Customize the ranking rules applied, in order, to sort the results.
+    /// Defaults to `[Word, Typo, Exact]`, with `Frequency` before `Exact` when the query is a single word.
+    ///
+    /// Any order works and a ranking rule can appear more than once. Without any ranking rule
+    /// the documents containing every word of the query are returned as a single bucket,
+    /// in the order of their ids.
+    pub fn with_ranking_rules(&mut self, ranking_rules: Vec<RankingRule>) -> &mut Self {
+        self.ranking_rules = ranking_rules;
+        self
+    }
+
+    /// Customize the weight of every field of the documents, used by the [`RankingRule::Field`]
+    /// ranking rule. The n-th weight is the weight of the n-th field, the fields without weight
+    /// have a weight of 1. Higher is better. Past the 256th field the fields are merged in the
+    /// last one, and only the 256th weight applies.
+    pub fn with_field_weights(&mut self, field_weights: Vec<u32>) -> &mut Self {
+        self.field_weights = field_weights;
+        self
+    }
+
+    /// Ignore these words in the query, unless the query only contains stop words.
+    /// The words between quotes are never ignored. To also drop them from the index
+    /// build it with a [`StopWordsTokenizer`].
+    pub fn with_stop_words(&mut self, stop_words: HashSet<String>) -> &mut Self {
+        self.stop_words = stop_words;
+        self
+    }
+
+    /// Every word of the query that is a key of the map also matches the documents containing
+    /// any of its synonyms. A synonym made of multiple words must appear as a phrase in the documents.
+    /// The synonyms are not symmetric, `tv -> television` doesn't make `television` match `tv`.
+    pub fn with_synonyms(&mut self, synonyms: HashMap<String, Vec<String>>) -> &mut Self {
+        self.synonyms = synonyms;
+        self
+    }
+
+    /// Customize the number of typos allowed on every word of the query
+    pub fn with_typo_tolerance(&mut self, typo_tolerance: TypoConfig) -> &mut Self {
+        self.typo_tolerance = typo_tolerance;
+        self
+    }
+
+    /// Override the number of typos allowed on some words of the query, whatever their length.
+    /// A rare or technical term can require an exact spelling while the other words follow the
+    /// [`TypoConfig`]. The words are compared once normalized and can't go over [`MAX_TYPOS`].
+    ///
+    /// The last word of the query is still searched as a prefix, an override of 0 typos makes
+    /// `kefir` match `kefirounet` but not `kefyr`. It doesn't apply to the phrases.
+    pub fn with_word_typos(&mut self, word_typos: HashMap<String, usize>) -> &mut Self {
+        self.word_typos = word_typos;
+        self
+    }
+
+    /// Only match the words of the documents written exactly like the words of the query,
+    /// with the same case, accents and punctuation, and without typos or prefix search.
+    ///
+    /// The fst only contains the normalized words, it's used to find the documents
+    /// containing the words once normalized and then the original text of each of them is
+    /// checked. It's slower than a normal search on words contained in many documents.
+    pub fn with_verbatim(&mut self, verbatim: bool) -> &mut Self {
+        self.verbatim = verbatim;
+        self
+    }
+
+    /// Search the words of the query containing wildcards as patterns matched against the words of
+    /// the index: `*` matches any sequence of characters, even empty, and `?` exactly one character.
+    /// `kef*net` matches `kefirounet`. The patterns are searched without typos nor prefix, and only
+    /// within a word, the tokenizer must keep the wildcards in the words of the query.
+    pub fn with_wildcards(&mut self, wildcards: bool) -> &mut Self {
+        self.wildcards = wildcards;
+        self
+    }
+
+    /// Parse the query with the boolean operators, like `(kefir OR tamo) AND beau`. The words are
+    /// grouped with `AND`, `OR` and parentheses, and a `-` excludes a word or a group, the words
+    /// next to each other are joined by an implicit `AND`. The operators decide which documents
+    /// match instead of the [`MatchMode`] and the minimum number of matching words, the words that
+    /// aren't excluded are then ranked as usual. The [`RankingRule::Word`] ranking rule is needed to
+    /// return the documents missing some of the words, like the ones only matching one side of an `OR`.
+    /// The excluded words are matched exactly, the phrases between double quotes still work.
+    pub fn with_boolean_operators(&mut self, boolean_operators: bool) -> &mut Self {
+        self.boolean_operators = boolean_operators;
+        self
+    }
+
+    /// Also match the words of the index containing a word of the query anywhere, "fir" then finds
+    /// "kefir". These matches count as one more typo than allowed on the word, the
+    /// [`RankingRule::Typo`] ranking rule returns them last. It doesn't apply to the phrases.
+    ///
+    /// There is no extra index, the fst can only look up prefixes efficiently thus the whole fst
+    /// is scanned for every word of the query. It's linear in the number of words of the index,
+    /// [`Search::with_max_candidate_terms`] bounds the number of matching words, not the scan.
+    pub fn with_infix(&mut self, infix: bool) -> &mut Self {
+        self.infix = infix;
+        self
+    }
+
+    /// Only return the documents for which `filter` returns true, it's called once for every
+    /// document matching the query before the ranking rules thus the filtered out documents
+    /// don't count in the limit. It can be used to filter on metadata stored outside of the index.
+    pub fn with_filter(
+        &mut self,
+        filter: impl Fn(Id) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.filter = Some(Filter(Arc::new(filter)));
+        self
+    }
+
+    /// Only search the documents whose id is in `scope`, like the documents of a single user.
+    /// The other documents are dropped before the ranking rules run, unlike with a filter
+    /// there is no function to call on every matching document. The limit and the offset
+    /// apply to the documents of the scope, and a placeholder search returns the whole scope.
+    pub fn with_scope(&mut self, scope: RoaringBitmap) -> &mut Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// A query without any word once normalized, like an empty query, is a placeholder search.
+    /// By default it returns all the documents in the order they were inserted, it can be
+    /// used to browse the documents. Disable it to return nothing instead.
+    /// A query made exclusively of stop words is not a placeholder search, the stop words are searched.
+    pub fn with_placeholder(&mut self, placeholder: bool) -> &mut Self {
+        self.placeholder = placeholder;
+        self
+    }
+
+    /// Only keep the `max_candidate_terms` best words of the index matched by every word of the query,
+    /// to bound the time spent on a short prefix matching a lot of words, typically for type-ahead.
+    /// Only the first 16 times `max_candidate_terms` matching words are read from the index, in
+    /// alphabetical order, and the ones with the fewest typos are kept among them, then the most
+    /// frequent ones. Unlimited by default.
+    ///
+    /// The truncation biases the ranking: the documents matching only the dropped words are not returned
+    /// at all, even if they are the only ones containing the other words of the query, and a frequent word
+    /// far in the alphabetical order may be dropped for a rare one. The [`Index::count`] only counts the
+    /// documents of the kept words as well.
+    pub fn with_max_candidate_terms(&mut self, max_candidate_terms: usize) -> &mut Self {
+        self.max_candidate_terms = Some(max_candidate_terms);
+        self
+    }
+
+    /// Discard the documents matching the words of the query with more than `max_typos` typos in total,
+    /// before any ranking rule sees them. It trims the documents only sharing typo'd words with the query,
+    /// while [`TypoConfig`] bounds the typos of every word. The words of the query a document doesn't
+    /// contain are not typos. Unlimited by default.
+    pub fn with_max_typos_per_result(&mut self, max_typos: usize) -> &mut Self {
+        self.max_typos_per_result = Some(max_typos);
+        self
+    }
+
+    /// Only return the documents matching at least `min_matching_words` different words of the query,
+    /// a phrase counting as a single word. If the query contains less words, all of them are required.
+    /// The default is one, the [`RankingRule::Word`] ranking rule still ranks the documents matching
+    /// the most words first. It has no effect with [`MatchMode::All`].
+    pub fn with_min_matching_words(&mut self, min_matching_words: usize) -> &mut Self {
+        self.min_matching_words = min_matching_words;
+        self
+    }
+
+    /// With [`MatchMode::All`] the documents missing a word of the query are never returned,
+    /// the words can still be matched with typos or as a prefix.
+    /// By default the [`RankingRule::Word`] ranking rule ranks them after the others.
+    pub fn with_match_mode(&mut self, match_mode: MatchMode) -> &mut Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Choose which words of the query are searched as prefixes. By default it's the last one, to
+    /// search as you type. A query submitted at once, like from a form, should use [`PrefixMode::None`].
+    pub fn with_prefix(&mut self, prefix_mode: PrefixMode) -> &mut Self {
+        self.prefix_mode = prefix_mode;
+        self
+    }
+
+    /// Choose how the typos of the words matched by the query are counted, by default with the
+    /// Damerau-Levenshtein distance. It only changes the typo buckets the words are sorted in,
+    /// the ones ranked by [`RankingRule::Typo`]: the words are still matched by levenshtein
+    /// automatons allowing the typos of the [`TypoConfig`], and a word can't have more typos than that.
+    pub fn with_distance_metric(&mut self, distance_metric: DistanceMetric) -> &mut Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Sort the documents of a same bucket with `tiebreak`, they're equally relevant for the ranking rules.
+    /// By default they're returned by ascending id, i.e. in the order they were inserted.
+    /// Use `|a, b| b.cmp(&a)` to return the most recent documents first, or compare metadata
+    /// stored outside of the index. Every bucket is sorted entirely before its first document is returned,
+    /// unless it's cut by the limit: only the documents that will be returned are selected and sorted.
+    pub fn with_tiebreak(
+        &mut self,
+        tiebreak: impl Fn(Id, Id) -> Ordering + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.tiebreak = Some(Tiebreak(Arc::new(tiebreak)));
+        self
+    }
+
+    /// Only return the best ranked document of the documents sharing the same `key`, like the same
+    /// product sold by multiple sellers. The documents are deduplicated as they're ranked, before the
+    /// offset and the limit apply, thus the search still returns `limit` distinct keys when it can.
+    /// Within a bucket the first document in the order of [`Search::with_tiebreak`] wins.
+    /// [`Index::count`] doesn't deduplicate the documents.
+    pub fn with_dedup<K: Hash + Eq + 'static>(
+        &mut self,
+        key: impl Fn(Id) -> K + Send + Sync + 'static,
+    ) -> &mut Self {
+        let key = Arc::new(key);
+        self.dedup = Some(Dedup(Arc::new(move || {
+            let key = key.clone();
+            let mut seen = HashSet::new();
+            Box::new(move |id| seen.insert(key(id)))
+        })));
+        self
+    }
+
+    /// Call `trace` on every step of the ranking rules, to see which ranking rule split the documents
+    /// and returned which bucket. Nothing is traced for a placeholder search, it has no ranking rule.
+    pub fn with_trace(&mut self, trace: impl Fn(TraceEvent) + Send + Sync + 'static) -> &mut Self {
+        self.trace = Some(Trace(Arc::new(trace)));
+        self
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct WordCandidate {
-    // the original string
-    original: String,
-    // normalized string
-    normalized: String,
-    // its index in the phrase
-    index: usize,
-    // the number of documuents its contained in
-    typos: Vec<RoaringBitmap>,
-}
+/// Returns the builder of the levenshtein automatons allowing `nb_typo` typos.
+/// The builders are expensive to create, especially for many typos, they're only built when needed.
+/// A transposition counts as a single typo, like in the distance used to rank the matched words.
+fn levenshtein(nb_typo: usize) -> &'static LevenshteinAutomatonBuilder {
+    static LEVENSHTEINS: [OnceLock<LevenshteinAutomatonBuilder>; MAX_TYPOS + 1] =
+        [const { OnceLock::new() }; MAX_TYPOS + 1];
+    LEVENSHTEINS[nb_typo].get_or_init(|| LevenshteinAutomatonBuilder::new(nb_typo as u8, true))
+}
+
+/// Returns the ids of a bucket in the order they should be returned, see [`Search::with_tiebreak`]
+fn bucket_ids(tiebreak: Option<&Tiebreak>, bucket: RoaringBitmap) -> impl Iterator<Item = Id> {
+    let (sorted, unsorted) = match tiebreak {
+        Some(Tiebreak(tiebreak)) => {
+            let mut ids: Vec<Id> = bucket.into_iter().collect();
+            ids.sort_by(|a, b| tiebreak(*a, *b));
+            (Some(ids), None)
+        }
+        None => (None, Some(bucket)),
+    };
+    sorted
+        .into_iter()
+        .flatten()
+        .chain(unsorted.into_iter().flatten())
+}
+
+/// Run the automaton on a word that is not in the fst
+fn automaton_matches(automaton: &impl Automaton, word: &str) -> bool {
+    let mut state = automaton.start();
+    for &byte in word.as_bytes() {
+        if !automaton.can_match(&state) {
+            return false;
+        }
+        state = automaton.accept(&state, byte);
+    }
+    automaton.is_match(&state)
+}
+
+/// Returns the length in bytes of the shortest prefix of `word` that is `normalized_len` bytes long once normalized.
+/// Since the normalization drops and replaces characters we can't use `normalized_len` directly on `word`.
+fn original_len(tokenizer: &dyn Tokenizer, word: &str, normalized_len: usize) -> usize {
+    let mut len = 0;
+    for (offset, c) in word.char_indices() {
+        if len >= normalized_len {
+            return offset;
+        }
+        len += tokenizer.normalize(c.encode_utf8(&mut [0; 4])).len();
+    }
+    word.len()
+}
+
+/// The `max_len` characters of the document around the highlight with the most highlights in
+/// its window, or its first characters if nothing is highlighted
+fn snippet<'d>(document: &'d str, highlights: &[Range<usize>], max_len: usize) -> &'d str {
+    let windows = highlights.iter().map(|highlight| {
+        let window = snippet_window(document, highlight.clone(), max_len);
+        let highlighted = highlights
+            .iter()
+            .filter(|other| window.start <= other.start && other.end <= window.end)
+            .count();
+        (highlighted, window)
+    });
+    // the first match wins the ties
+    let window = windows
+        .rev()
+        .max_by_key(|(highlighted, _)| *highlighted)
+        .map_or_else(
+            || snippet_window(document, 0..0, max_len),
+            |(_, window)| window,
+        );
+    document[window].trim()
+}
+
+/// The byte range of at most `max_len` characters centered on `highlight`, within its field
+fn snippet_window(document: &str, highlight: Range<usize>, max_len: usize) -> Range<usize> {
+    let field_start = document[..highlight.start]
+        .rfind(FIELD_SEPARATOR)
+        .map_or(0, |separator| separator + FIELD_SEPARATOR.len_utf8());
+    let field_end = document[highlight.start..]
+        .find(FIELD_SEPARATOR)
+        .map_or(document.len(), |separator| highlight.start + separator);
+    let field = &document[field_start..field_end];
+
+    // the window is counted in characters, a character can take multiple bytes
+    let (start, end) = (highlight.start - field_start, highlight.end - field_start);
+    let before = field[..start].chars().count();
+    let matched = field[start..end].chars().count();
+    let total = before + field[start..].chars().count();
+    let first = before
+        .saturating_sub(max_len.saturating_sub(matched) / 2)
+        .min(total.saturating_sub(max_len));
+    let byte = |chars: usize| {
+        field
+            .char_indices()
+            .nth(chars)
+            .map_or(field.len(), |(offset, _)| offset)
+    };
+    let (mut window_start, mut window_end) = (byte(first), byte(first + max_len));
+
+    // don't cut the words at the edges of the window, the matched word is kept whole
+    let cuts = |at: usize| {
+        let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+        is_word(field[..at].chars().next_back()) && is_word(field[at..].chars().next())
+    };
+    if cuts(window_start) {
+        let space = field[window_start..start.max(window_start)].find(char::is_whitespace);
+        window_start = space.map_or(window_start, |space| window_start + space);
+    }
+    if cuts(window_end) {
+        let space = field[end.min(window_end)..window_end].rfind(char::is_whitespace);
+        window_end = space.map_or(window_end, |space| end.min(window_end) + space);
+    }
+    field_start + window_start..field_start + window_end
+}
+
+/// Join the fields of a document as stored in the index
+fn join_fields(fields: &[impl AsRef<str>]) -> String {
+    let mut document = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            document.push(FIELD_SEPARATOR);
+        }
+        document.push_str(field.as_ref());
+    }
+    document
+}
+
+/// Lowercase the word, remove its diacritics and drop everything that's not a letter or a digit.
+///
+/// It's the normalization applied by [`Tokenizer::normalize`] unless the tokenizer overrides it.
+pub fn normalize(s: &str) -> String {
+    normalize_with(s, NormalizeConfig::default())
+}
+
+/// Controls what [`normalize_with`] folds, see [`NormalizeTokenizer`].
+/// By default the words are lowercased and lose their diacritics, like with [`normalize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizeConfig {
+    /// Lowercase the letters, "Kefir" matches "kefir"
+    pub lowercase: bool,
+    /// Remove the diacritics and split the ligatures, "café" matches "cafe".
+    /// Otherwise the letters are kept composed, thus "año" and "ano" stay different words.
+    pub fold_accents: bool,
+    /// How the letters with diacritics are folded when [`NormalizeConfig::fold_accents`] is enabled
+    pub folding: Folding,
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            fold_accents: true,
+            folding: Folding::All,
+        }
+    }
+}
+
+/// The letters with diacritics [`normalize_with`] folds differently, for the languages where they
+/// aren't simply a base letter with an accent, see [`NormalizeConfig::folding`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Folding {
+    /// Every letter loses its diacritics, it suits French or Spanish
+    #[default]
+    All,
+    /// The umlauts are written out, "müller" matches "mueller" but not "muller"
+    German,
+    /// "å", "æ", "ø", "ä" and "ö" are letters of their own in Danish, Norwegian and Swedish,
+    /// they're kept as is
+    Nordic,
+    /// The letters of the table are replaced by their text, after being lowercased,
+    /// the other ones lose their diacritics
+    Custom(&'static [(char, &'static str)]),
+}
+
+impl Folding {
+    /// The text replacing a letter, if it isn't folded like the other letters
+    fn replacement(self, c: char) -> Option<&'static str> {
+        const GERMAN: &[(char, &str)] = &[
+            ('ä', "ae"),
+            ('ö', "oe"),
+            ('ü', "ue"),
+            ('Ä', "AE"),
+            ('Ö', "OE"),
+            ('Ü', "UE"),
+        ];
+        const NORDIC: &[(char, &str)] = &[
+            ('å', "å"),
+            ('æ', "æ"),
+            ('ø', "ø"),
+            ('ä', "ä"),
+            ('ö', "ö"),
+            ('Å', "Å"),
+            ('Æ', "Æ"),
+            ('Ø', "Ø"),
+            ('Ä', "Ä"),
+            ('Ö', "Ö"),
+        ];
+        let table = match self {
+            Folding::All => return None,
+            Folding::German => GERMAN,
+            Folding::Nordic => NORDIC,
+            Folding::Custom(table) => table,
+        };
+        table
+            .iter()
+            .find(|(letter, _)| *letter == c)
+            .map(|(_, replacement)| *replacement)
+    }
+}
+
+/// Normalize the word like [`normalize`] but only fold what's enabled by `config`.
+/// Everything that's not a letter or a digit is still dropped.
+pub fn normalize_with(s: &str, config: NormalizeConfig) -> String {
+    let mut normalized = String::with_capacity(s.len());
+    // the tables of the foldings only contain composed letters
+    let composed: String;
+    let s = match config.folding {
+        Folding::All => s,
+        _ => {
+            composed = s.nfc().collect();
+            &composed
+        }
+    };
+    for c in s.chars() {
+        // most of our documents are ascii, don't go through the unicode tables for them
+        if c.is_ascii() {
+            if c.is_ascii_alphanumeric() {
+                normalized.push(match config.lowercase {
+                    true => c.to_ascii_lowercase(),
+                    false => c,
+                });
+            }
+            continue;
+        }
+
+        match config.lowercase {
+            true => c
+                .to_lowercase()
+                .for_each(|c| push_letter(&mut normalized, c, config)),
+            false => push_letter(&mut normalized, c, config),
+        }
+    }
+
+    if !config.fold_accents && !normalized.is_ascii() {
+        // the diacritics of the decomposed letters are composed back
+        normalized = normalized.nfkc().collect();
+    }
+    normalized
+}
+
+/// Push a letter that's not ascii in the normalized word
+fn push_letter(normalized: &mut String, c: char, config: NormalizeConfig) {
+    if !config.fold_accents {
+        if c.is_alphanumeric() || is_combining_mark(c) {
+            normalized.push(c);
+        }
+        return;
+    }
+    if let Some(replacement) = config.folding.replacement(c) {
+        normalized.push_str(replacement);
+        return;
+    }
+    // the letters that doesn't decompose into a base letter and a diacritic
+    match c {
+        'ß' => normalized.push_str("ss"),
+        'æ' => normalized.push_str("ae"),
+        'Æ' => normalized.push_str("AE"),
+        'œ' => normalized.push_str("oe"),
+        'Œ' => normalized.push_str("OE"),
+        'þ' => normalized.push_str("th"),
+        'Þ' => normalized.push_str("TH"),
+        'ł' => normalized.push('l'),
+        'Ł' => normalized.push('L'),
+        'đ' | 'ð' => normalized.push('d'),
+        'Đ' | 'Ð' => normalized.push('D'),
+        'ø' => normalized.push('o'),
+        'Ø' => normalized.push('O'),
+        c => normalized.extend(
+            std::iter::once(c)
+                .nfkd()
+                .filter(|c| !is_combining_mark(*c) && c.is_alphanumeric()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use levenshtein_automata::Distance;
+
+    use super::*;
+
+    fn create_small_index() -> Index<'static> {
+        let names: &[&str] = &[
+            "Tamo le plus beau",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "tamo est très beau aussi",
+            "le plus beau c'est kefir",
+            "mais il est un peu con",
+            "le petit kefir",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+            "kefir est un demi poney",
+            "le double kef",
+            "les keftas c'est bon aussi",
+        ];
+        Index::new_in_memory(names)
+    }
+
+    #[test]
+    fn test_search_with_only_word() {
+        let index = create_small_index();
+        let mut search = Search::new("tamo");
+        search.with_ranking_rules(vec![RankingRule::Word]);
+
+        insta::assert_debug_snapshot!(index.search(&search), @r###"
+        [
+            "Tamo le plus beau",
+            "tamo est très beau aussi",
+        ]
+        "###);
+
+        // "tamo est" was matched first and then tamo alone
+        let mut search = Search::new("tamo est");
+        search.with_ranking_rules(vec![RankingRule::Word]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tamo est très beau aussi",
+            "Tamo le plus beau",
+            "le plus beau c'est kefir",
+            "mais il est un peu con",
+            "kefir est un demi poney",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+
+        // "kefir" is dropped first, but the documents matching only "kefir" or
+        // its prefixes like kefirounet must still be returned at the end
+        let mut search = Search::new("beau kefir");
+        search
+            .with_ranking_rules(vec![RankingRule::Word])
+            .with_limit(20);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le beau chien",
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+            "tamo est très beau aussi",
+            "kefir le bon petit chien",
+            "le petit kefir",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+            "kefir est un demi poney",
+        ]
+        "#);
+
+        // an earlier word matching nothing is searched as a prefix as well
+        let mut search = Search::new("kefirou nez");
+        search
+            .with_ranking_rules(vec![RankingRule::Word])
+            .with_typo_tolerance(TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet a un gros nez",
+            "kefirounet se prends pour un poney",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_concurrent_search() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Index<'static>>();
+        assert_send_sync::<Search<'static>>();
+
+        let index = Arc::new(create_small_index());
+        let queries = [
+            "kefir",
+            "tamo beau",
+            "le petit chien",
+            "kefirounet",
+            "\"petit kefir\"",
+        ];
+        let expected: Vec<_> = queries
+            .iter()
+            .map(|query| index.search(&Search::new(query)))
+            .collect();
+
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let index = index.clone();
+                std::thread::spawn(move || {
+                    (0..50)
+                        .map(|i| {
+                            let query = (thread + i) % queries.len();
+                            let results = index.search(&Search::new(queries[query]));
+                            (query, results.join("\n"))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for thread in threads {
+            for (query, results) in thread.join().unwrap() {
+                assert_eq!(results, expected[query].join("\n"), "{}", queries[query]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_ids() {
+        let index = create_small_index();
+        let search = Search::new("tamo");
+
+        let ids = index.search_ids(&search);
+        insta::assert_debug_snapshot!(ids, @"
+        [
+            3,
+            0,
+        ]
+        ");
+        // the ids must point to the documents returned by search, in the same order
+        let documents: Vec<_> = ids
+            .iter()
+            .map(|id| index.get_document(*id).unwrap())
+            .collect();
+        assert_eq!(documents, index.search(&search));
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let index = create_small_index();
+        let queries = [
+            "kefir",
+            "beau kefir",
+            "le beau chien",
+            "kefirounet poney",
+            "tamo le plus beau kefir",
+            "kef",
+            "\"petit kefir\" le",
+            "le le le",
+            "",
+        ];
+        let ranking_rules = [
+            Search::new("").ranking_rules,
+            vec![RankingRule::Word],
+            vec![RankingRule::Typo, RankingRule::Word],
+            vec![
+                RankingRule::Word,
+                RankingRule::Frequency,
+                RankingRule::Position,
+                RankingRule::Exact,
+            ],
+        ];
+
+        for query in queries {
+            for ranking_rules in ranking_rules.iter() {
+                let mut search = Search::new(query);
+                search
+                    .with_limit(usize::MAX)
+                    .with_ranking_rules(ranking_rules.clone())
+                    .with_synonyms(HashMap::from([(
+                        String::from("kefir"),
+                        vec![String::from("kefirounet")],
+                    )]));
+                let ids = index.search_ids(&search);
+                let unique: HashSet<_> = ids.iter().collect();
+                assert_eq!(
+                    unique.len(),
+                    ids.len(),
+                    "{query:?} with {ranking_rules:?} returned {ids:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_many() {
+        let index = create_small_index();
+        let mut searches = vec![
+            Search::new("kefir"),
+            Search::new("tamo"),
+            Search::new(""),
+            Search::new("beau chien"),
+        ];
+        searches[2].with_limit(3);
+        searches[3].with_ranking_rules(vec![RankingRule::Word, RankingRule::Proximity]);
+
+        let results = index.search_many(&searches);
+        // the buffers of a search must not leak into the next one
+        let expected: Vec<_> = searches.iter().map(|search| index.search(search)).collect();
+        assert_eq!(results, expected);
+        insta::assert_debug_snapshot!(results[1], @r#"
+        [
+            "tamo est très beau aussi",
+            "Tamo le plus beau",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_search_ranked() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir le beau");
+        search.with_ranking_rules(vec![RankingRule::Word]);
+        insta::assert_debug_snapshot!(index.search_ranked(&search), @r#"
+        [
+            (
+                0,
+                "kefir le beau chien",
+            ),
+            (
+                0,
+                "le plus beau c'est kefir",
+            ),
+            (
+                1,
+                "Tamo le plus beau",
+            ),
+            (
+                1,
+                "tamo est très beau aussi",
+            ),
+            (
+                2,
+                "kefir le bon petit chien",
+            ),
+            (
+                2,
+                "le petit kefir",
+            ),
+            (
+                2,
+                "kefir est un demi poney",
+            ),
+            (
+                2,
+                "le double kef",
+            ),
+        ]
+        "#);
+        // the ranks of the buckets stay the same with an offset
+        search.with_offset(3).with_limit(2);
+        insta::assert_debug_snapshot!(index.search_ranked(&search), @r#"
+        [
+            (
+                1,
+                "tamo est très beau aussi",
+            ),
+            (
+                2,
+                "kefir le bon petit chien",
+            ),
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_search_buckets() {
+        let index = create_small_index();
+        let mut search = Search::new("beau chien");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Typo]);
+        // the documents matching both words, then the ones matching only one of them
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le beau chien",
+            ],
+            [
+                "kefir le bon petit chien",
+            ],
+            [
+                "Tamo le plus beau",
+                "tamo est très beau aussi",
+                "le plus beau c'est kefir",
+            ],
+        ]
+        "#);
+
+        // the pagination applies to the documents
+        search.with_offset(1).with_limit(3);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le bon petit chien",
+            ],
+            [
+                "Tamo le plus beau",
+                "tamo est très beau aussi",
+            ],
+        ]
+        "#);
+        assert_eq!(
+            index.search_buckets(&search).concat(),
+            index.search(&search)
+        );
+    }
+
+    #[test]
+    fn test_search_iter() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_limit(usize::MAX).with_offset(1);
+
+        let all: Vec<_> = index.search_iter(&search).collect();
+        assert_eq!(all, index.search(&search));
+        // we can stop whenever we want, the remaining buckets are never sorted
+        let first: Vec<_> = index
+            .search_iter(&search)
+            .take_while(|document| document.starts_with("kefir"))
+            .collect();
+        insta::assert_debug_snapshot!(first, @r#"
+        [
+            "kefir le beau chien",
+            "kefir est un demi poney",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let index = create_small_index();
+        let mut search = Search::new("kefirounet");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+
+        // the two kefirounet share a key, only the best ranked one is kept
+        search.with_dedup(|id| if id == 8 { 7 } else { id });
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet se prends pour un poney",
+        ]
+        "#);
+
+        // 9 is ranked before 4, and the duplicates don't count in the limit
+        let mut search = Search::new("kefir");
+        search
+            .with_limit(4)
+            .with_dedup(|id| if id == 9 { 4 } else { id });
+        insta::assert_snapshot!(format!("{:?}", index.search_ids(&search)), @"[1, 2, 9, 6]");
+
+        // only the best documents of a big bucket are deduplicated, the pairs share a key
+        let index = Index::new_in_memory(&["kefir"; 100]);
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut search = Search::new("kefir");
+        search
+            .with_limit(2)
+            .with_tiebreak(|a, b| b.cmp(&a))
+            .with_dedup(move |id| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                id / 2
+            });
+        insta::assert_snapshot!(format!("{:?}", index.search_ids(&search)), @"[99, 97]");
+        insta::assert_snapshot!(calls.load(std::sync::atomic::Ordering::Relaxed), @"3");
+    }
+
+    #[test]
+    fn test_search_owned() {
+        let index = std::sync::RwLock::new(create_small_index());
+        let search = Search::new("kefir");
+        // the lock is released before the results are used
+        let owned: Vec<String> = index.read().unwrap().search_owned(&search);
+        index.write().unwrap().delete(1);
+        assert_eq!(owned, create_small_index().search(&search));
+    }
+
+    #[test]
+    fn test_structured_documents() {
+        #[derive(Debug, Clone)]
+        struct Dog {
+            name: String,
+            age: u8,
+        }
+
+        impl AsRef<str> for Dog {
+            fn as_ref(&self) -> &str {
+                &self.name
+            }
+        }
+
+        let dogs = [
+            Dog {
+                name: String::from("kefir"),
+                age: 2,
+            },
+            Dog {
+                name: String::from("tamo"),
+                age: 7,
+            },
+        ];
+        let mut index = Index::from_documents(&dogs);
+        index.insert(Dog {
+            name: String::from("kefirounet"),
+            age: 1,
+        });
+
+        insta::assert_debug_snapshot!(index.search(&Search::new("kefir")), @r#"
+        [
+            Dog {
+                name: "kefir",
+                age: 2,
+            },
+            Dog {
+                name: "kefirounet",
+                age: 1,
+            },
+        ]
+        "#);
+        assert_eq!(index.get_document(1).unwrap().age, 7);
+    }
+
+    #[test]
+    fn test_count() {
+        let index = create_small_index();
+        let mut search = Search::new("beau kefir");
+        search.with_limit(2);
+        insta::assert_debug_snapshot!(index.count(&search), @"9");
+        assert_eq!(
+            index.count(&search),
+            index.search(search.with_limit(usize::MAX)).len()
+        );
+
+        search.with_ranking_rules(vec![RankingRule::Typo]);
+        insta::assert_debug_snapshot!(index.count(&search), @"2");
+        assert_eq!(index.count(&search), index.search(&search).len());
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let mut index = create_small_index();
+        index.delete(1);
+        let mut search = Search::new(" ?! ");
+        search.with_limit(3).with_offset(1);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le beau chien",
+            "tamo est très beau aussi",
+            "le plus beau c'est kefir",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.count(&search), @"11");
+
+        search.with_filter(|id| id > 8);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le double kef",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+
+        search.with_placeholder(false);
+        insta::assert_debug_snapshot!(index.search(&search), @"[]");
+        insta::assert_debug_snapshot!(index.count(&search), @"0");
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut index = create_small_index();
+        let before = index.stats();
+        insta::assert_debug_snapshot!(before, @"
+        IndexStats {
+            documents: 12,
+            terms: 30,
+            fst_bytes: 257,
+            bitmaps_bytes: 592,
+            positions_bytes: 1472,
+        }
+        ");
+
+        index.insert(String::from("zzz"));
+        index.delete(0);
+        let after = index.stats();
+        assert_eq!(after.documents, before.documents);
+        assert_eq!(after.terms, before.terms + 1);
+        assert!(after.bitmaps_bytes > before.bitmaps_bytes);
+    }
+
+    #[test]
+    fn test_document_frequency() {
+        let mut index = create_small_index();
+        index.insert(String::from("un poney zzz"));
+        index.delete(5);
+        let frequencies = ["Kéfir", "kef", "poney", "zzz", "con", "xyz"]
+            .map(|word| (word, index.contains(word), index.document_frequency(word)));
+        insta::assert_snapshot!(format!("{frequencies:?}"), @r#"[("Kéfir", true, 5), ("kef", true, 1), ("poney", true, 3), ("zzz", true, 1), ("con", false, 0), ("xyz", false, 0)]"#);
+    }
+
+    #[test]
+    fn test_terms() {
+        let mut index = Index::new_in_memory(&["Kéfir le chien", "le chat"]);
+        index.insert(String::from("un beau chat"));
+        index.insert(String::from("zzz"));
+        index.delete(0);
+        insta::assert_debug_snapshot!(index.terms().collect::<Vec<_>>(), @r#"
+        [
+            (
+                "beau",
+                1,
+            ),
+            (
+                "chat",
+                2,
+            ),
+            (
+                "le",
+                1,
+            ),
+            (
+                "un",
+                1,
+            ),
+            (
+                "zzz",
+                1,
+            ),
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_autocomplete() {
+        let mut index = create_small_index();
+        index.insert(String::from("kefounet"));
+        insta::assert_debug_snapshot!(index.autocomplete("KEF", 4), @r#"
+        [
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "le plus beau c'est kefir",
+            "le petit kefir",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.autocomplete("kefirou", 10), @r#"
+        [
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+        // the words inserted since the fst was built
+        assert_eq!(index.autocomplete("kefo", 10), vec!["kefounet"]);
+        insta::assert_debug_snapshot!(index.autocomplete("", 10), @"[]");
+    }
+
+    #[test]
+    fn test_suggest() {
+        let index = create_small_index();
+        insta::assert_debug_snapshot!(index.suggest("le petit kefor"), @r#"
+        Some(
+            "le petit kefir",
+        )
+        "#);
+        insta::assert_debug_snapshot!(index.suggest("\"le petit\" poner tami"), @r#"
+        Some(
+            "\"le petit\" poney tamo",
+        )
+        "#);
+        // all the words exist
+        insta::assert_debug_snapshot!(index.suggest("le petit kefir"), @"None");
+        // nothing close enough
+        insta::assert_debug_snapshot!(index.suggest("zzzzz"), @"None");
+    }
+
+    #[test]
+    fn test_max_typos_per_result() {
+        // the short prefix "kef" also matches "nef" and "keg" with a typo
+        let index = Index::new_in_memory(&["kefir", "la nef", "le kef", "un keg"]);
+        let mut search = Search::new("kef");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le kef",
+            "kefir",
+            "la nef",
+            "un keg",
+        ]
+        "#);
+        search.with_max_typos_per_result(0);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le kef",
+            "kefir",
+        ]
+        "#);
+
+        // more typos than the words can have is the same as no cutoff
+        search.with_max_typos_per_result(usize::MAX);
+        insta::assert_debug_snapshot!(index.search(&search).len(), @"4");
+
+        // the typos of all the words add up, the tamo documents have two
+        let index = create_small_index();
+        let mut search = Search::new("tami beua");
+        search.with_limit(20).with_max_typos_per_result(1);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_min_matching_words() {
+        let index = create_small_index();
+        let mut search = Search::new("le petit kefir chien");
+        search.with_limit(20).with_min_matching_words(3);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le bon petit chien",
+            "le petit kefir",
+            "kefir le beau chien",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.count(&search), @"3");
+
+        // a query with less words than the quorum needs all of them
+        let mut search = Search::new("beau kefir");
+        search.with_limit(20).with_min_matching_words(3);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_max_candidate_terms() {
+        let index = create_small_index();
+        // "ke" matches kefir, kefirounet, kef and keftas
+        let mut search = Search::new("ke");
+        search.with_limit(20).with_max_candidate_terms(2);
+        // kefir is the most frequent, then kefirounet
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le plus beau c'est kefir",
+            "le petit kefir",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "kefir est un demi poney",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+
+        // the word with less typos is preferred over a more frequent one
+        let index = Index::new_in_memory(&["tamo", "tami", "tami le retour", "tami 3"]);
+        let mut search = Search::new("tamo");
+        search.with_max_candidate_terms(1);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tamo",
+        ]
+        "#);
+
+        // only the first 16 matching words are read, the frequent "kz" comes after them
+        let mut names: Vec<String> = ('a'..='t').map(|c| format!("k{c}")).collect();
+        names.extend(["kz", "kz kz", "kz le retour"].map(String::from));
+        let index = Index::new_in_memory(&names);
+        let mut search = Search::new("k");
+        search.with_max_candidate_terms(1);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "ka",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_prefix_mode() {
+        let index = create_small_index();
+        let mut search = Search::new("kef");
+        search.with_limit(20);
+        insta::assert_debug_snapshot!(index.search(&search).len(), @"9");
+        search.with_prefix(PrefixMode::None);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le double kef",
+        ]
+        "#);
+
+        // the earlier words matching nothing are not searched as prefixes either
+        let mut search = Search::new("kefirouy poney");
+        search.with_match_mode(MatchMode::All);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet se prends pour un poney",
+        ]
+        "#);
+        search.with_prefix(PrefixMode::None);
+        insta::assert_debug_snapshot!(index.search(&search), @"[]");
+
+        let mut search = Search::new("kef pon");
+        search
+            .with_prefix(PrefixMode::All)
+            .with_match_mode(MatchMode::All);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir est un demi poney",
+            "kefirounet se prends pour un poney",
+            "kefir le bon petit chien",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_distance_metric() {
+        let index = Index::new_in_memory(&["atam", "tam", "tamo"]);
+        let mut search = Search::new("tam");
+        search
+            .with_prefix(PrefixMode::None)
+            .with_ranking_rules(vec![RankingRule::Typo]);
+        // both words are one typo away from a 3 letters word
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tam",
+            "atam",
+            "tamo",
+        ]
+        "#);
+        // but "tamo" starts like the query
+        search.with_distance_metric(DistanceMetric::JaroWinkler);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tam",
+            "tamo",
+            "atam",
+        ]
+        "#);
+
+        // the words must still be matched by the automatons
+        search.with_typo_tolerance(TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tam",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_match_mode() {
+        let index = create_small_index();
+        let mut search = Search::new("beau kefir");
+        search.with_limit(20).with_match_mode(MatchMode::All);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.count(&search), @"2");
+
+        search.with_match_mode(MatchMode::Any);
+        insta::assert_debug_snapshot!(index.count(&search), @"9");
+    }
+
+    #[test]
+    fn test_search_explained() {
+        let index = create_small_index();
+        let mut search = Search::new("tami kefi");
+        search.with_limit(3);
+        insta::assert_debug_snapshot!(index.search_explained(&search), @r#"
+        [
+            ExplainedDoc {
+                id: 3,
+                document: "tamo est très beau aussi",
+                matches: [
+                    TermMatch {
+                        query_word: "tami",
+                        term: "tamo",
+                        typos: 1,
+                    },
+                ],
+            },
+            ExplainedDoc {
+                id: 0,
+                document: "Tamo le plus beau",
+                matches: [
+                    TermMatch {
+                        query_word: "tami",
+                        term: "tamo",
+                        typos: 1,
+                    },
+                ],
+            },
+            ExplainedDoc {
+                id: 4,
+                document: "le plus beau c'est kefir",
+                matches: [
+                    TermMatch {
+                        query_word: "kefi",
+                        term: "kefir",
+                        typos: 0,
+                    },
+                ],
+            },
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_search_scored() {
+        let index = create_small_index();
+
+        // the documents matching both words must score higher than the ones matching only "beau"
+        let scored = index.search_scored(&Search::new("beau kef"));
+        insta::assert_debug_snapshot!(scored, @r#"
+        [
+            (
+                1.0,
+                "le plus beau c'est kefir",
+            ),
+            (
+                1.0,
+                "kefir le beau chien",
+            ),
+            (
+                0.5,
+                "Tamo le plus beau",
+            ),
+            (
+                0.5,
+                "tamo est très beau aussi",
+            ),
+            (
+                0.5,
+                "le petit kefir",
+            ),
+            (
+                0.5,
+                "le double kef",
+            ),
+            (
+                0.5,
+                "les keftas c'est bon aussi",
+            ),
+            (
+                0.5,
+                "kefir le bon petit chien",
+            ),
+            (
+                0.5,
+                "kefirounet se prends pour un poney",
+            ),
+            (
+                0.5,
+                "kefirounet a un gros nez",
+            ),
+        ]
+        "#);
+        assert!(scored.windows(2).all(|w| w[0].0 >= w[1].0));
+
+        // the scores of two queries can be compared, matching a single word is not a perfect match
+        let index = Index::new_in_memory(&["kefir le chien", "tamo"]);
+        let scores = ["kefir le chien", "kefyr le chein", "zzz zzz kefir"]
+            .map(|query| index.search_scored(&Search::new(query))[0].0);
+        insta::assert_debug_snapshot!(scores, @"
+        [
+            1.0,
+            0.6666667,
+            0.33333334,
+        ]
+        ");
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut index = create_small_index();
+
+        // a word that was already in the fst and a new one
+        let id = index.insert(String::from("tamo le poulpe"));
+        assert_eq!(id, 12);
+        insta::assert_debug_snapshot!(index.search(&Search::new("poulpe")), @r#"
+        [
+            "tamo le poulpe",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search(&Search::new("tamo")), @r#"
+        [
+            "tamo est très beau aussi",
+            "tamo le poulpe",
+            "Tamo le plus beau",
+        ]
+        "#);
+
+        // merging the pending words in the fst shouldn't change the results
+        index.merge_pending();
+        assert!(index.pending.is_empty());
+        insta::assert_debug_snapshot!(index.search(&Search::new("poulpe")), @r#"
+        [
+            "tamo le poulpe",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut index = create_small_index();
+        let id = index.insert(String::from("tamo le poulpe"));
+
+        assert!(index.delete(0));
+        assert!(index.delete(id));
+        // can't delete twice or something that doesn't exists
+        assert!(!index.delete(id));
+        assert!(!index.delete(1000));
+        assert_eq!(index.get_document(0), None);
+
+        insta::assert_debug_snapshot!(index.search(&Search::new("tamo")), @r#"
+        [
+            "tamo est très beau aussi",
+        ]
+        "#);
+        // "poulpe" was only used by the deleted document
+        assert!(index.search(&Search::new("poulpe")).is_empty());
+        assert!(!index.pending.contains_key("poulpe"));
+
+        // "mais" only appears in the 5th document, its entry stays in the fst until the next rebuild
+        assert!(index.delete(5));
+        assert!(index.fst.get("mais").is_some());
+        assert!(index.search(&Search::new("mais")).is_empty());
+        index.merge_pending();
+        assert!(index.fst.get("mais").is_none());
+        insta::assert_debug_snapshot!(index.search(&Search::new("tamo")), @r#"
+        [
+            "tamo est très beau aussi",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_merge() {
+        let full = create_small_index();
+        let names: Vec<&str> = (0..12).map(|id| full.get_document(id).unwrap()).collect();
+        let (left, right) = names.split_at(6);
+
+        let mut left = Index::new_in_memory(left);
+        // the pending words must be merged as well
+        left.insert(String::from("tamo le poulpe"));
+        let mut right = Index::new_in_memory(right);
+        right.delete(2);
+        let merged = left.merge(right);
+
+        let mut full = Index::new_in_memory(&names[..6]);
+        full.insert(String::from("tamo le poulpe"));
+        for name in &names[6..] {
+            full.insert(name.to_string());
+        }
+        full.delete(9);
+
+        for query in [
+            "tamo",
+            "beau kef",
+            "poulpe",
+            "kefir le beau chien",
+            "\"petit kefir\"",
+            "un poney",
+        ] {
+            let mut search = Search::new(query);
+            search.with_limit(20);
+            assert_eq!(merged.search(&search), full.search(&search), "{query}");
+        }
+        assert_eq!(merged.get_document(7), Some("le petit kefir"));
+        assert_eq!(merged.get_document(9), None);
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut index = create_small_index();
+        index.insert(String::from("tamo le poulpe"));
+        let mut compacted = create_small_index();
+        compacted.insert(String::from("tamo le poulpe"));
+        for id in (0..12).step_by(2) {
+            index.delete(id);
+            compacted.delete(id);
+        }
+        let (compacted, ids) = compacted.compact();
+        insta::assert_snapshot!(format!("{ids:?}"), @"[None, Some(0), None, Some(1), None, Some(2), None, Some(3), None, Some(4), None, Some(5), Some(6)]");
+
+        for query in [
+            "tamo",
+            "beau kef",
+            "poulpe",
+            "kefir le beau chien",
+            "\"petit kefir\"",
+            "un poney",
+        ] {
+            let mut search = Search::new(query);
+            search.with_limit(20);
+            assert_eq!(index.search(&search), compacted.search(&search), "{query}");
+            let remapped: Vec<Id> = index
+                .search_ids(&search)
+                .iter()
+                .map(|id| ids[*id as usize].unwrap())
+                .collect();
+            assert_eq!(remapped, compacted.search_ids(&search), "{query}");
+        }
+        // the words of the deleted documents are gone
+        assert_eq!(compacted.terms().count(), compacted.fst.len());
+        assert!(compacted.fst.len() < index.fst.len());
+        assert_eq!(compacted.get_document(6), Some("tamo le poulpe"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut index = create_small_index();
+        index.insert(String::from("tamo le poulpe"));
+        index.delete(0);
+
+        let path = std::env::temp_dir().join("zearch_test_save_and_load.zearch");
+        index.save(&path).unwrap();
+        let loaded = Index::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for query in ["tamo", "beau kef", "poulpe", "kefir le beau chien", "c'est"] {
+            let search = Search::new(query);
+            assert_eq!(index.search(&search), loaded.search(&search), "{query}");
+        }
+        assert_eq!(loaded.get_document(0), None);
+    }
+
+    #[test]
+    fn test_format_version() {
+        let mut bytes = Vec::new();
+        create_small_index().write_into(&mut bytes).unwrap();
+        assert!(Index::from_bytes(&bytes).is_some());
+
+        // an index written by another version of the format is rejected
+        let version = MAGIC.len()..MAGIC.len() + 4;
+        bytes[version].copy_from_slice(&2u32.to_be_bytes());
+        let path = std::env::temp_dir().join("zearch_test_format_version.zearch");
+        std::fs::write(&path, &bytes).unwrap();
+        let error = Index::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        insta::assert_snapshot!(error, @"unsupported zearch index version 2, expected version 1");
+        assert!(Index::from_bytes(&bytes).is_none());
+
+        // and so are the files that aren't indexes
+        let error = Index::read_header(&mut b"kefir".as_slice()).unwrap_err();
+        insta::assert_snapshot!(error, @"not a zearch index");
+    }
+
+    #[test]
+    fn test_construct_from_iter() {
+        let names = [
+            "Tamo le plus beau",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "le petit kefir",
+        ];
+        let mut expected = Vec::new();
+        Index::construct(&names, &mut expected).unwrap();
+
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        Index::construct_from_iter(names.iter().map(|name| name.to_string()), &mut bytes).unwrap();
+        assert_eq!(bytes.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_bitmaps_from_words() {
+        // the same words tokenized out of the order of the ids and of the positions
+        let mut words: Vec<(Id, u32, String)> = [
+            (3, 0, "kefir"),
+            (1, 2, "kefir"),
+            (0, 0, "tamo"),
+            (2, 1, "kefir"),
+            (1, 0, "kefir"),
+            (2, 0, "kefir"),
+            (1, 1, "tamo"),
+        ]
+        .into_iter()
+        .map(|(id, pos, word)| (id, pos, word.to_string()))
+        .collect();
+        let (bitmaps, positions, lengths) = Index::<str>::bitmaps_from_words(&mut words, 4);
+        let positions: Vec<Vec<(Id, u32)>> = positions
+            .iter()
+            .map(|positions| {
+                positions
+                    .iter()
+                    .map(|pos| ((pos >> 32) as Id, pos as u32))
+                    .collect()
+            })
+            .collect();
+        insta::assert_debug_snapshot!((bitmaps, positions, lengths), @"
+        (
+            [
+                RoaringBitmap<[1, 2, 3]>,
+                RoaringBitmap<[0, 1]>,
+            ],
+            [
+                [
+                    (
+                        1,
+                        0,
+                    ),
+                    (
+                        1,
+                        2,
+                    ),
+                    (
+                        2,
+                        0,
+                    ),
+                    (
+                        2,
+                        1,
+                    ),
+                    (
+                        3,
+                        0,
+                    ),
+                ],
+                [
+                    (
+                        0,
+                        0,
+                    ),
+                    (
+                        1,
+                        1,
+                    ),
+                ],
+            ],
+            [
+                1,
+                3,
+                2,
+                1,
+            ],
+        )
+        ");
+    }
+
+    #[test]
+    fn test_construct_pathological() {
+        // no document at all
+        let mut bytes = Vec::new();
+        Index::construct(&[] as &[&str], &mut bytes).unwrap();
+        let index = Index::from_bytes(&bytes).unwrap();
+        assert_eq!(index.search(&Search::new("kefir")), Vec::<&str>::new());
+
+        // the same word written in many ways, non-ascii bytes sorting after the ascii ones,
+        // documents with nothing to index and duplicated documents
+        let names = [
+            "KEFIR kefir Kéfir kéfir",
+            "zèbre zebre zébu zzz",
+            "",
+            "   ",
+            "!!! ??? ...",
+            "kefir",
+            "kefir",
+            "ε λ α",
+        ];
+        let mut bytes = Vec::new();
+        Index::construct(&names, &mut bytes).unwrap();
+        let mut from_iter = std::io::Cursor::new(Vec::new());
+        Index::construct_from_iter(names, &mut from_iter).unwrap();
+        assert_eq!(from_iter.into_inner(), bytes);
+
+        let index = Index::from_bytes(&bytes).unwrap();
+        insta::assert_debug_snapshot!(index.terms().collect::<Vec<_>>(), @r#"
+        [
+            (
+                "",
+                1,
+            ),
+            (
+                "kefir",
+                3,
+            ),
+            (
+                "zebre",
+                1,
+            ),
+            (
+                "zebu",
+                1,
+            ),
+            (
+                "zzz",
+                1,
+            ),
+            (
+                "α",
+                1,
+            ),
+            (
+                "ε",
+                1,
+            ),
+            (
+                "λ",
+                1,
+            ),
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search_ids(&Search::new("kefir")), @"
+        [
+            0,
+            5,
+            6,
+        ]
+        ");
+
+        let err = IndexError::from(std::io::Error::other("disk full"));
+        insta::assert_snapshot!(err, @"can't write the index: disk full");
+    }
+
+    #[test]
+    fn test_construct_to_path() {
+        let path = std::env::temp_dir().join("zearch_test_construct_to_path.zearch");
+        let index = create_small_index();
+        let names: Vec<&str> = (0..index.documents.len() as Id)
+            .map(|id| index.get_document(id).unwrap())
+            .collect();
+
+        let built = unsafe { Index::construct_to_path(&names, &path) }.unwrap();
+        let search = Search::new("kefir beau");
+        assert_eq!(built.search(&search), index.search(&search));
+
+        // it's the same file as the one built in memory
+        let mut expected = Vec::new();
+        index.write_into(&mut expected).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+
+        drop(built);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_utf8_keys() {
+        // an index built elsewhere with a key that is not valid utf-8
+        let mut build = MapBuilder::memory();
+        build.insert(b"kefir", 0).unwrap();
+        build.insert(b"kefi\xff", 1).unwrap();
+        let fst = build.into_inner().unwrap();
+
+        let mut bytes = Vec::new();
+        Index::write_header(&mut bytes).unwrap();
+        bytes.extend(2u32.to_be_bytes());
+        Index::write_slice(&mut bytes, b"kefir").unwrap();
+        Index::write_slice(&mut bytes, b"kefi").unwrap();
+        Index::write_bitmaps(
+            &mut bytes,
+            &[RoaringBitmap::from([0]), RoaringBitmap::from([1])],
+            &[
+                RoaringTreemap::from([position(0, 0)]),
+                RoaringTreemap::from([position(1, 0)]),
+            ],
+        )
+        .unwrap();
+        Index::write_slice(&mut bytes, &fst).unwrap();
+        Index::write_tail(
+            &mut bytes,
+            &BTreeMap::new(),
+            &RoaringBitmap::new(),
+            &[1, 1],
+            &[],
+        )
+        .unwrap();
+        let index = Index::from_bytes(&bytes).unwrap();
+
+        // the prefix search without typos goes through all the keys starting with the prefix
+        let mut search = Search::new("kefi");
+        search.with_typo_tolerance(TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.terms().collect::<Vec<_>>(), @r#"
+        [
+            (
+                "kefir",
+                1,
+            ),
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.suggest("kefiz"), @r#"
+        Some(
+            "kefir",
+        )
+        "#);
+    }
+
+    #[test]
+    fn test_out_of_bounds_ids() {
+        // an index with a single document and a single word
+        let index_bytes = |word_bitmap: u64, document: u32| {
+            let mut build = MapBuilder::memory();
+            build.insert(b"kefir", word_bitmap).unwrap();
+            let fst = build.into_inner().unwrap();
+
+            let mut bytes = Vec::new();
+            Index::write_header(&mut bytes).unwrap();
+            bytes.extend(1u32.to_be_bytes());
+            Index::write_slice(&mut bytes, b"kefir").unwrap();
+            Index::write_bitmaps(
+                &mut bytes,
+                &[RoaringBitmap::from([document])],
+                &[RoaringTreemap::from([position(document, 0)])],
+            )
+            .unwrap();
+            Index::write_slice(&mut bytes, &fst).unwrap();
+            Index::write_tail(
+                &mut bytes,
+                &BTreeMap::new(),
+                &RoaringBitmap::new(),
+                &[1],
+                &[],
+            )
+            .unwrap();
+            bytes
+        };
+
+        assert!(Index::from_bytes(&index_bytes(0, 0)).is_some());
+        // the word points to a bitmap that doesn't exist
+        assert!(Index::from_bytes(&index_bytes(1, 0)).is_none());
+        // the bitmap contains a document that doesn't exist
+        assert!(Index::from_bytes(&index_bytes(0, 1)).is_none());
+
+        // a corrupted fst is rejected by its checksum instead of panicking once it's read
+        let bytes = index_bytes(0, 0);
+        let mut build = MapBuilder::memory();
+        build.insert(b"kefir", 0).unwrap();
+        let fst = build.into_inner().unwrap();
+        let start = bytes
+            .windows(fst.len())
+            .position(|window| window == fst)
+            .unwrap();
+        for offset in 0..fst.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[start + offset] ^= 0b1010_0101;
+            assert!(Index::from_bytes(&corrupted).is_none(), "byte {offset}");
+        }
+    }
+
+    #[test]
+    fn test_arena() {
+        let index = create_small_index();
+        let documents: Vec<&str> = index.documents.iter().collect();
+        let mut arena = Index::new_in_memory_arena(&documents);
+
+        for query in ["tamo", "beau kef", "kefir le beau chien"] {
+            let search = Search::new(query);
+            assert_eq!(index.search(&search), arena.search(&search), "{query}");
+        }
+
+        let id = arena.insert(String::from("kefir le chat"));
+        arena.delete(0);
+        let other = Index::new_in_memory(&["un autre kefir"]);
+        let arena = arena.merge(other);
+        insta::assert_debug_snapshot!(arena.search(&Search::new("kefir le")), @r#"
+        [
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "kefir le chat",
+            "le plus beau c'est kefir",
+            "le petit kefir",
+            "kefir est un demi poney",
+            "un autre kefir",
+            "le double kef",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+        assert_eq!(arena.get_document(id), Some("kefir le chat"));
+        assert_eq!(arena.get_document(0), None);
+
+        let mut bytes = Vec::new();
+        arena.write_into(&mut bytes).unwrap();
+        let loaded = Index::from_bytes(&bytes).unwrap();
+        let search = Search::new("kefir");
+        assert_eq!(arena.search(&search), loaded.search(&search));
+    }
+
+    #[test]
+    // miri can't mmap files
+    #[cfg_attr(miri, ignore)]
+    fn test_load_mmap() {
+        let index = create_small_index();
+
+        let path = std::env::temp_dir().join("zearch_test_load_mmap.zearch");
+        index.save(&path).unwrap();
+        let loaded = unsafe { Index::load_mmap(&path) }.unwrap();
+        loaded.preload();
+        // nothing is mapped
+        index.preload();
+
+        for query in ["tamo", "beau kef", "kefir le beau chien"] {
+            let search = Search::new(query);
+            assert_eq!(index.search(&search), loaded.search(&search), "{query}");
+        }
+        drop(loaded);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    // miri can't mmap files
+    #[cfg_attr(miri, ignore)]
+    fn test_load_mmap_documents() {
+        let mut index = create_small_index();
+        index.delete(0);
+
+        let path = std::env::temp_dir().join("zearch_test_load_mmap_documents.zearch");
+        index.save(&path).unwrap();
+        let mut loaded = unsafe { Index::load_mmap_documents(&path) }.unwrap();
+        assert!(matches!(loaded.documents, Documents::Mapped(_)));
+
+        for query in ["tamo", "beau kef", "kefir le beau chien"] {
+            let search = Search::new(query);
+            assert_eq!(index.search(&search), loaded.search(&search), "{query}");
+        }
+
+        // the inserted documents can't be written in the file
+        let id = loaded.insert("Tamo le chat".to_string());
+        assert_eq!(loaded.get_document(id), Some("Tamo le chat"));
+        insta::assert_debug_snapshot!(loaded.search(&Search::new("tamo chat")), @r#"
+        [
+            "Tamo le chat",
+            "tamo est très beau aussi",
+        ]
+        "#);
+        drop(loaded);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ngrams() {
+        let mut index = create_small_index().with_ngrams();
+        let mut search = Search::new("kefirouent");
+        search.with_prefix(PrefixMode::None);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.candidate_stats(&search), @r#"
+        [
+            WordCandidateStats {
+                original: "kefirouent",
+                normalized: "kefirouent",
+                prefix: false,
+                documents: [
+                    0,
+                    0,
+                    2,
+                    0,
+                ],
+            },
+        ]
+        "#);
+
+        // the words inserted afterward are found before and after being merged in the fst
+        index.insert(String::from("tamo le poulpe"));
+        search.input = "poulep";
+        let pending = index.search_ids(&search);
+        insta::assert_debug_snapshot!(pending, @"
+        [
+            7,
+            9,
+            12,
+        ]
+        ");
+        index.merge_pending();
+        assert_eq!(index.search_ids(&search), pending);
+    }
+
+    #[test]
+    fn test_typo_tolerance() {
+        // the default must match the historical `(len / 3).min(3)`
+        let config = TypoConfig::default();
+        for len in 0..15 {
+            assert_eq!(
+                config.typos_for(&"a".repeat(len)),
+                (len / 3).min(3),
+                "{len}"
+            );
+        }
+
+        let index = create_small_index();
+        let mut search = Search::new("tami");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tamo est très beau aussi",
+            "Tamo le plus beau",
+        ]
+        "#);
+        search.with_typo_tolerance(TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @"[]");
+    }
+
+    #[test]
+    fn test_prefix_distance() {
+        let index = Index::new_in_memory(&["kefir", "kefirs", "kfirou", "kefirounet", "kefirouet"]);
+        // "kefir" is a whole word, "kefirs" is one typo away from it. "kfirou" is the prefix,
+        // "kefirounet" starts with it with one typo and "kefirouet" is shorter than the query
+        let search = Search::new("kefir kfirou");
+        let terms: Vec<_> = index
+            .get_candidates(&search)
+            .into_iter()
+            .map(|word| (word.original, word.terms))
+            .collect();
+        insta::assert_debug_snapshot!(terms, @r#"
+        [
+            (
+                "kefir",
+                [
+                    (
+                        "kefir",
+                        0,
+                    ),
+                    (
+                        "kefirs",
+                        1,
+                    ),
+                ],
+            ),
+            (
+                "kfirou",
+                [
+                    (
+                        "kefirouet",
+                        1,
+                    ),
+                    (
+                        "kefirounet",
+                        1,
+                    ),
+                    (
+                        "kfirou",
+                        0,
+                    ),
+                ],
+            ),
+        ]
+        "#);
+
+        // a word matched with deleted letters is shorter than the query
+        let search = Search::new("kefirounet");
+        let terms: Vec<_> = index
+            .get_candidates(&search)
+            .into_iter()
+            .map(|word| word.terms)
+            .collect();
+        insta::assert_debug_snapshot!(terms, @r#"
+        [
+            [
+                (
+                    "kefirouet",
+                    1,
+                ),
+                (
+                    "kefirounet",
+                    0,
+                ),
+            ],
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_transposition() {
+        // the automatons and the distance used to sort the words in the typo buckets must agree
+        for (query, word) in [
+            ("tmao", "tamo"),
+            ("kefri", "kefir"),
+            ("hte", "the"),
+            ("ab", "ba"),
+        ] {
+            let distance = damerau_levenshtein(query, word);
+            assert_eq!(distance, 1, "{query} {word}");
+            let dfa = levenshtein(1).build_dfa(query);
+            assert_eq!(
+                dfa.eval(word),
+                Distance::Exact(distance as u8),
+                "{query} {word}"
+            );
+        }
+
+        let index = create_small_index();
+        // "tmao" is searched as a whole word
+        let search = Search::new("tmao beau");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "tamo est très beau aussi",
+            "Tamo le plus beau",
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+        ]
+        "#);
+        // and "kefri" as a prefix
+        let search = Search::new("petit kefri");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le petit kefir",
+            "kefir le bon petit chien",
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+            "kefir est un demi poney",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_no_typo_fast_path() {
+        let mut index = create_small_index();
+        index.insert(String::from("le kefirinho"));
+
+        for word in ["kefir", "kef", "le", "tamo", "kefirinho", "ke", "chat", "k"] {
+            for prefix in [false, true] {
+                let mut fast = WordCandidate::new(word.to_string(), word.to_string(), 0, 0);
+                fast.prefix = prefix;
+                index.insert_word_matches(&mut fast, 0, None);
+
+                // the levenshtein automaton without typos must give the same result
+                let mut slow = WordCandidate::new(word.to_string(), word.to_string(), 0, 0);
+                slow.prefix = prefix;
+                let lev = match prefix {
+                    true => levenshtein(0).build_prefix_dfa(word),
+                    false => levenshtein(0).build_dfa(word),
+                };
+                index.insert_matches(&mut slow, &lev, None);
+
+                assert_eq!(fast.typos, slow.typos, "{word} {prefix}");
+                assert_eq!(fast.terms, slow.terms, "{word} {prefix}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_pagination() {
+        let index = create_small_index();
+        let mut search = Search::new("le beau kef");
+        let all = index.search(search.with_limit(100));
+        assert!(all.len() > 2);
+
+        let mut paginated = Vec::new();
+        search.with_limit(2);
+        for page in 0.. {
+            let results = index.search(search.with_offset(page * 2));
+            if results.is_empty() {
+                break;
+            }
+            paginated.extend(results);
+        }
+        assert_eq!(all, paginated);
+    }
+
+    #[test]
+    fn test_custom_ranking_rule() {
+        /// Returns the documents from the most recent to the oldest one
+        struct Newest {
+            ids: Vec<Id>,
+        }
+
+        impl RankingRuleImpl for Newest {
+            fn name(&self) -> &'static str {
+                "newest"
+            }
+
+            fn next(
+                &mut self,
+                prev: Option<&dyn RankingRuleImpl>,
+                words: &mut Vec<WordCandidate>,
+                _index: &dyn IndexView,
+            ) -> ControlFlow<RoaringBitmap, ()> {
+                if self.ids.is_empty() {
+                    self.ids = prev.unwrap().current_results(words).iter().collect();
+                }
+                match self.ids.pop() {
+                    Some(id) => ControlFlow::Break(RoaringBitmap::from_iter([id])),
+                    None => ControlFlow::Break(RoaringBitmap::new()),
+                }
+            }
+
+            fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+                self.ids.iter().copied().collect()
+            }
+
+            fn cleanup(&mut self, used: &RoaringBitmap) {
+                self.ids.retain(|id| !used.contains(*id));
+            }
+        }
+
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![
+            RankingRule::Word,
+            RankingRule::custom(|_| Box::new(Newest { ids: Vec::new() })),
+        ]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir est un demi poney",
+            "kefirounet a un gros nez",
+            "kefirounet se prends pour un poney",
+            "le petit kefir",
+            "le plus beau c'est kefir",
+            "kefir le beau chien",
+            "kefir le bon petit chien",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_stuck_ranking_rule() {
+        /// Returns the same document forever, the search never makes any progress
+        struct Stuck;
+
+        impl RankingRuleImpl for Stuck {
+            fn name(&self) -> &'static str {
+                "stuck"
+            }
+
+            fn next(
+                &mut self,
+                _prev: Option<&dyn RankingRuleImpl>,
+                _words: &mut Vec<WordCandidate>,
+                _index: &dyn IndexView,
+            ) -> ControlFlow<RoaringBitmap, ()> {
+                ControlFlow::Continue(())
+            }
+
+            fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+                // always the same document, it's only new the first time
+                RoaringBitmap::from([1])
+            }
+        }
+
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::custom(|_| Box::new(Stuck))]);
+        // the search gives up and returns what it found so far
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le bon petit chien",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_limit_stops_ranking() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word]);
+        for tiebreak in [false, true] {
+            if tiebreak {
+                search.with_tiebreak(|a, b| (a % 3).cmp(&(b % 3)));
+            }
+            let all = index.search_ids(search.with_offset(0).with_limit(20));
+            // the bucket is cut in the middle but the documents are the same
+            for offset in 0..3 {
+                for limit in 0..=all.len() {
+                    search.with_offset(offset).with_limit(limit);
+                    let expected: Vec<Id> = all.iter().copied().skip(offset).take(limit).collect();
+                    assert_eq!(index.search_ids(&search), expected, "{offset} {limit}");
+                }
+            }
+        }
+
+        // the documents of the bucket that won't be returned are not sorted
+        let documents: Vec<String> = (0..1000).map(|i| format!("kefir {i}")).collect();
+        let index = Index::new_in_memory(&documents);
+        let compared = |limit: usize| {
+            let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let counter = count.clone();
+            let mut search = Search::new("kefir");
+            search
+                .with_ranking_rules(vec![RankingRule::Word])
+                .with_limit(limit)
+                .with_tiebreak(move |a, b| {
+                    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    // shuffle the documents
+                    (a * 7919 % 1000).cmp(&(b * 7919 % 1000))
+                });
+            index.search(&search);
+            count.load(std::sync::atomic::Ordering::Relaxed)
+        };
+        assert!(compared(5) < compared(1000) / 2);
+    }
+
+    #[test]
+    fn test_exhaustive() {
+        let index = create_small_index();
+        let rules = [
+            vec![RankingRule::Word, RankingRule::Typo, RankingRule::Exact],
+            vec![RankingRule::Typo, RankingRule::Exact],
+            vec![RankingRule::Proximity],
+            vec![],
+        ];
+        for rules in rules {
+            let mut search = Search::new("kefir le");
+            search
+                .with_ranking_rules(rules.clone())
+                .with_limit(2)
+                .with_exhaustive(true);
+            // every matching document is ranked once the first ranking rule runs out of documents
+            let ids = index.search_ids(&search);
+            assert_eq!(ids.len(), index.count(&search), "{rules:?}");
+            assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+            let mut limited = search.clone();
+            limited.with_limit(usize::MAX).with_exhaustive(false);
+            assert_eq!(ids, index.search_ids(&limited), "{rules:?}");
+            // the offset still applies
+            search.with_offset(1);
+            assert_eq!(index.search_ids(&search), &ids[1..], "{rules:?}");
+        }
+    }
+
+    #[test]
+    fn test_max_buckets() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_limit(20);
+        let buckets: Vec<Vec<Id>> = index
+            .ranked_buckets(&search)
+            .map(|bucket| bucket.iter().collect())
+            .collect();
+        insta::assert_snapshot!(format!("{buckets:?}"), @"[[1, 2, 9], [4, 6], [7, 8]]");
+
+        // the documents of the buckets after the second one are omitted
+        search.with_max_buckets(2);
+        let expected: Vec<Id> = buckets[..2].concat();
+        assert_eq!(index.search_ids(&search), expected);
+        // the limit is reached first
+        search.with_limit(1);
+        assert_eq!(index.search_ids(&search), &expected[..1]);
+        search.with_limit(20).with_max_buckets(0);
+        assert_eq!(index.search_ids(&search), &[]);
+
+        // the count doesn't build the buckets, the omitted documents are counted
+        search.with_max_buckets(1);
+        assert_eq!(index.search_ids(&search).len(), 3);
+        assert_eq!(index.count(&search), 7);
+    }
+
+    #[test]
+    fn test_ranking_rules_configurations() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir le chien");
+        // without any ranking rule the documents containing every word are returned by id
+        let mut every_word = index.search_ids(search.with_match_mode(MatchMode::All));
+        every_word.sort_unstable();
+        let unsorted = index.search_ids(search.with_ranking_rules(vec![]));
+        assert_eq!(unsorted, every_word);
+        insta::assert_debug_snapshot!(unsorted, @"
+        [
+            1,
+            2,
+        ]
+        ");
+        search.with_match_mode(MatchMode::Any);
+
+        // every ranking rule can be used alone, first, or more than once
+        let rules = [
+            RankingRule::Word,
+            RankingRule::Typo,
+            RankingRule::Exact,
+            RankingRule::Proximity,
+            RankingRule::Field,
+            RankingRule::Frequency,
+            RankingRule::Position,
+            RankingRule::Length,
+            RankingRule::WholeWord,
+            RankingRule::Verbatim,
+            RankingRule::Boost,
+        ];
+        let results: Vec<String> = rules
+            .iter()
+            .map(|rule| vec![rule.clone()])
+            .chain([
+                vec![RankingRule::Exact, RankingRule::Word, RankingRule::Exact],
+                vec![RankingRule::Word, RankingRule::Word],
+                vec![RankingRule::Typo, RankingRule::Typo],
+            ])
+            .map(|rules| {
+                let ids = index.search_ids(search.with_ranking_rules(rules.clone()));
+                format!("{rules:?} {ids:?}")
+            })
+            .collect();
+        insta::assert_debug_snapshot!(results, @r#"
+        [
+            "[Word] [1, 2, 0, 4, 6, 9, 10]",
+            "[Typo] [1, 2]",
+            "[Exact] [1, 2]",
+            "[Proximity] [2, 1]",
+            "[Field] [1, 2]",
+            "[Frequency] [1, 2]",
+            "[Position] [1, 2]",
+            "[Length] [1, 2]",
+            "[WholeWord] [1, 2]",
+            "[Verbatim] [1, 2]",
+            "[Boost] [1, 2]",
+            "[Exact, Word, Exact] [1, 2, 9, 10, 0, 4, 6]",
+            "[Word, Word] [1, 2, 0, 4, 6, 9, 10]",
+            "[Typo, Typo] [1, 2]",
+        ]
+        "#);
+
+        // the ranking rules after the exact one sort its buckets
+        let index = Index::new_in_memory(&["kefir", "kefir kefir"]);
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Exact, RankingRule::Frequency]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir kefir",
+            "kefir",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_single_word_frequency() {
+        let names = [
+            "le chien de kefir",
+            "kefir mange le repas de kefir",
+            "kefir le kefir du kefir",
+            "kefirounet",
+        ];
+        let index = Index::new_in_memory(&names);
+        // the documents mentioning kefir the most come first, whatever their id
+        insta::assert_debug_snapshot!(index.search(&Search::new("kefir")), @r#"
+        [
+            "kefir le kefir du kefir",
+            "kefir mange le repas de kefir",
+            "le chien de kefir",
+            "kefirounet",
+        ]
+        "#);
+
+        // the ranking rules chosen explicitly are kept as is
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Typo]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le chien de kefir",
+            "kefir mange le repas de kefir",
+            "kefir le kefir du kefir",
+            "kefirounet",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_trace() {
+        let index = create_small_index();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let trace = events.clone();
+        let mut search = Search::new("kefir beau");
+        search
+            .with_ranking_rules(vec![RankingRule::Word, RankingRule::Typo])
+            .with_limit(3)
+            .with_trace(move |event| trace.lock().unwrap().push(format!("{event:?}")));
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le beau chien",
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(events.lock().unwrap(), @r#"
+        [
+            "Continue { ranking_rule: 0, name: \"word\" }",
+            "Bucket { ranking_rule: 1, name: \"typo\", documents: 0, returned: RoaringBitmap<[]> }",
+            "Bucket { ranking_rule: 1, name: \"typo\", documents: 2, returned: RoaringBitmap<[2, 4]> }",
+            "Bucket { ranking_rule: 1, name: \"typo\", documents: 0, returned: RoaringBitmap<[]> }",
+            "Exhausted { ranking_rule: 1, name: \"typo\" }",
+            "Continue { ranking_rule: 0, name: \"word\" }",
+            "Bucket { ranking_rule: 1, name: \"typo\", documents: 2, returned: RoaringBitmap<[0]> }",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_phrase_search() {
+        let mut index = create_small_index();
+        index.insert(String::from("le chien est plus beau que tamo"));
+
+        // "Tamo le plus beau" and "le plus beau c'est kefir" contain the phrase but the
+        // last document only contains the words scattered
+        insta::assert_debug_snapshot!(index.search(&Search::new("\"le plus beau\"")), @r#"
+        [
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+        ]
+        "#);
+        // the words are not in the right order
+        assert!(index.search(&Search::new("\"beau plus\"")).is_empty());
+        // the phrase can be combined with other words
+        insta::assert_debug_snapshot!(index.search(&Search::new("kefir \"plus beau\"")), @r#"
+        [
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+            "le chien est plus beau que tamo",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "kefir est un demi poney",
+            "le petit kefir",
+        ]
+        "#);
+        // the documents inserted after the index was built are searchable too
+        insta::assert_debug_snapshot!(index.search(&Search::new("\"chien est\"")), @r#"
+        [
+            "le chien est plus beau que tamo",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_search_highlighted() {
+        let index = create_small_index();
+
+        let highlight = |query: &str| -> Vec<Vec<&str>> {
+            index
+                .search_highlighted(&Search::new(query))
+                .into_iter()
+                .map(|doc| {
+                    doc.highlights
+                        .iter()
+                        .map(|range| &doc.document[range.clone()])
+                        .collect()
+                })
+                .collect()
+        };
 
-impl WordCandidate {
-    pub fn new(original: String, normalized: String, index: usize) -> Self {
-        Self {
-            original,
-            normalized,
-            index,
-            // we have a maximum of 3 typos
-            typos: vec![RoaringBitmap::new(); 4],
-        }
+        // the last word is a prefix, only the prefix is highlighted
+        insta::assert_debug_snapshot!(highlight("beau kef"), @r#"
+        [
+            [
+                "beau",
+                "kef",
+            ],
+            [
+                "kef",
+                "beau",
+            ],
+            [
+                "beau",
+            ],
+            [
+                "beau",
+            ],
+            [
+                "kef",
+            ],
+            [
+                "kef",
+            ],
+            [
+                "kef",
+            ],
+            [
+                "kef",
+            ],
+            [
+                "kef",
+            ],
+            [
+                "kef",
+            ],
+        ]
+        "#);
+        // the typos and the accents are highlighted
+        insta::assert_debug_snapshot!(highlight("tres"), @r#"
+        [
+            [
+                "très",
+            ],
+        ]
+        "#);
+        // the accent takes two bytes but is only one normalized byte
+        insta::assert_debug_snapshot!(highlight("tre"), @r#"
+        [
+            [
+                "trè",
+            ],
+            [
+                "pre",
+            ],
+        ]
+        "#);
+        insta::assert_debug_snapshot!(highlight("cest"), @r#"
+        [
+            [
+                "c'est",
+            ],
+            [
+                "c'est",
+            ],
+            [
+                "est",
+            ],
+            [
+                "est",
+            ],
+            [
+                "est",
+            ],
+        ]
+        "#);
     }
 
-    // Since the fst::Automaton doesn't tells us which automaton matched and with how many typos or prefixes
-    // we need to recompute the stuff ourselves and insert our shit in the right cell
-    pub fn insert_with_maybe_typo(&mut self, other: &str, bitmap: &RoaringBitmap) {
-        // TODO: why is this crate taking ownership of my value to do a read only operation :(
-        let distance = DamerauLevenshtein {
-            src: self.normalized.clone(),
-            // if we did a prefix query we shouldn't count the extra letters as typo
-            tar: other[0..other.len().min(self.normalized.len())].to_string(),
-            restricted: true,
-        }
-        .distance();
-
-        // distance shouldn't be able to go over 3 but we don't want any crash so let's ensure that
-        let distance = distance.min(3);
-        self.typos[distance] |= bitmap;
+    #[test]
+    fn test_search_snippets() {
+        let documents: &[&[&str]] = &[
+            &["Ce matin le petit chien est parti se promener dans la forêt avec son ami Kéfir le chat"],
+            &["Kefir"],
+            &["un titre sans rapport", "la longue description de kefir le chien"],
+        ];
+        let index = Index::new_in_memory_fields(documents);
+        insta::assert_debug_snapshot!(index.search_snippets(&Search::new("kefir"), 20), @r#"
+        [
+            "ami Kéfir le chat",
+            "Kefir",
+            "de kefir le",
+        ]
+        "#);
+        // the window with the most matched words is chosen
+        insta::assert_debug_snapshot!(index.search_snippets(&Search::new("kefir chien"), 30), @r#"
+        [
+            "le petit chien est parti se",
+            "description de kefir le chien",
+            "Kefir",
+        ]
+        "#);
     }
-}
 
-pub struct Search<'a> {
-    input: &'a str,
-    limit: usize,
-    ranking_rules: Vec<RankingRule>,
-}
+    #[test]
+    fn test_phrase_across_fields() {
+        // the fields past the 256th are merged in the last one
+        let mut many_fields = vec![""; 257];
+        many_fields[0] = "alpha";
+        many_fields[256] = "beta gamma";
+        let documents = [vec!["kefir le", "chien"], many_fields];
+        let index = Index::new_in_memory_fields(&documents);
 
-impl<'a> Search<'a> {
-    /// Create a new search requests from an input
-    pub fn new(input: &'a str) -> Self {
-        Self {
-            input,
-            limit: 10,
-            ranking_rules: vec![RankingRule::Word, RankingRule::Typo, RankingRule::Exact],
-        }
-    }
+        let search = |query: &str| index.search_ids(&Search::new(query));
+        // a phrase doesn't match the end of a field followed by the start of the next one
+        assert_eq!(search("\"le chien\""), &[] as &[Id]);
+        assert_eq!(search("\"alpha gamma\""), &[] as &[Id]);
+        assert_eq!(search("\"beta gamma\""), &[1]);
 
-    /// Customize the number of results you want to get back
-    pub fn with_limit(&mut self, limit: usize) -> &mut Self {
-        self.limit = limit;
-        self
+        // the merged fields get the weight of the last field
+        let mut weights = vec![1; 300];
+        weights[255] = 3;
+        let mut search = Search::new("gamma kefir");
+        search
+            .with_ranking_rules(vec![RankingRule::Word, RankingRule::Field])
+            .with_field_weights(weights);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            1,
+            0,
+        ]
+        ");
     }
-}
 
-fn normalize(s: &str) -> String {
-    s.chars()
-        .filter_map(|c| match c.to_ascii_lowercase() {
-            'á' | 'â' | 'à' | 'ä' => Some('a'),
-            'é' | 'ê' | 'è' | 'ë' => Some('e'),
-            'í' | 'î' | 'ì' | 'ï' => Some('i'),
-            'ó' | 'ô' | 'ò' | 'ö' => Some('o'),
-            'ú' | 'û' | 'ù' | 'ü' => Some('u'),
-            c if c.is_ascii_punctuation() || !c.is_ascii_graphic() || c.is_ascii_control() => None,
-            c => Some(c),
-        })
-        .collect()
-}
+    #[test]
+    fn test_normalize() {
+        insta::assert_debug_snapshot!(
+            ["café", "Straße", "Łódź", "ÉCOLE", "Привет", "ΣΟΦΊΑ", "c'est", "l'œuvre", "ÀÆŒ"]
+                .map(normalize),
+            @r#"
+        [
+            "cafe",
+            "strasse",
+            "lodz",
+            "ecole",
+            "привет",
+            "σοφια",
+            "cest",
+            "loeuvre",
+            "aaeoe",
+        ]
+        "#
+        );
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let names: &[&str] = &[
+            "Straße des 17. Juni",
+            "Łódź Fabryczna",
+            "Café de Flore",
+            "ÉCOLE NORMALE",
+            "ΣΟΦΊΑ",
+        ];
+        let index = Index::new_in_memory(names);
+        // the uppercase letters with diacritics must be folded like the lowercase ones
+        for (query, expected) in [
+            ("strasse", 0),
+            ("lodz", 1),
+            ("cafe", 2),
+            ("café", 2),
+            ("CAFÉ", 2),
+            ("ecole", 3),
+            ("école", 3),
+            ("σοφια", 4),
+        ] {
+            assert_eq!(index.search_ids(&Search::new(query)), [expected], "{query}");
+        }
+    }
 
-    fn create_small_index() -> Index<'static> {
-        let names = [
-            "Tamo le plus beau",
+    #[test]
+    fn test_stop_words() {
+        let index = create_small_index();
+        let stop_words: HashSet<String> = ["le", "un", "est"].map(String::from).into();
+
+        let mut search = Search::new("le chien");
+        search.with_stop_words(stop_words.clone());
+        // "le" is not searched at all
+        let words: Vec<_> = index
+            .get_candidates(&search)
+            .into_iter()
+            .map(|word| word.original)
+            .collect();
+        assert_eq!(words, ["chien"]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
             "kefir le bon petit chien",
             "kefir le beau chien",
+        ]
+        "#);
+
+        // there is only stop words, they're searched anyway
+        let mut search = Search::new("est un");
+        search.with_stop_words(stop_words);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "mais il est un peu con",
+            "kefir est un demi poney",
+            "kefirounet a un gros nez",
+            "kefirounet se prends pour un poney",
             "tamo est très beau aussi",
             "le plus beau c'est kefir",
-            "mais il est un peu con",
-            "le petit kefir",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let names: &[&str] = &[
+            "Apple announces a new phone",
+            "an apple a day",
+            "call apple.get() first",
+            "my Apple pie",
+        ];
+        let index = Index::new_in_memory(names);
+
+        let search = |query: &str| {
+            let mut search = Search::new(query);
+            search.with_verbatim(true);
+            index.search(&search)
+        };
+        insta::assert_debug_snapshot!(index.search(&Search::new("Apple")), @r#"
+        [
+            "Apple announces a new phone",
+            "an apple a day",
+            "my Apple pie",
+            "call apple.get() first",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(search("Apple"), @r#"
+        [
+            "Apple announces a new phone",
+            "my Apple pie",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(search("apple.get()"), @r#"
+        [
+            "call apple.get() first",
+        ]
+        "#);
+        // no prefix and no typos
+        insta::assert_debug_snapshot!(search("Appl"), @"[]");
+        insta::assert_debug_snapshot!(search("\"my Apple\""), @r#"
+        [
+            "my Apple pie",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(search("\"my apple\""), @"[]");
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let index = create_small_index();
+        let mut search = Search::new("kef*net");
+        search.with_wildcards(true);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
             "kefirounet se prends pour un poney",
             "kefirounet a un gros nez",
+        ]
+        "#);
+
+        let mut search = Search::new("le ?efir");
+        search.with_wildcards(true);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le plus beau c'est kefir",
+            "le petit kefir",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
             "kefir est un demi poney",
+            "Tamo le plus beau",
             "le double kef",
-            "les keftas c'est bon aussi",
-        ];
-        Index::new_in_memory(names.as_slice()).unwrap()
+        ]
+        "#);
+
+        // without the flag the wildcards are dropped by the normalization
+        insta::assert_debug_snapshot!(index.search(&Search::new("kef*net")), @"[]");
     }
 
     #[test]
-    fn test_search_with_only_word() {
+    fn test_candidate_stats() {
         let index = create_small_index();
-        let mut search = Search::new("tamo");
-        search.ranking_rules = vec![RankingRule::Word];
+        let stats = index.candidate_stats(&Search::new("le beua kefir"));
+        insta::assert_debug_snapshot!(stats, @r#"
+        [
+            WordCandidateStats {
+                original: "le",
+                normalized: "le",
+                prefix: false,
+                documents: [
+                    6,
+                ],
+            },
+            WordCandidateStats {
+                original: "beua",
+                normalized: "beua",
+                prefix: false,
+                documents: [
+                    0,
+                    4,
+                ],
+            },
+            WordCandidateStats {
+                original: "kefir",
+                normalized: "kefir",
+                prefix: true,
+                documents: [
+                    7,
+                    0,
+                ],
+            },
+        ]
+        "#);
+    }
 
-        insta::assert_debug_snapshot!(index.search(&search), @r###"
+    #[test]
+    fn test_unmatched_words() {
+        let index = create_small_index();
+        insta::assert_debug_snapshot!(index.unmatched_words(&Search::new("Beau xyzzy")), @r#"
+        [
+            "xyzzy",
+        ]
+        "#);
+        // the typos and the prefix count as a match
+        insta::assert_debug_snapshot!(index.unmatched_words(&Search::new("xyzzy beua kefirou")), @r#"
+        [
+            "xyzzy",
+        ]
+        "#);
+        // a phrase is unmatched when its words never appear contiguously
+        insta::assert_debug_snapshot!(index.unmatched_words(&Search::new("\"beau chien\" \"chien beau\"")), @r#"
+        [
+            "chien beau",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_word_typos() {
+        let index = create_small_index();
+        // "tamo" allows a typo by default
+        insta::assert_debug_snapshot!(index.search(&Search::new("tami plus")), @r#"
         [
             "Tamo le plus beau",
             "tamo est très beau aussi",
+            "le plus beau c'est kefir",
         ]
-        "###);
+        "#);
 
-        // "tamo est" was matched first and then tamo alone
-        let mut search = Search::new("tamo est");
-        search.ranking_rules = vec![RankingRule::Word];
-        insta::assert_debug_snapshot!(index.search(&search), @r###"
+        let mut search = Search::new("tami plus");
+        search.with_word_typos([(String::from("TAMI"), 0)].into());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "Tamo le plus beau",
+            "le plus beau c'est kefir",
+        ]
+        "#);
+
+        // the last word is still a prefix but "kef" is one typo away
+        let mut search = Search::new("kefi");
+        search.with_word_typos([(String::from("kefi"), 0)].into());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "le plus beau c'est kefir",
+            "le petit kefir",
+            "kefir est un demi poney",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+        ]
+        "#);
+
+        // and a short word can get more typos than its length allows
+        let mut search = Search::new("nz");
+        insta::assert_debug_snapshot!(index.search(&search), @"[]");
+        search.with_word_typos([(String::from("nz"), 1)].into());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet a un gros nez",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_tiebreak() {
+        let index = create_small_index();
+        // the documents of a bucket are returned by ascending id by default
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word]);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            1,
+            2,
+            4,
+            6,
+            7,
+            8,
+            9,
+        ]
+        ");
+
+        // the most recent documents first
+        search.with_tiebreak(|a, b| b.cmp(&a));
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            9,
+            8,
+            7,
+            6,
+            4,
+            2,
+            1,
+        ]
+        ");
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir est un demi poney",
+                "kefirounet a un gros nez",
+                "kefirounet se prends pour un poney",
+                "le petit kefir",
+                "le plus beau c'est kefir",
+                "kefir le beau chien",
+                "kefir le bon petit chien",
+            ],
+        ]
+        "#);
+
+        // the ranking rules still come first, the tiebreak only sorts within the buckets
+        let mut search = Search::new("kefir");
+        search.with_tiebreak(|a, b| b.cmp(&a));
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir est un demi poney",
+                "kefir le beau chien",
+                "kefir le bon petit chien",
+            ],
+            [
+                "le petit kefir",
+                "le plus beau c'est kefir",
+            ],
+            [
+                "kefirounet a un gros nez",
+                "kefirounet se prends pour un poney",
+            ],
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_negative_words() {
+        let index = create_small_index();
+        insta::assert_debug_snapshot!(index.search(&Search::new("beau -chien")), @r#"
         [
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
             "tamo est très beau aussi",
+        ]
+        "#);
+        // the negative words don't accept typos nor prefixes
+        insta::assert_debug_snapshot!(index.search(&Search::new("beau -chiens -tam")), @r#"
+        [
+            "le plus beau c'est kefir",
             "Tamo le plus beau",
+            "kefir le beau chien",
+            "tamo est très beau aussi",
         ]
-        "###);
+        "#);
+        // a query of only negative words returns all the other documents
+        let search = Search::new("-kefir -beau");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "mais il est un peu con",
+            "kefirounet se prends pour un poney",
+            "kefirounet a un gros nez",
+            "le double kef",
+            "les keftas c'est bon aussi",
+        ]
+        "#);
+        assert_eq!(index.count(&search), 5);
+    }
 
-        // "kefir" was removed right after we found no matches for both matches
-        // and thus no prefix search was ran and we missed kefirounet
-        let mut search = Search::new("beau kefir");
-        search.ranking_rules = vec![RankingRule::Word];
-        insta::assert_debug_snapshot!(index.search(&search), @r###"
+    #[test]
+    fn test_boolean_operators() {
+        let index = create_small_index();
+        let matches = |query: &str| {
+            let mut search = Search::new(query);
+            search.with_boolean_operators(true).with_limit(20);
+            let mut ids = index.search_ids(&search);
+            ids.sort_unstable();
+            format!("{query}: {ids:?}")
+        };
+        let queries = [
+            "(kefir OR tamo) AND beau",
+            "kefir OR tamo beau",
+            "beau -(kefir OR chien)",
+            "-(kefir OR beau OR un)",
+            "petit (\"le bon\" OR -chien)",
+            "AND OR",
+        ];
+        insta::assert_debug_snapshot!(queries.map(matches), @r#"
+        [
+            "(kefir OR tamo) AND beau: [0, 2, 3, 4]",
+            "kefir OR tamo beau: [0, 1, 2, 3, 4, 6, 9]",
+            "beau -(kefir OR chien): [0, 3]",
+            "-(kefir OR beau OR un): [10, 11]",
+            "petit (\"le bon\" OR -chien): [1, 6]",
+            "AND OR: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]",
+        ]
+        "#);
+
+        // the documents are still ranked by the words they match
+        let mut search = Search::new("(kefir OR tamo) AND beau");
+        search.with_boolean_operators(true);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
         [
             "kefir le beau chien",
             "le plus beau c'est kefir",
             "Tamo le plus beau",
             "tamo est très beau aussi",
         ]
-        "###);
+        "#);
+    }
+
+    #[test]
+    fn test_scope() {
+        let index = create_small_index();
+        let even: RoaringBitmap = (0..12).step_by(2).collect();
+
+        let mut search = Search::new("kefir");
+        search.with_scope(even.clone());
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            2,
+            4,
+            6,
+            8,
+        ]
+        ");
+        assert_eq!(index.count(&search), 4);
+
+        // the offset and the limit apply to the scoped documents
+        search.with_offset(1).with_limit(1);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            4,
+        ]
+        ");
+
+        // a placeholder search returns the whole scope
+        let mut search = Search::new("");
+        search.with_scope(even);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            0,
+            2,
+            4,
+            6,
+            8,
+            10,
+        ]
+        ");
+    }
+
+    #[test]
+    fn test_infix() {
+        let index = create_small_index();
+        let mut search = Search::new("fir");
+        insta::assert_debug_snapshot!(index.search(&search), @"[]");
+
+        search.with_infix(true);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le bon petit chien",
+                "kefir le beau chien",
+                "le plus beau c'est kefir",
+                "le petit kefir",
+                "kefir est un demi poney",
+            ],
+            [
+                "kefirounet se prends pour un poney",
+                "kefirounet a un gros nez",
+            ],
+        ]
+        "#);
+
+        // the words matched with typos come first, "nez" and "pet" are one typo away from "net"
+        let mut search = Search::new("net");
+        search
+            .with_infix(true)
+            .with_ranking_rules(vec![RankingRule::Typo]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le bon petit chien",
+                "le petit kefir",
+                "kefirounet a un gros nez",
+            ],
+            [
+                "kefirounet se prends pour un poney",
+            ],
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut index = create_small_index();
+        index.delete(3);
+        let candidates = RoaringBitmap::from([0, 3, 5, 7, 9, 42]);
+
+        // the matching candidates are ranked, then come the others
+        let search = Search::new("kefir poney");
+        insta::assert_debug_snapshot!(index.rank(&search, &candidates), @"
+        [
+            9,
+            7,
+            0,
+            5,
+        ]
+        ");
+
+        let mut search = Search::new("kefir poney");
+        search.with_offset(1).with_limit(2);
+        insta::assert_debug_snapshot!(index.rank(&search, &candidates), @"
+        [
+            7,
+            0,
+        ]
+        ");
+
+        // the candidates matching only some of the words are discarded, not ranked last
+        let mut search = Search::new("kefir poney");
+        search.with_match_mode(MatchMode::All);
+        insta::assert_debug_snapshot!(index.rank(&search, &candidates), @"
+        [
+            9,
+            0,
+            5,
+        ]
+        ");
+
+        // the candidates ranked last are deduplicated with the others
+        let mut search = Search::new("kefir poney");
+        search.with_dedup(|_| 0);
+        insta::assert_debug_snapshot!(index.rank(&search, &candidates), @"
+        [
+            9,
+        ]
+        ");
+    }
+
+    #[test]
+    fn test_filter() {
+        let index = create_small_index();
+        let mut search = Search::new("kefir");
+        search.with_limit(2).with_filter(|id| id % 2 == 0);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            2,
+            4,
+        ]
+        ");
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let names: &[&str] = &[
+            "the new york times",
+            "a television in new jersey",
+            "my tv from york",
+            "nyc",
+        ];
+        let index = Index::new_in_memory(names);
+        let synonyms: HashMap<String, Vec<String>> = [
+            (String::from("TV"), vec![String::from("television")]),
+            (String::from("nyc"), vec![String::from("new york")]),
+        ]
+        .into();
+
+        let search = |query: &str, synonyms: &HashMap<String, Vec<String>>| {
+            let mut search = Search::new(query);
+            search.with_synonyms(synonyms.clone());
+            index.search(&search)
+        };
+        insta::assert_debug_snapshot!(search("tv", &HashMap::new()), @r#"
+        [
+            "my tv from york",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(search("tv", &synonyms), @r#"
+        [
+            "a television in new jersey",
+            "my tv from york",
+        ]
+        "#);
+        // "new" and "york" must be next to each others
+        insta::assert_debug_snapshot!(search("nyc", &synonyms), @r#"
+        [
+            "the new york times",
+            "nyc",
+        ]
+        "#);
     }
 }
@@ -0,0 +1,141 @@
+//! The n-grams of the words of the index, see [`Index::with_ngrams`](crate::Index::with_ngrams).
+//! Every word is split in overlapping sequences of [`N`] characters, padded at both ends so the
+//! first and last letters count as much as the others, and a word of the query retrieves the
+//! words of the index sharing its n-grams. Editing a character changes at most [`N`] n-grams,
+//! thus the number of n-grams that differ between two words gives an estimate of their number
+//! of typos. It can't be less than the difference of their lengths either. A transposition
+//! changes more n-grams than a substitution and counts as two typos.
+use std::collections::HashMap;
+
+use fst::{Map, Streamer};
+use roaring::RoaringBitmap;
+
+/// The number of characters of the n-grams
+pub(crate) const N: usize = 3;
+
+/// Pads the words, it can't be part of a normalized word
+const PADDING: char = '\0';
+
+type Gram = [char; N];
+
+pub(crate) struct NGrams {
+    // the ids of the words containing every n-gram, the id of a word is its value in the fst
+    grams: HashMap<Gram, RoaringBitmap>,
+    // the words by id, the fst can only find the id of a word
+    words: Vec<String>,
+}
+
+impl NGrams {
+    /// Split every word of the fst in n-grams
+    pub fn new(fst: &Map<impl AsRef<[u8]>>) -> Self {
+        let mut ngrams = NGrams {
+            grams: HashMap::new(),
+            words: Vec::new(),
+        };
+        let mut stream = fst.stream();
+        while let Some((word, id)) = stream.next() {
+            // the keys that aren't valid utf-8 can't be searched anyway
+            if let Ok(word) = std::str::from_utf8(word) {
+                ngrams.insert(id as u32, word);
+            }
+        }
+        ngrams
+    }
+
+    /// Add a word that was just inserted in the fst with this id
+    pub fn insert(&mut self, id: u32, word: &str) {
+        if self.words.len() <= id as usize {
+            self.words.resize(id as usize + 1, String::new());
+        }
+        self.words[id as usize] = word.to_string();
+        for gram in grams(word) {
+            self.grams.entry(gram).or_default().insert(id);
+        }
+    }
+
+    /// Returns the words sharing enough n-grams with `word` to have at most `max_typos` typos,
+    /// along with their id and their estimated number of typos
+    pub fn matches(&self, word: &str, max_typos: usize) -> Vec<(&str, u32, usize)> {
+        let (query, len) = (grams(word), word.chars().count());
+        let mut shared: HashMap<u32, usize> = HashMap::new();
+        for gram in &query {
+            for id in self.grams.get(gram).into_iter().flatten() {
+                *shared.entry(id).or_default() += 1;
+            }
+        }
+
+        shared
+            .into_iter()
+            .filter_map(|(id, shared)| {
+                let matched = self.words[id as usize].as_str();
+                let lengths = (len, matched.chars().count());
+                let typos = estimate_typos((query.len(), grams(matched).len()), lengths, shared);
+                (typos <= max_typos).then_some((matched, id, typos))
+            })
+            .collect()
+    }
+}
+
+/// Returns the estimated number of typos between two words, or `None` if there is more than
+/// `max_typos`. It's used on the words that aren't in the fst yet.
+pub(crate) fn typos_between(word: &str, other: &str, max_typos: usize) -> Option<usize> {
+    let lengths = (word.chars().count(), other.chars().count());
+    let (word, other) = (grams(word), grams(other));
+    let shared = word.iter().filter(|gram| other.contains(gram)).count();
+    let typos = estimate_typos((word.len(), other.len()), lengths, shared);
+    (typos <= max_typos).then_some(typos)
+}
+
+/// Every typo changes at most `N` n-grams of each word and inserts or deletes at most one letter
+fn estimate_typos(grams: (usize, usize), lengths: (usize, usize), shared: usize) -> usize {
+    let changed = (grams.0.max(grams.1) - shared).div_ceil(N);
+    changed.max(lengths.0.abs_diff(lengths.1))
+}
+
+/// The distinct n-grams of the padded word
+fn grams(word: &str) -> Vec<Gram> {
+    let padding = std::iter::repeat_n(PADDING, N - 1);
+    let chars: Vec<char> = padding.clone().chain(word.chars()).chain(padding).collect();
+    let mut grams: Vec<Gram> = chars
+        .windows(N)
+        .map(|gram| gram.try_into().unwrap())
+        .collect();
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ngram_typos() {
+        let typos: Vec<_> = [
+            ("kefirounet", "kefirounet"),
+            ("kefirounet", "kefiromnet"),
+            ("kefirounet", "kefirounets"),
+            ("kefirounet", "kefiruonet"),
+            ("kefirounet", "kefir"),
+            ("tamo", "tamo"),
+            ("tamo", "tama"),
+        ]
+        .into_iter()
+        .map(|(word, other)| {
+            let typos = typos_between(word, other, usize::MAX).unwrap();
+            format!("{word} {other} {typos}")
+        })
+        .collect();
+        insta::assert_debug_snapshot!(typos, @r#"
+        [
+            "kefirounet kefirounet 0",
+            "kefirounet kefiromnet 1",
+            "kefirounet kefirounets 1",
+            "kefirounet kefiruonet 2",
+            "kefirounet kefir 5",
+            "tamo tamo 0",
+            "tamo tama 1",
+        ]
+        "#);
+    }
+}
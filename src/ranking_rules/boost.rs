@@ -0,0 +1,120 @@
+//! The boost ranking rule ranks up the documents with the highest static boost,
+//! see [`Index::with_boosts`](crate::Index::with_boosts). The ranking is made of
+//! buckets, not of scores, thus the boost isn't multiplied with anything: the
+//! documents with the same boost tie and the position of the rule decides how
+//! it composes with the others. Right after the word ranking rule, which must
+//! stay first, a featured document comes before the documents matching as many
+//! words of the query, even the ones matching them without typos. Last, it only
+//! sorts the documents the previous ranking rules found equally relevant.
+use std::ops::ControlFlow;
+
+use roaring::RoaringBitmap;
+
+use crate::{Id, IndexView, WordCandidate};
+
+use super::{Buckets, RankingRuleImpl};
+
+pub struct Boost {
+    buckets: Buckets,
+}
+
+impl Boost {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(current: &RoaringBitmap, index: &dyn IndexView) -> Vec<RoaringBitmap> {
+        let mut boosts: Vec<(f32, Id)> = current
+            .iter()
+            .map(|id| (index.document_boost(id), id))
+            .collect();
+        boosts.sort_by(|(left, _), (right, _)| right.total_cmp(left));
+
+        boosts
+            .chunk_by(|(left, _), (right, _)| left == right)
+            .map(|bucket| bucket.iter().map(|(_, id)| *id).collect())
+            .collect()
+    }
+}
+
+impl RankingRuleImpl for Boost {
+    fn name(&self) -> &'static str {
+        "boost"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets
+            .next(prev, words, |current| Self::compute_buckets(current, index))
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_boost() {
+        let documents = [
+            ("kefir le chien", 1.0),
+            ("kefyr le chien de luxe", 3.0),
+            ("le petit kefir", 1.0),
+            ("kefir", 0.5),
+        ];
+        let mut index = Index::new_in_memory_boosted(&documents);
+        index.insert(String::from("le kefir du jour"));
+
+        // right after the word ranking rule, the featured documents come first despite their typos
+        let mut search = Search::new("kefir chien");
+        search.with_ranking_rules(vec![
+            RankingRule::Word,
+            RankingRule::Boost,
+            RankingRule::Typo,
+        ]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefyr le chien de luxe",
+            "kefir le chien",
+            "le petit kefir",
+            "le kefir du jour",
+            "kefir",
+        ]
+        "#);
+
+        // last, the boost only sorts the documents found equally relevant
+        search.with_ranking_rules(vec![
+            RankingRule::Word,
+            RankingRule::Typo,
+            RankingRule::Boost,
+        ]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le chien",
+            "kefyr le chien de luxe",
+            "le petit kefir",
+            "le kefir du jour",
+            "kefir",
+        ]
+        "#);
+
+        // the boosts are saved with the index
+        let mut bytes = Vec::new();
+        index.write_into(&mut bytes).unwrap();
+        let loaded = Index::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.search(&search), index.search(&search));
+    }
+}
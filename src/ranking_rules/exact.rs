@@ -5,28 +5,79 @@
 //! but it also greatly improve the user experience since typing a name
 //! with an accent and getting the misspeled version first make you want
 //! to kill someone for example.
-//! Since it's the last ranking rule, its bucket shouldn't be that big
+//! Since it's usually the last ranking rule, its bucket shouldn't be that big
 //! thus it's not a problem to spend a lot of time going through all
-//! the IDs of the previous ranking rule.
+//! the IDs of the previous ranking rule. The next ranking rules, if any, sort its buckets.
+//!
+//! What it compares exactly: the first word of the query, as it was written, with the first
+//! word of the document, cut to the length of the document word. The documents are then
+//! sorted by the number of typos between the two, up to [`MAX_TYPOS`]. The extra letters of a longer
+//! document word count as typos, thus for the query "kefir", "kefir le chien" comes first,
+//! then "le petit kefyr" because "ke" is one typo away from "le", and "kefirounet" last.
+//! The other words of the query and of the document are not looked at, to rank up
+//! the whole words over the prefixes wherever they are, see [`super::whole_word`].
 use std::ops::ControlFlow;
 
 use roaring::RoaringBitmap;
-use text_distance::DamerauLevenshtein;
 
-use crate::{Index, WordCandidate};
+use crate::{distance::damerau_levenshtein, IndexView, WordCandidate, MAX_TYPOS};
 
-use super::RankingRuleImpl;
+use super::{Buckets, RankingRuleImpl};
 
 pub struct Exact {
-    buckets: Vec<RoaringBitmap>,
+    buckets: Buckets,
 }
 
 impl Exact {
     pub fn new() -> Self {
         Self {
-            buckets: Vec::new(),
+            buckets: Buckets::new(),
         }
     }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        let mut words: Vec<&WordCandidate> = words.iter().collect();
+        words.sort_by_key(|word| word.index);
+
+        // the documents with more typos than a word can have share the last bucket
+        let mut buckets = vec![RoaringBitmap::new(); MAX_TYPOS + 1];
+
+        for id in current.iter() {
+            let mut distance = 0;
+
+            let mut words = words.iter().peekable();
+            for (id, word) in index
+                .tokenizer()
+                .tokenize(index.text(id))
+                .into_iter()
+                .enumerate()
+            {
+                match words.peek() {
+                    Some(WordCandidate {
+                        original, index, ..
+                    }) if *index == id => {
+                        distance += damerau_levenshtein(
+                            // Since we're merging two string it's possible that the smallest one falls in the middle of a character of the second one
+                            &String::from_utf8_lossy(
+                                &original.as_bytes()[0..word.len().min(original.len())],
+                            ),
+                            word,
+                        );
+                    }
+                    // we're not looking at the same word
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+
+            buckets[distance.min(MAX_TYPOS)].insert(id);
+        }
+        buckets
+    }
 }
 
 impl RankingRuleImpl for Exact {
@@ -38,66 +89,18 @@ impl RankingRuleImpl for Exact {
         &mut self,
         prev: Option<&dyn RankingRuleImpl>,
         words: &mut Vec<WordCandidate>,
-        index: &Index,
+        index: &dyn IndexView,
     ) -> ControlFlow<RoaringBitmap, ()> {
-        // We're the last ranking rule, we should always break
-
-        if self.buckets.is_empty() {
-            let current = prev.unwrap().current_results(words);
-            let mut words: Vec<&WordCandidate> = words.iter().collect();
-
-            words.sort_by_key(|word| word.index);
-
-            // we won't generate more than 4 buckets
-            self.buckets = vec![RoaringBitmap::new(); 4];
-
-            for id in current.iter() {
-                let mut distance = 0;
-
-                let mut words = words.iter().peekable();
-                for (id, word) in index.documents[id as usize].split_whitespace().enumerate() {
-                    match words.peek() {
-                        Some(WordCandidate {
-                            original, index, ..
-                        }) if *index == id => {
-                            distance += DamerauLevenshtein {
-                                // Since we're merging two string it's possible that the smallest one falls in the middle of a character of the second one
-                                src: String::from_utf8_lossy(
-                                    &original.as_bytes()[0..word.len().min(original.len())],
-                                )
-                                .into_owned(),
-                                tar: word.to_string(),
-                                restricted: true,
-                            }
-                            .distance();
-                        }
-                        // we're not looking at the same word
-                        Some(_) => continue,
-                        None => break,
-                    }
-                }
-
-                let idx = distance.min(3);
-                self.buckets[idx].insert(id as u32);
-            }
-            self.buckets.retain(|bucket| !bucket.is_empty());
-            self.buckets.reverse();
-        }
-
-        match self.buckets.pop() {
-            Some(bucket) => ControlFlow::Break(bucket),
-            // we have nothing to return and the previous ranking rule doesn't either
-            None => ControlFlow::Break(RoaringBitmap::new()),
-        }
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
     }
 
-    fn current_results(&self, _words: &Vec<WordCandidate>) -> RoaringBitmap {
-        self.buckets.first().cloned().unwrap_or_default()
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
     }
 
     fn cleanup(&mut self, used: &RoaringBitmap) {
-        for bucket in self.buckets.iter_mut() {
-            *bucket -= used;
-        }
+        self.buckets.cleanup(used);
     }
 }
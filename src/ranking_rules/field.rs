@@ -0,0 +1,154 @@
+//! The field ranking rule ranks up the documents matching the query
+//! in their most important fields, like a title over a description.
+//! Every query word is worth the weight of the best field it was found in,
+//! and the documents are sorted by the sum of the weights of the query words.
+//! The position of every word in the index contains its field, thus we don't
+//! need to read the documents.
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    ops::{ControlFlow, Range},
+};
+
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::{field_positions, position, Id, IndexView, WordCandidate, MAX_FIELD};
+
+use super::{positions_in, Buckets, RankingRuleImpl};
+
+/// The weight of the fields not specified by the search
+const DEFAULT_WEIGHT: u32 = 1;
+
+pub struct Field {
+    weights: Vec<u32>,
+    buckets: Buckets,
+}
+
+impl Field {
+    pub fn new(weights: Vec<u32>) -> Self {
+        Self {
+            weights,
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        weights: &[u32],
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        // the positions of all the terms matched by every query word
+        let words: Vec<Vec<&RoaringTreemap>> = words
+            .iter()
+            .map(|word| {
+                word.terms
+                    .iter()
+                    .filter_map(|(term, _)| index.word_positions(term))
+                    .collect()
+            })
+            .collect();
+
+        let mut buckets: BTreeMap<Reverse<u32>, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let weight = words
+                .iter()
+                .map(|positions| Self::best_weight(weights, id, positions))
+                .sum();
+            buckets.entry(Reverse(weight)).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+
+    /// The weight of the best field of the document containing one of the positions
+    fn best_weight(weights: &[u32], id: Id, positions: &[&RoaringTreemap]) -> u32 {
+        let contains = |range: Range<u64>| {
+            positions
+                .iter()
+                .any(|positions| !positions_in(positions, range.clone()).is_empty())
+        };
+
+        let mut best = None;
+        // the fields past the last one are merged in it, it gets the weight of the first of them
+        let fields = weights.len().min(MAX_FIELD as usize + 1);
+        for (field, weight) in weights[..fields].iter().enumerate() {
+            if contains(field_positions(id, field)) {
+                best = best.max(Some(*weight));
+            }
+        }
+        // the fields without weight
+        if fields <= MAX_FIELD as usize {
+            let start = field_positions(id, fields).start;
+            if contains(start..position(id + 1, 0)) {
+                best = best.max(Some(DEFAULT_WEIGHT));
+            }
+        }
+
+        best.unwrap_or_default()
+    }
+}
+
+impl RankingRuleImpl for Field {
+    fn name(&self) -> &'static str {
+        "field"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        let weights = &self.weights;
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(weights, current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_field_rr() {
+        let documents = [
+            ["Le chien", "Tamo est le plus beau"],
+            ["Tamo", "le plus beau chien"],
+            ["Kefir", "il est beau aussi"],
+        ];
+        let index = Index::new_in_memory_fields(&documents);
+        assert_eq!(
+            index.get_fields(1).unwrap().collect::<Vec<_>>(),
+            ["Tamo", "le plus beau chien"]
+        );
+
+        let mut search = Search::new("tamo");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Field]);
+        // without any weight all the fields are equals and the documents are sorted by id
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            0,
+            1,
+        ]
+        ");
+
+        // the title is more important than the body
+        search.with_field_weights(vec![2, 1]);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            1,
+            0,
+        ]
+        ");
+    }
+}
@@ -0,0 +1,112 @@
+//! The frequency ranking rule ranks up the documents containing the query
+//! words the most times, an article about a subject usually mentions it more
+//! than once. The documents are sorted by the total number of occurrences of
+//! all the terms matched by the query words.
+//! The index doesn't store any frequency, but it stores every position of every
+//! word, and the positions of a document are contiguous in the treemaps thus
+//! counting them only costs two `rank` per term and document.
+use std::{cmp::Reverse, collections::BTreeMap, ops::ControlFlow};
+
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::{Id, IndexView, WordCandidate};
+
+use super::{document_positions, Buckets, RankingRuleImpl};
+
+pub struct Frequency {
+    buckets: Buckets,
+}
+
+impl Frequency {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        // a term matched by multiple query words must only be counted once
+        let mut terms: Vec<&str> = words
+            .iter()
+            .flat_map(|word| word.terms.iter().map(|(term, _)| term.as_str()))
+            .collect();
+        terms.sort_unstable();
+        terms.dedup();
+        let positions: Vec<&RoaringTreemap> = terms
+            .into_iter()
+            .filter_map(|term| index.word_positions(term))
+            .collect();
+
+        let mut buckets: BTreeMap<Reverse<u64>, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let frequency = positions
+                .iter()
+                .map(|positions| Self::count(positions, id))
+                .sum();
+            buckets.entry(Reverse(frequency)).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+
+    /// The number of positions of the document in the treemap
+    fn count(positions: &RoaringTreemap, id: Id) -> u64 {
+        let ranks = document_positions(positions, id);
+        ranks.end - ranks.start
+    }
+}
+
+impl RankingRuleImpl for Frequency {
+    fn name(&self) -> &'static str {
+        "frequency"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_frequency() {
+        let names: &[&str] = &[
+            "i also like rust",
+            "rust rust rust everywhere",
+            "rust is great, rust is fast",
+            "nothing to see here",
+        ];
+        let index = Index::new_in_memory(names);
+
+        let mut search = Search::new("rust");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Frequency]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "rust rust rust everywhere",
+            "rust is great, rust is fast",
+            "i also like rust",
+        ]
+        "#);
+    }
+}
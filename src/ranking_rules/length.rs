@@ -0,0 +1,97 @@
+//! The length ranking rule ranks up the short documents, a three words title
+//! matching the query is more about it than a long article mentioning it once.
+//! It's the length normalization of BM25 reduced to buckets: the documents are
+//! grouped by the power of two of their number of words, thus a document with
+//! 10 words ties with one with 12 but beats one with 40.
+use std::{collections::BTreeMap, ops::ControlFlow};
+
+use roaring::RoaringBitmap;
+
+use crate::{IndexView, WordCandidate};
+
+use super::{Buckets, RankingRuleImpl};
+
+pub struct Length {
+    buckets: Buckets,
+}
+
+impl Length {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(current: &RoaringBitmap, index: &dyn IndexView) -> Vec<RoaringBitmap> {
+        let mut buckets: BTreeMap<u32, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let length = index.document_length(id).max(1).ilog2();
+            buckets.entry(length).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+}
+
+impl RankingRuleImpl for Length {
+    fn name(&self) -> &'static str {
+        "length"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets
+            .next(prev, words, |current| Self::compute_buckets(current, index))
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_length() {
+        let names: &[&str] = &[
+            "in the end it was only about the dog kefir and nothing else",
+            "kefir the dog",
+            "kefir the good little dog",
+            "kefir",
+            "nothing here",
+        ];
+        let mut index = Index::new_in_memory(names);
+        index.insert(String::from("kefir is a dog"));
+
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Length]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir",
+            "kefir the dog",
+            "kefir the good little dog",
+            "kefir is a dog",
+            "in the end it was only about the dog kefir and nothing else",
+        ]
+        "#);
+
+        // the lengths are saved with the index
+        let mut bytes = Vec::new();
+        index.write_into(&mut bytes).unwrap();
+        let loaded = Index::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.search(&search), index.search(&search));
+        // an index saved without them is truncated
+        bytes.truncate(bytes.len() - 6 * 4);
+        assert!(Index::from_bytes(&bytes).is_none());
+    }
+}
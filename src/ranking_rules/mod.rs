@@ -1,20 +1,151 @@
-use std::ops::ControlFlow;
+use std::{
+    ops::{ControlFlow, Range},
+    sync::Arc,
+};
 
-use roaring::RoaringBitmap;
+use roaring::{MultiOps, RoaringBitmap, RoaringTreemap};
 
-use crate::{Index, WordCandidate};
+use crate::{position, Id, IndexView, WordCandidate};
 
+pub mod boost;
 pub mod exact;
+pub mod field;
+pub mod frequency;
+pub mod length;
+pub mod position;
+pub mod proximity;
 pub mod typo;
+pub mod verbatim;
+pub mod weighted;
+pub mod whole_word;
 pub mod word;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+type RankingRuleBuilder = dyn Fn(&[WordCandidate]) -> Box<dyn RankingRuleImpl> + Send + Sync;
+
+#[derive(Clone)]
 pub enum RankingRule {
     Word,
     Typo,
     Exact,
+    Proximity,
+    Field,
+    /// Ranks up the documents containing the query words the most times
+    Frequency,
+    /// Ranks up the documents where the query words appear the earliest
+    Position,
+    /// Ranks up the shortest documents, they're more about the query words than the long ones
+    Length,
+    /// Ranks up the documents matching the query words as whole words rather than
+    /// as the prefix of a longer word
+    WholeWord,
+    /// Ranks up the documents containing the query words written exactly like in the query,
+    /// with the same case and accents
+    Verbatim,
+    /// Ranks up the documents with the highest boost, see [`Index::with_boosts`](crate::Index::with_boosts)
+    Boost,
+    /// Blends ranking rules into one, the documents are sorted by the weighted sum of the rank
+    /// of their bucket in every ranking rule, scaled between 0 and 1
+    Weighted(Vec<(RankingRule, f32)>),
+    /// A ranking rule implemented outside of this crate.
+    /// The function is called at the start of every search to create a fresh ranking rule.
+    Custom(Arc<RankingRuleBuilder>),
+}
+
+impl RankingRule {
+    /// Create a [`RankingRule::Custom`] from any function returning a ranking rule
+    pub fn custom(
+        builder: impl Fn(&[WordCandidate]) -> Box<dyn RankingRuleImpl> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Custom(Arc::new(builder))
+    }
+
+    /// The default ranking rules of Meilisearch, to get the same ranking when migrating from it:
+    /// - `words` is [`RankingRule::Word`]
+    /// - `typo` is [`RankingRule::Typo`]
+    /// - `proximity` is [`RankingRule::Proximity`]
+    /// - `attribute` is [`RankingRule::Field`], the fields are weighted by
+    ///   [`Search::with_field_weights`](crate::Search::with_field_weights) instead of their order
+    /// - `sort` has no equivalent, it only applies when a Meilisearch search sorts on a field.
+    ///   Sort the documents of every bucket with [`Search::with_tiebreak`](crate::Search::with_tiebreak) instead
+    /// - `exactness` is [`RankingRule::Exact`]
+    pub fn default_meilisearch() -> Vec<RankingRule> {
+        vec![
+            RankingRule::Word,
+            RankingRule::Typo,
+            RankingRule::Proximity,
+            RankingRule::Field,
+            RankingRule::Exact,
+        ]
+    }
 }
 
+impl std::fmt::Debug for RankingRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word => write!(f, "Word"),
+            Self::Typo => write!(f, "Typo"),
+            Self::Exact => write!(f, "Exact"),
+            Self::Proximity => write!(f, "Proximity"),
+            Self::Field => write!(f, "Field"),
+            Self::Frequency => write!(f, "Frequency"),
+            Self::Position => write!(f, "Position"),
+            Self::Length => write!(f, "Length"),
+            Self::WholeWord => write!(f, "WholeWord"),
+            Self::Verbatim => write!(f, "Verbatim"),
+            Self::Boost => write!(f, "Boost"),
+            Self::Weighted(rules) => f.debug_tuple("Weighted").field(rules).finish(),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl PartialEq for RankingRule {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Word, Self::Word)
+            | (Self::Typo, Self::Typo)
+            | (Self::Exact, Self::Exact)
+            | (Self::Proximity, Self::Proximity)
+            | (Self::Field, Self::Field)
+            | (Self::Frequency, Self::Frequency)
+            | (Self::Position, Self::Position)
+            | (Self::Length, Self::Length)
+            | (Self::WholeWord, Self::WholeWord)
+            | (Self::Verbatim, Self::Verbatim)
+            | (Self::Boost, Self::Boost) => true,
+            // the weights are compared by their bits to stay `Eq`
+            (Self::Weighted(left), Self::Weighted(right)) => {
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .zip(right)
+                        .all(|((left, a), (right, b))| left == right && a.to_bits() == b.to_bits())
+            }
+            (Self::Custom(left), Self::Custom(right)) => Arc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RankingRule {}
+
+/// The ranking rules are called one after the other to split the candidates in buckets,
+/// from the most relevant to the least relevant one.
+///
+/// The search always calls `next` on the current ranking rule, which can either:
+/// - Narrow the candidates and return `Continue`, the search then moves to the next ranking rule
+///   which will sort the results of [`RankingRuleImpl::current_results`]. If there is no next
+///   ranking rule the results are directly inserted as a bucket and `next` is called again.
+/// - Return `Break` with a non-empty bucket, it is inserted as is and `next` is called again.
+/// - Return `Break` with an empty bucket once it has nothing left to sort, the search then goes
+///   back to the previous ranking rule. The ranking rule must be ready to be called again
+///   with a new set of candidates.
+///
+/// Every time a bucket is inserted, [`RankingRuleImpl::cleanup`] is called on all the ranking rules
+/// and the documents are removed from the candidates.
+///
+/// A search stops early when the ranking rules keep going without returning any new document,
+/// the documents found until then are returned. It only protects against the broken ranking rules.
 pub trait RankingRuleImpl {
     /// For debugging/logging purposes
     #[allow(unused)]
@@ -24,19 +155,136 @@ pub trait RankingRuleImpl {
     /// 2. Let me know if I should pass the word candidates to the next ranking rules:
     ///    - ControlFlow::Continue(()) means yes
     ///    - ControlFlow::Break(_) means no and I should insert your results to the bucket sort + call you again
+    ///
+    /// `prev` is the previous ranking rule, its [`RankingRuleImpl::current_results`] are the documents you should sort.
     fn next(
         &mut self,
         prev: Option<&dyn RankingRuleImpl>,
         words: &mut Vec<WordCandidate>,
-        index: &Index,
+        index: &dyn IndexView,
     ) -> ControlFlow<RoaringBitmap, ()>;
 
     /// Can be called if you returned a `Continue` right before, but there is no ranking rules after you
     /// so we're simply going to insert your results in the bucket sort and call you again.
-    fn current_results(&self, words: &Vec<WordCandidate>) -> RoaringBitmap;
+    /// It's also used by the next ranking rule to know what it should sort.
+    fn current_results(&self, words: &[WordCandidate]) -> RoaringBitmap;
 
     /// If your ranking rule uses any kind of caches then it should remove the `used` elements from it.
-    fn cleanup(&mut self, _used: &RoaringBitmap) {
-        ()
+    fn cleanup(&mut self, _used: &RoaringBitmap) {}
+}
+
+/// The documents a ranking rule must sort, the first ranking rule sorts
+/// the documents containing every word
+pub(crate) fn universe(
+    prev: Option<&dyn RankingRuleImpl>,
+    words: &[WordCandidate],
+) -> RoaringBitmap {
+    match prev {
+        Some(prev) => prev.current_results(words),
+        None => words
+            .iter()
+            .map(|word| word.typos.as_slice().union())
+            .intersection(),
+    }
+}
+
+/// The ranks of the positions of the treemap contained in `range`, they can be read back with `select`.
+/// Counting or reading the positions of a document this way only costs two `rank`.
+pub(crate) fn positions_in(positions: &RoaringTreemap, range: Range<u64>) -> Range<u64> {
+    // rank returns the number of values lower or equal to its argument
+    let before = |value: u64| value.checked_sub(1).map_or(0, |last| positions.rank(last));
+    let start = before(range.start);
+    start..before(range.end).max(start)
+}
+
+/// The ranks of the positions of the document in the treemap, they're contiguous
+pub(crate) fn document_positions(positions: &RoaringTreemap, id: Id) -> Range<u64> {
+    positions_in(positions, position(id, 0)..position(id + 1, 0))
+}
+
+/// Helper for the ranking rules that split the results of the previous ranking rule
+/// in buckets all at once, and then let the next ranking rules sort every bucket one by one.
+pub(crate) struct Buckets {
+    // the buckets we still have to return, from the worst to the best one
+    buckets: Option<Vec<RoaringBitmap>>,
+}
+
+impl Buckets {
+    pub fn new() -> Self {
+        Self { buckets: None }
+    }
+
+    /// `compute` receives the documents to sort and must return the buckets from the best to the worst one
+    pub fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &[WordCandidate],
+        compute: impl FnOnce(&RoaringBitmap) -> Vec<RoaringBitmap>,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        match self.buckets.as_mut() {
+            // it's our first call since the previous ranking rule moved on
+            None => {
+                let current = universe(prev, words);
+                let mut buckets = compute(&current);
+                buckets.retain(|bucket| !bucket.is_empty());
+                if buckets.is_empty() {
+                    return ControlFlow::Break(RoaringBitmap::new());
+                }
+                buckets.reverse();
+                self.buckets = Some(buckets);
+                ControlFlow::Continue(())
+            }
+            // the current bucket has been fully sorted, we can move on to the next one
+            Some(buckets) => {
+                buckets.pop();
+                if buckets.is_empty() {
+                    self.buckets = None;
+                    ControlFlow::Break(RoaringBitmap::new())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            }
+        }
+    }
+
+    pub fn current_results(&self) -> RoaringBitmap {
+        self.buckets
+            .as_ref()
+            .and_then(|buckets| buckets.last().cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn cleanup(&mut self, used: &RoaringBitmap) {
+        for bucket in self.buckets.iter_mut().flatten() {
+            *bucket -= used;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Index, Search};
+
+    #[test]
+    fn test_default_meilisearch() {
+        let ranking_rules = RankingRule::default_meilisearch();
+        insta::assert_snapshot!(format!("{ranking_rules:?}"), @"[Word, Typo, Proximity, Field, Exact]");
+
+        let index = Index::new_in_memory_fields(&[
+            ["kefir", "le chien petit"],
+            ["le petit chien", "kefir"],
+            ["petit kefir le chien", ""],
+        ]);
+        // the proximity of the words ranks before the field they're in
+        let mut search = Search::new("petit chien");
+        search.with_ranking_rules(ranking_rules);
+        insta::assert_debug_snapshot!(index.search_ids(&search), @"
+        [
+            1,
+            0,
+            2,
+        ]
+        ");
     }
 }
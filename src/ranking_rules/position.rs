@@ -0,0 +1,109 @@
+//! The position ranking rule ranks up the documents where the query words
+//! appear early, a word in the first sentence or in the title is usually more
+//! important than one in a footnote.
+//! The documents are sorted by the position of the first word matching the query.
+//! The positions contain the field in their high bits, thus a match in the first
+//! field always beats a match in the second one.
+use std::{collections::BTreeMap, ops::ControlFlow};
+
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::{position, Id, IndexView, WordCandidate};
+
+use super::{document_positions, Buckets, RankingRuleImpl};
+
+pub struct Position {
+    buckets: Buckets,
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        let positions: Vec<&RoaringTreemap> = words
+            .iter()
+            .flat_map(|word| word.terms.iter())
+            .filter_map(|(term, _)| index.word_positions(term))
+            .collect();
+
+        let mut buckets: BTreeMap<u64, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let first = positions
+                .iter()
+                .filter_map(|positions| Self::first_position(positions, id))
+                .min()
+                .unwrap_or(u64::MAX);
+            buckets.entry(first).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+
+    /// The first position of the document in the treemap, relative to the start of the document
+    fn first_position(positions: &RoaringTreemap, id: Id) -> Option<u64> {
+        let ranks = document_positions(positions, id);
+        (!ranks.is_empty())
+            .then(|| positions.select(ranks.start))
+            .flatten()
+            .map(|first| first - position(id, 0))
+    }
+}
+
+impl RankingRuleImpl for Position {
+    fn name(&self) -> &'static str {
+        "position"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_position() {
+        let names: &[&str] = &[
+            "in the end it was only about the dog kefir",
+            "le petit kefir",
+            "kefir",
+            "nothing here",
+        ];
+        let index = Index::new_in_memory(names);
+
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Position]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir",
+            "le petit kefir",
+            "in the end it was only about the dog kefir",
+        ]
+        "#);
+    }
+}
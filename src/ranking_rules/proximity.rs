@@ -0,0 +1,147 @@
+//! The proximity ranking rule ranks up the documents where the words
+//! of the query are close to each other and in the same order.
+//! The positions of the words come from the index, like the position ranking rule,
+//! thus the documents are never tokenized again. The positions of the fields are far
+//! apart, the words of two different fields are never close to each other.
+use std::{collections::BTreeMap, ops::ControlFlow};
+
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::{position, Id, IndexView, WordCandidate};
+
+use super::{document_positions, Buckets, RankingRuleImpl};
+
+/// Past this distance two words are considered as unrelated, it's also the cost of a missing word
+const MAX_DISTANCE: usize = 8;
+
+pub struct Proximity {
+    buckets: Buckets,
+}
+
+impl Proximity {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        let mut words: Vec<&WordCandidate> = words.iter().collect();
+        words.sort_by_key(|word| word.index);
+
+        // for every query word, the positions of the terms it matched in the index
+        let terms: Vec<Vec<&RoaringTreemap>> = words
+            .iter()
+            .map(|word| {
+                word.terms
+                    .iter()
+                    .filter_map(|(term, _typos)| index.word_positions(term))
+                    .collect()
+            })
+            .collect();
+
+        let mut buckets: BTreeMap<usize, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let distance = Self::distance(id, &terms);
+            buckets.entry(distance).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+
+    /// Sum of the distances between every consecutive word of the query in the document
+    fn distance(id: Id, terms: &[Vec<&RoaringTreemap>]) -> usize {
+        let start = position(id, 0);
+        let positions: Vec<Vec<usize>> = terms
+            .iter()
+            .map(|terms| {
+                terms
+                    .iter()
+                    .flat_map(|positions| {
+                        document_positions(positions, id)
+                            .filter_map(|rank| positions.select(rank))
+                            .map(|position| (position - start) as usize)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        positions
+            .windows(2)
+            .map(|pair| {
+                pair[0]
+                    .iter()
+                    .flat_map(|left| {
+                        pair[1].iter().map(move |right| {
+                            if left < right {
+                                right - left
+                            } else {
+                                // the words are swapped, it costs a little bit more
+                                left - right + 1
+                            }
+                        })
+                    })
+                    .min()
+                    .unwrap_or(MAX_DISTANCE)
+                    .min(MAX_DISTANCE)
+            })
+            .sum()
+    }
+}
+
+impl RankingRuleImpl for Proximity {
+    fn name(&self) -> &'static str {
+        "proximity"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_proximity_rr() {
+        let names: &[&str] = &[
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+            "beau le kefir",
+            "kefir le beau chien",
+        ];
+        let index = Index::new_in_memory(names);
+
+        let mut search = Search::new("le beau");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Proximity]);
+        // the words are adjacent, then separated by one word or swapped which costs the same
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefir le beau chien",
+            "le plus beau c'est kefir",
+            "Tamo le plus beau",
+            "beau le kefir",
+        ]
+        "#);
+    }
+}
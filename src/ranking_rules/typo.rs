@@ -2,14 +2,18 @@ use std::ops::ControlFlow;
 
 use roaring::{MultiOps, RoaringBitmap};
 
-use crate::{Index, WordCandidate};
+use crate::{IndexView, WordCandidate};
 
 use super::RankingRuleImpl;
 
 pub struct Typo {
     first_iteration: bool,
+    // the number of typo buckets of every word we're currently using
     typo_allowed: usize,
+    // the highest number of typo buckets of the words, it's derived from the typo tolerance
     max_typos: usize,
+    // the results of the previous ranking rule when we started sorting them
+    universe: Option<RoaringBitmap>,
 }
 
 impl Typo {
@@ -22,6 +26,7 @@ impl Typo {
                 .map(|word| word.typos.len())
                 .max()
                 .unwrap_or_default(),
+            universe: None,
         }
     }
 }
@@ -33,10 +38,14 @@ impl RankingRuleImpl for Typo {
 
     fn next(
         &mut self,
-        _prev: Option<&dyn RankingRuleImpl>,
-        _words: &mut Vec<WordCandidate>,
-        _index: &Index,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        _index: &dyn IndexView,
     ) -> ControlFlow<RoaringBitmap, ()> {
+        // the previous ranking rule may have split the documents further than the words
+        if self.typo_allowed == 0 {
+            self.universe = prev.map(|prev| prev.current_results(words));
+        }
         // for the first iteration we returns the intersection of every words
         if self.first_iteration {
             self.first_iteration = false;
@@ -44,7 +53,7 @@ impl RankingRuleImpl for Typo {
             ControlFlow::Continue(())
         } else {
             self.typo_allowed += 1;
-            if self.max_typos <= self.typo_allowed {
+            if self.max_typos < self.typo_allowed {
                 // we can reset ourselves, if we're called again it'll be from the previous ranking rule
                 self.typo_allowed = 0;
                 ControlFlow::Break(RoaringBitmap::new())
@@ -54,10 +63,53 @@ impl RankingRuleImpl for Typo {
         }
     }
 
-    fn current_results(&self, words: &Vec<WordCandidate>) -> RoaringBitmap {
-        words
+    fn current_results(&self, words: &[WordCandidate]) -> RoaringBitmap {
+        let results = words
             .iter()
             .map(|word| word.typos.iter().take(self.typo_allowed).union())
-            .intersection()
+            .intersection();
+        match &self.universe {
+            Some(universe) => results & universe,
+            None => results,
+        }
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        if let Some(universe) = &mut self.universe {
+            *universe -= used;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search, TypoConfig};
+
+    #[test]
+    fn test_typo_buckets() {
+        let index = Index::new_in_memory(&["kefirounet", "kefiroxxet", "kefixxxnet", "kefiroumet"]);
+
+        let mut search = Search::new("kefirounet");
+        search.with_ranking_rules(vec![RankingRule::Typo]);
+        // the documents matching with the maximum number of typos must be returned as well
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet",
+            "kefiroumet",
+            "kefiroxxet",
+            "kefixxxnet",
+        ]
+        "#);
+
+        search.with_typo_tolerance(TypoConfig {
+            max_typos: 1,
+            ..TypoConfig::default()
+        });
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "kefirounet",
+            "kefiroumet",
+        ]
+        "#);
     }
 }
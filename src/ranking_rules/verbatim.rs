@@ -0,0 +1,122 @@
+//! The verbatim ranking rule ranks up the documents containing the words of the query
+//! exactly as they were written, before the normalization. "café" and "cafe" are the
+//! same word in the fst, but for the query "café" the documents written with the accent
+//! come first. Like with [`Search::with_verbatim`](crate::Search::with_verbatim) the
+//! case, the accents and the punctuation must match.
+//! The original words are not stored in the index, they're read back from the documents
+//! which are already kept, thus it's slow on big buckets and better used last.
+use std::{collections::BTreeMap, collections::HashSet, ops::ControlFlow};
+
+use roaring::RoaringBitmap;
+
+use crate::{IndexView, WordCandidate};
+
+use super::{Buckets, RankingRuleImpl};
+
+pub struct Verbatim {
+    buckets: Buckets,
+}
+
+impl Verbatim {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        // the documents with the most verbatim words come first
+        let mut buckets: BTreeMap<usize, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let tokens: HashSet<&str> = index
+                .tokenizer()
+                .tokenize(index.text(id))
+                .into_iter()
+                .collect();
+            let count = words
+                .iter()
+                .filter(|word| tokens.contains(word.original.as_str()))
+                .count();
+            buckets.entry(words.len() - count).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+}
+
+impl RankingRuleImpl for Verbatim {
+    fn name(&self) -> &'static str {
+        "verbatim"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_verbatim() {
+        let names: &[&str] = &["le cafe noir", "le café noir", "un Café noir"];
+        let index = Index::new_in_memory(names);
+
+        let mut search = Search::new("café");
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le cafe noir",
+            "le café noir",
+            "un Café noir",
+        ]
+        "#);
+
+        search.with_ranking_rules(vec![
+            RankingRule::Word,
+            RankingRule::Typo,
+            RankingRule::Verbatim,
+        ]);
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le café noir",
+            "le cafe noir",
+            "un Café noir",
+        ]
+        "#);
+
+        // the documents matching more words exactly come first
+        let mut search = Search::new("Café noir");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Verbatim]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "un Café noir",
+            ],
+            [
+                "le cafe noir",
+                "le café noir",
+            ],
+        ]
+        "#);
+    }
+}
@@ -0,0 +1,234 @@
+//! The weighted ranking rule blends multiple ranking rules into a single one, see
+//! [`RankingRule::Weighted`](crate::RankingRule::Weighted). Every ranking rule sorts all the
+//! documents on its own, as if it was the only ranking rule of the search, and a document
+//! gets the rank of its bucket scaled between 0 for the first bucket and 1 for the last one.
+//! Its score is the weighted sum of its ranks, the documents with the same score tie.
+//! The documents a ranking rule never returned, like the ones of a stuck custom rule, share
+//! its last bucket. With `[(Typo, 0.7), (Frequency, 0.3)]`, a document with a typo mentioning
+//! the query words the most times can come before a document without typo mentioning them once.
+//!
+//! It isn't free: the other ranking rules only sort the buckets the search needs, lazily, while
+//! all the blended ranking rules sort all the documents given to the weighted rule before it
+//! returns its first bucket, even when the limit of the search is small. And the ranks only keep
+//! the order of the buckets, not how far apart they are, thus a ranking rule splitting the
+//! documents in two buckets moves them as much as one splitting them in a hundred buckets.
+//! The blended ranking rules only sort the documents the weighted rule receives, like the previous
+//! ranking rules would, thus [`RankingRule::Word`](crate::RankingRule::Word) can't bring back the
+//! documents missing a word.
+use std::{collections::HashMap, ops::ControlFlow};
+
+use roaring::RoaringBitmap;
+
+use crate::{Id, Index, IndexView, WordCandidate, STALLED_STEPS_PER_WORD};
+
+use super::{Buckets, RankingRuleImpl};
+
+pub struct Weighted {
+    rules: Vec<(Box<dyn RankingRuleImpl>, f32)>,
+    buckets: Buckets,
+}
+
+/// Plays the previous ranking rule of the blended ranking rules, to give them the documents to sort
+struct Universe(RoaringBitmap);
+
+impl RankingRuleImpl for Universe {
+    fn name(&self) -> &'static str {
+        "universe"
+    }
+
+    fn next(
+        &mut self,
+        _prev: Option<&dyn RankingRuleImpl>,
+        _words: &mut Vec<WordCandidate>,
+        _index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        ControlFlow::Break(RoaringBitmap::new())
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.0.clone()
+    }
+}
+
+impl Weighted {
+    pub fn new(rules: Vec<(Box<dyn RankingRuleImpl>, f32)>) -> Self {
+        Self {
+            rules,
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        rules: &mut [(Box<dyn RankingRuleImpl>, f32)],
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        let mut scores: HashMap<Id, f32> = current.iter().map(|id| (id, 0.0)).collect();
+        for (rule, weight) in rules {
+            let buckets = Self::rank(&mut **rule, current, words, index);
+            let last = buckets.len().saturating_sub(1).max(1) as f32;
+            for (rank, bucket) in buckets.iter().enumerate() {
+                for id in bucket {
+                    scores.entry(id).and_modify(|score| {
+                        *score += *weight * rank as f32 / last;
+                    });
+                }
+            }
+        }
+
+        let mut scores: Vec<(f32, Id)> =
+            scores.into_iter().map(|(id, score)| (score, id)).collect();
+        scores.sort_by(|(left, _), (right, _)| left.total_cmp(right));
+        scores
+            .chunk_by(|(left, _), (right, _)| left == right)
+            .map(|bucket| bucket.iter().map(|(_, id)| *id).collect())
+            .collect()
+    }
+
+    /// Run the ranking rule alone on the documents until it's exhausted, like the search does,
+    /// and returns its buckets from the best to the worst one
+    fn rank(
+        rule: &mut dyn RankingRuleImpl,
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        // the word ranking rule drops the words and the buckets are removed from their documents
+        let mut words = words.to_vec();
+        let mut universe = Universe(current.clone());
+        let mut buckets = Vec::new();
+        let max_stalled_steps = STALLED_STEPS_PER_WORD * (words.len() + 1);
+        let mut stalled_steps = 0;
+        while stalled_steps < max_stalled_steps {
+            stalled_steps += 1;
+            let mut bucket = match rule.next(Some(&universe), &mut words, index) {
+                ControlFlow::Continue(()) => rule.current_results(&words),
+                ControlFlow::Break(bucket) if bucket.is_empty() => break,
+                ControlFlow::Break(bucket) => bucket,
+            };
+            bucket &= &universe.0;
+            Index::cleanup(&bucket, &mut words);
+            rule.cleanup(&bucket);
+            universe.0 -= &bucket;
+            if !bucket.is_empty() {
+                stalled_steps = 0;
+                buckets.push(bucket);
+            }
+        }
+        if !universe.0.is_empty() {
+            buckets.push(universe.0);
+        }
+        buckets
+    }
+}
+
+impl RankingRuleImpl for Weighted {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(&mut self.rules, current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_weighted() {
+        let names: &[&str] = &[
+            "kefir",
+            "kefir kefir",
+            "kefyr kefyr kefyr kefyr",
+            "kefyr",
+            "kefyr kefyr kefyr",
+        ];
+        let index = Index::new_in_memory(names);
+
+        let mut search = Search::new("kefir");
+        let searches = [
+            vec![RankingRule::Typo, RankingRule::Frequency],
+            vec![RankingRule::Frequency, RankingRule::Typo],
+            vec![RankingRule::Weighted(vec![
+                (RankingRule::Typo, 0.5),
+                (RankingRule::Frequency, 0.5),
+            ])],
+        ];
+        // the blend lets the frequent matches with a typo tie with the rare exact matches
+        let results = searches.map(|ranking_rules| {
+            search.with_ranking_rules(ranking_rules);
+            index.search_buckets(&search)
+        });
+        insta::assert_debug_snapshot!(results, @r#"
+        [
+            [
+                [
+                    "kefir kefir",
+                ],
+                [
+                    "kefir",
+                ],
+                [
+                    "kefyr kefyr kefyr kefyr",
+                ],
+                [
+                    "kefyr kefyr kefyr",
+                ],
+                [
+                    "kefyr",
+                ],
+            ],
+            [
+                [
+                    "kefyr kefyr kefyr kefyr",
+                ],
+                [
+                    "kefyr kefyr kefyr",
+                ],
+                [
+                    "kefir kefir",
+                ],
+                [
+                    "kefir",
+                ],
+                [
+                    "kefyr",
+                ],
+            ],
+            [
+                [
+                    "kefir kefir",
+                ],
+                [
+                    "kefir",
+                    "kefyr kefyr kefyr kefyr",
+                ],
+                [
+                    "kefyr kefyr kefyr",
+                ],
+                [
+                    "kefyr",
+                ],
+            ],
+        ]
+        "#);
+    }
+}
@@ -0,0 +1,187 @@
+//! The whole word ranking rule ranks up the documents matching the words of the query
+//! as whole words rather than as the beginning of a longer word. The last word of the
+//! query is searched as a prefix, thus "kefir" matches "kefirounet" without any typo
+//! and the typo ranking rule can't tell them apart.
+//! A word of the index is a whole word match if the full word, extra letters included,
+//! is within the typos allowed on the query word, counted with the [`DistanceMetric`](crate::DistanceMetric)
+//! of the search. The documents are sorted by the number
+//! of query words they only matched as a prefix. Placed after the typo ranking rule it
+//! separates the whole words from the prefixes matched with the same number of typos.
+use std::{collections::BTreeMap, ops::ControlFlow};
+
+use roaring::{MultiOps, RoaringBitmap};
+
+use crate::{IndexView, WordCandidate};
+
+use super::{Buckets, RankingRuleImpl};
+
+pub struct WholeWord {
+    buckets: Buckets,
+}
+
+impl WholeWord {
+    pub fn new() -> Self {
+        Self {
+            buckets: Buckets::new(),
+        }
+    }
+
+    fn compute_buckets(
+        current: &RoaringBitmap,
+        words: &[WordCandidate],
+        index: &dyn IndexView,
+    ) -> Vec<RoaringBitmap> {
+        // for every word searched as a prefix, the documents that only matched a longer word
+        let prefix_only: Vec<RoaringBitmap> = words
+            .iter()
+            .filter(|word| word.prefix)
+            .map(|word| {
+                let whole = word
+                    .terms
+                    .iter()
+                    .filter(|(term, _)| word.matches_whole_word(term))
+                    .filter_map(|(term, _)| index.word_documents(term))
+                    .union();
+                word.typos.as_slice().union() - whole
+            })
+            .collect();
+
+        let mut buckets: BTreeMap<usize, RoaringBitmap> = BTreeMap::new();
+        for id in current.iter() {
+            let count = prefix_only
+                .iter()
+                .filter(|bitmap| bitmap.contains(id))
+                .count();
+            buckets.entry(count).or_default().insert(id);
+        }
+
+        buckets.into_values().collect()
+    }
+}
+
+impl RankingRuleImpl for WholeWord {
+    fn name(&self) -> &'static str {
+        "whole word"
+    }
+
+    fn next(
+        &mut self,
+        prev: Option<&dyn RankingRuleImpl>,
+        words: &mut Vec<WordCandidate>,
+        index: &dyn IndexView,
+    ) -> ControlFlow<RoaringBitmap, ()> {
+        self.buckets.next(prev, words, |current| {
+            Self::compute_buckets(current, words, index)
+        })
+    }
+
+    fn current_results(&self, _words: &[WordCandidate]) -> RoaringBitmap {
+        self.buckets.current_results()
+    }
+
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        self.buckets.cleanup(used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Index, RankingRule, Search};
+
+    #[test]
+    fn test_whole_word() {
+        let names: &[&str] = &[
+            "kefirounet a un gros nez",
+            "le petit kefyr",
+            "kefir le chien",
+            "kefir et kefirounet",
+        ];
+        let index = Index::new_in_memory(names);
+
+        // the typo ranking rule can't tell a prefix from a whole word
+        let mut search = Search::new("kefir");
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Typo]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefirounet a un gros nez",
+                "kefir le chien",
+                "kefir et kefirounet",
+            ],
+            [
+                "le petit kefyr",
+            ],
+        ]
+        "#);
+
+        // the whole words come first, with or without typos
+        search.with_ranking_rules(vec![RankingRule::WholeWord]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "le petit kefyr",
+                "kefir le chien",
+                "kefir et kefirounet",
+            ],
+            [
+                "kefirounet a un gros nez",
+            ],
+        ]
+        "#);
+
+        // after the typo ranking rule, the whole words come first among the matches with the same typos
+        search.with_ranking_rules(vec![
+            RankingRule::Word,
+            RankingRule::Typo,
+            RankingRule::WholeWord,
+        ]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le chien",
+                "kefir et kefirounet",
+            ],
+            [
+                "kefirounet a un gros nez",
+            ],
+            [
+                "le petit kefyr",
+            ],
+        ]
+        "#);
+
+        // the exact ranking rule only compares the first word of the query and of the document
+        search.with_ranking_rules(vec![RankingRule::Word, RankingRule::Exact]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir le chien",
+                "kefir et kefirounet",
+            ],
+            [
+                "le petit kefyr",
+            ],
+            [
+                "kefirounet a un gros nez",
+            ],
+        ]
+        "#);
+
+        // the infix matches don't raise the typos allowed on a whole word, "kefirou" is two typos away
+        let index = Index::new_in_memory(&["kefirou", "kefir"]);
+        let mut search = Search::new("kefir");
+        search
+            .with_infix(true)
+            .with_ranking_rules(vec![RankingRule::WholeWord]);
+        insta::assert_debug_snapshot!(index.search_buckets(&search), @r#"
+        [
+            [
+                "kefir",
+            ],
+            [
+                "kefirou",
+            ],
+        ]
+        "#);
+    }
+}
@@ -2,16 +2,19 @@ use std::ops::ControlFlow;
 
 use roaring::{MultiOps, RoaringBitmap};
 
-use crate::{Index, WordCandidate};
+use crate::{Index, IndexView, WordCandidate};
 
 use super::RankingRuleImpl;
 
 pub struct Word {
     first_iteration: bool,
+    /// The words we popped, they're merged back into the last word once it
+    /// has been used alone so the documents matching only them are returned as well.
+    dropped: Vec<WordCandidate>,
 }
 
 impl Word {
-    pub fn new(words: &mut Vec<WordCandidate>) -> Self {
+    pub fn new(words: &mut [WordCandidate]) -> Self {
         // Since the default strategy is to pop the words from
         // the biggest frequency to the lowest we're going to
         // sort all the words by frequency in advance.
@@ -22,6 +25,7 @@ impl Word {
 
         Self {
             first_iteration: true,
+            dropped: Vec::new(),
         }
     }
 }
@@ -35,23 +39,39 @@ impl RankingRuleImpl for Word {
         &mut self,
         _pred: Option<&dyn RankingRuleImpl>,
         words: &mut Vec<WordCandidate>,
-        _index: &Index,
+        _index: &dyn IndexView,
     ) -> ControlFlow<RoaringBitmap, ()> {
         // for the first iteration we returns the intersection of every words
         if self.first_iteration {
             self.first_iteration = false;
             // Nothing to do for the first iteration
             ControlFlow::Continue(())
-        } else {
-            words.pop();
-            if words.is_empty() {
-                return ControlFlow::Break(RoaringBitmap::new());
+        } else if words.len() > 1 {
+            self.dropped.extend(words.pop());
+            ControlFlow::Continue(())
+        } else if let (Some(last), false) = (words.last_mut(), self.dropped.is_empty()) {
+            // the last bucket contains the documents matching any of the words
+            for dropped in self.dropped.drain(..) {
+                if last.typos.len() < dropped.typos.len() {
+                    last.typos.resize(dropped.typos.len(), RoaringBitmap::new());
+                }
+                for (typo, bitmap) in last.typos.iter_mut().zip(dropped.typos) {
+                    *typo |= bitmap;
+                }
+                last.terms.extend(dropped.terms);
             }
             ControlFlow::Continue(())
+        } else {
+            words.pop();
+            ControlFlow::Break(RoaringBitmap::new())
         }
     }
 
-    fn current_results(&self, words: &Vec<WordCandidate>) -> RoaringBitmap {
+    fn cleanup(&mut self, used: &RoaringBitmap) {
+        Index::cleanup(used, &mut self.dropped);
+    }
+
+    fn current_results(&self, words: &[WordCandidate]) -> RoaringBitmap {
         words
             .iter()
             .map(|word| word.typos.as_slice().union())
@@ -67,37 +87,31 @@ mod test {
 
     #[test]
     fn test_words_rr() {
-        let index = Index::construct(Vec::new());
+        let index = Index::new_in_memory(&[] as &[&str]);
 
         // let's say we're working with "le beau chien"
         let mut words = vec![
             // "le" should be present in a tons of documents and will be first to be evicted
             WordCandidate {
-                original: String::from("le"),
-                normalized: String::from("le"),
-                index: 0,
                 typos: vec![RoaringBitmap::from_sorted_iter(0..1000).unwrap()],
+                ..WordCandidate::new(String::from("le"), String::from("le"), 0, 0)
             },
             // "beau" is present in a bunch of documents but only 4 overlaps with "le"
             WordCandidate {
-                original: String::from("beau"),
-                normalized: String::from("beau"),
-                index: 1,
                 // where I shove my stuff must not matter
                 typos: vec![
                     RoaringBitmap::from_sorted_iter(0..2).unwrap(),
                     RoaringBitmap::from_sorted_iter(100..102).unwrap(),
                     RoaringBitmap::from_sorted_iter(1000..1030).unwrap(),
                 ],
+                ..WordCandidate::new(String::from("beau"), String::from("beau"), 1, 0)
             },
             WordCandidate {
-                original: String::from("chien"),
-                normalized: String::from("chien"),
-                index: 2,
                 typos: vec![RoaringBitmap::from_sorted_iter(
                     (1..3).chain(98..101).chain(1028..1030),
                 )
                 .unwrap()],
+                ..WordCandidate::new(String::from("chien"), String::from("chien"), 2, 0)
             },
         ];
         let mut rr = Word::new(&mut words);
@@ -153,6 +167,8 @@ mod test {
         // this time we're going to do our job and filter the universe before calling next
         Index::cleanup(&bucket, &mut words);
         Index::cleanup(&second_bucket, &mut words);
+        rr.cleanup(&bucket);
+        rr.cleanup(&second_bucket);
         let control = rr.next(None, &mut words, &index);
         insta::assert_debug_snapshot!(control, @r###"
         Continue(
@@ -164,6 +180,20 @@ mod test {
         let third_bucket = rr.current_results(&words);
         insta::assert_debug_snapshot!(third_bucket, @"RoaringBitmap<[2, 98, 99]>");
 
+        Index::cleanup(&third_bucket, &mut words);
+        rr.cleanup(&third_bucket);
+        let control = rr.next(None, &mut words, &index);
+        insta::assert_debug_snapshot!(control, @"
+        Continue(
+            (),
+        )
+        ");
+        // Then the dropped words are merged back into "chien" and the last bucket
+        // contains the documents matching any of the words that were not returned yet
+        let fourth_bucket = rr.current_results(&words);
+        insta::assert_debug_snapshot!(fourth_bucket, @"RoaringBitmap<1023 values between 0 and 1027>");
+        assert_eq!(words.len(), 1);
+
         // Even without proper cleanup, the words ranking rule shouldn't take a look at what is inside the candidates
         // and just drop the last one + return Break([])
         let control = rr.next(None, &mut words, &index);
@@ -0,0 +1,182 @@
+//! A search updated on every keystroke, see [`SearchSession`].
+//! While the query is being typed only its last word changes, the words before it match the
+//! same documents as for the previous keystroke. The session keeps the documents matched by
+//! every word of the previous query and only runs the words that changed through the fst.
+use crate::{bucket_ids, Index, QueryPart, Search, SearchContext, WordCandidate};
+
+/// The words of the previous query and their documents, before they're filtered
+#[derive(Default)]
+pub(crate) struct WordCache {
+    words: Vec<CachedWord>,
+}
+
+struct CachedWord {
+    original: String,
+    phrase: bool,
+    // the position of the word in the query and if it was the last one,
+    // the last word is searched as a prefix by default
+    index: usize,
+    last: bool,
+    candidate: WordCandidate,
+}
+
+impl WordCache {
+    pub fn get(&self, part: &QueryPart, index: usize, last: bool) -> Option<&WordCandidate> {
+        self.words
+            .iter()
+            .find(|word| {
+                word.original == part.original()
+                    && word.phrase == matches!(part, QueryPart::Phrase { .. })
+                    && word.index == index
+                    && word.last == last
+            })
+            .map(|word| &word.candidate)
+    }
+
+    /// Replace the cached words by the words of the query, the n-th candidate belongs to the n-th part
+    pub fn store(&mut self, parts: &[QueryPart], candidates: &[WordCandidate]) {
+        self.words.clear();
+        self.words
+            .extend(
+                parts
+                    .iter()
+                    .zip(candidates)
+                    .enumerate()
+                    .map(|(index, (part, candidate))| CachedWord {
+                        original: part.original().to_string(),
+                        phrase: matches!(part, QueryPart::Phrase { .. }),
+                        index,
+                        last: index == parts.len() - 1,
+                        candidate: candidate.clone(),
+                    }),
+            );
+    }
+
+    fn clear(&mut self) {
+        self.words.clear();
+    }
+}
+
+/// Search as the user types, [`SearchSession::update`] is called with the whole query on every keystroke.
+/// When the new query extends the previous one, the words that didn't change reuse the documents
+/// they matched the last time. Any other query starts from scratch.
+///
+/// ```
+/// use zearch::{Index, Search, SearchSession};
+///
+/// let index = Index::new_in_memory(&["kefir le chien", "le petit kefir"]);
+/// let mut session = SearchSession::new(&index, Search::new(""));
+/// session.update("kefir l");
+/// assert_eq!(session.update("kefir le c")[0], "kefir le chien");
+/// ```
+pub struct SearchSession<'i, 'a, T: ?Sized + ToOwned = str> {
+    index: &'i Index<'a, T>,
+    // the settings of the searches, its input is replaced by the query of every update
+    search: Search<'static>,
+    input: String,
+    words: WordCache,
+    context: SearchContext,
+}
+
+impl<'i, 'a, T: ?Sized + ToOwned + AsRef<str>> SearchSession<'i, 'a, T> {
+    /// Every update runs `search` with the new query, the input it was created with is ignored
+    pub fn new(index: &'i Index<'a, T>, search: Search<'static>) -> Self {
+        Self {
+            index,
+            search,
+            input: String::new(),
+            words: WordCache::default(),
+            context: SearchContext::default(),
+        }
+    }
+
+    /// Run the search with the new query and returns the matching documents like [`Index::search`]
+    pub fn update(&mut self, input: &str) -> Vec<&'i T> {
+        if !input.starts_with(&self.input) {
+            self.words.clear();
+        }
+        self.input.clear();
+        self.input.push_str(input);
+
+        let mut search: Search = self.search.clone();
+        search.input = input;
+        let index = self.index;
+        let mut buckets = index.ranked_buckets_in(
+            &search,
+            std::mem::take(&mut self.context),
+            Some(&mut self.words),
+            None,
+        );
+        let documents = buckets
+            .by_ref()
+            .flat_map(|bucket| bucket_ids(search.tiebreak.as_ref(), bucket))
+            .skip(search.offset)
+            .take(search.limit())
+            .map(|id| &index.documents[id])
+            .collect();
+        self.context = buckets.into_context();
+        documents
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_search_session() {
+        let names = [
+            "Tamo le plus beau",
+            "kefir le bon petit chien",
+            "kefir le beau chien",
+            "le petit kefir",
+            "kefirounet a un gros nez",
+        ];
+        let index = Index::new_in_memory(&names);
+        let mut settings = Search::new("");
+        settings.with_limit(3);
+        let mut session = SearchSession::new(&index, settings.clone());
+
+        // typing, erasing then typing something else must give the same results as a new search
+        let query = "kefir le beau c";
+        let mut inputs: Vec<&str> = (1..=query.len()).map(|len| &query[..len]).collect();
+        inputs.extend([
+            "kefir le bo",
+            "tamo",
+            "tamo \"le plus",
+            "tamo \"le plus\" b",
+        ]);
+        for input in inputs {
+            let mut search = settings.clone();
+            search.input = input;
+            assert_eq!(session.update(input), index.search(&search), "{input}");
+        }
+
+        // the words before the last one are kept for the next keystroke
+        let cached: Vec<(&str, usize, bool)> = session
+            .words
+            .words
+            .iter()
+            .map(|word| (word.original.as_str(), word.index, word.last))
+            .collect();
+        insta::assert_debug_snapshot!(cached, @r#"
+        [
+            (
+                "tamo",
+                0,
+                false,
+            ),
+            (
+                "le plus",
+                1,
+                false,
+            ),
+            (
+                "b",
+                2,
+                true,
+            ),
+        ]
+        "#);
+    }
+}
@@ -0,0 +1,764 @@
+//! The tokenizer splits the documents and the queries in words.
+//! The same tokenizer must be used to build the index and to search it,
+//! that's why it's stored in the [`Index`](crate::Index) and not in the [`Search`](crate::Search).
+use std::collections::HashSet;
+
+use crate::{normalize, normalize_with, NormalizeConfig, TOKEN_SEPARATOR};
+
+/// Split a text in words, the words are normalized afterward.
+pub trait Tokenizer: Send + Sync {
+    /// Every word must be a sub-slice of `text`, that's how we find where they are in the document.
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str>;
+
+    /// Normalize a word returned by [`Tokenizer::tokenize`] before it's inserted in or searched in the index.
+    /// The words that are empty once normalized are ignored. Defaults to [`normalize`].
+    fn normalize(&self, word: &str) -> String {
+        normalize(word)
+    }
+
+    /// Whether the queries can use double quotes for the phrases and a `-` for the negative words,
+    /// otherwise every word of the query is searched as is. Defaults to `true`.
+    fn query_syntax(&self) -> bool {
+        true
+    }
+}
+
+/// The default tokenizer, split the text on every unicode whitespace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        text.split_whitespace().collect()
+    }
+}
+
+/// The tokenizer of the documents and the queries that were already tokenized and normalized,
+/// their words are joined by [`TOKEN_SEPARATOR`] with [`join_tokens`](crate::join_tokens).
+/// The words are searched exactly as they were given, without normalization nor query syntax.
+/// See [`Index::new_in_memory_tokens`](crate::Index::new_in_memory_tokens).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreTokenizedTokenizer;
+
+impl Tokenizer for PreTokenizedTokenizer {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        text.split(TOKEN_SEPARATOR).collect()
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        word.to_string()
+    }
+
+    fn query_syntax(&self) -> bool {
+        false
+    }
+}
+
+/// Wraps a tokenizer and drops the stop words, to use when building the index
+/// to make it smaller. The stop words are compared once normalized.
+pub struct StopWordsTokenizer<T> {
+    tokenizer: T,
+    stop_words: HashSet<String>,
+}
+
+impl<T: Tokenizer> StopWordsTokenizer<T> {
+    pub fn new(tokenizer: T, stop_words: HashSet<String>) -> Self {
+        Self {
+            stop_words: stop_words
+                .iter()
+                .map(|word| tokenizer.normalize(word))
+                .collect(),
+            tokenizer,
+        }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for StopWordsTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut words = self.tokenizer.tokenize(text);
+        words.retain(|word| !self.stop_words.contains(&self.tokenizer.normalize(word)));
+        words
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        self.tokenizer.normalize(word)
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// Wraps a tokenizer and drops the words shorter than `min_len` characters once normalized, to use
+/// when building the index to keep the one and two letters words out of the fst. The queries are
+/// tokenized alike, but a text made only of short words keeps them all, thus a query like "tv"
+/// still searches its words, the last one as a prefix.
+pub struct MinLengthTokenizer<T> {
+    tokenizer: T,
+    min_len: usize,
+}
+
+impl<T: Tokenizer> MinLengthTokenizer<T> {
+    pub fn new(tokenizer: T, min_len: usize) -> Self {
+        Self { tokenizer, min_len }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for MinLengthTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut words = self.tokenizer.tokenize(text);
+        let long_enough =
+            |word: &&str| self.tokenizer.normalize(word).chars().count() >= self.min_len;
+        if words.iter().any(long_enough) {
+            words.retain(long_enough);
+        }
+        words
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        self.tokenizer.normalize(word)
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// Wraps a tokenizer and splits its words between the letters and the digits,
+/// `iphone12` becomes `iphone` and `12`. Useful for product names, but the
+/// identifiers like `abc123` can then only be found as two words.
+pub struct SplitDigitsTokenizer<T> {
+    tokenizer: T,
+}
+
+impl<T: Tokenizer> SplitDigitsTokenizer<T> {
+    pub fn new(tokenizer: T) -> Self {
+        Self { tokenizer }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for SplitDigitsTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut words = Vec::new();
+        for word in self.tokenizer.tokenize(text) {
+            let mut start = 0;
+            let mut previous: Option<char> = None;
+            for (offset, c) in word.char_indices() {
+                if let Some(previous) = previous {
+                    let boundary = (previous.is_numeric() && c.is_alphabetic())
+                        || (previous.is_alphabetic() && c.is_numeric());
+                    if boundary {
+                        words.push(&word[start..offset]);
+                        start = offset;
+                    }
+                }
+                previous = Some(c);
+            }
+            words.push(&word[start..]);
+        }
+        words
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        self.tokenizer.normalize(word)
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// Wraps a tokenizer and keeps some punctuation characters in the normalized words, so
+/// `node.js`, `c++` or `@handle` are searchable as they're written. The text between the
+/// kept characters is normalized by the inner tokenizer. A word ending with a kept
+/// character, like `node.js.` at the end of a sentence, doesn't match `node.js` anymore.
+pub struct KeepPunctuationTokenizer<T> {
+    tokenizer: T,
+    punctuation: HashSet<char>,
+}
+
+impl<T: Tokenizer> KeepPunctuationTokenizer<T> {
+    pub fn new(tokenizer: T, punctuation: &str) -> Self {
+        Self {
+            tokenizer,
+            punctuation: punctuation.chars().collect(),
+        }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for KeepPunctuationTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        self.tokenizer.tokenize(text)
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        let mut normalized = String::with_capacity(word.len());
+        let mut start = 0;
+        for (offset, c) in word.char_indices() {
+            if self.punctuation.contains(&c) {
+                normalized.push_str(&self.tokenizer.normalize(&word[start..offset]));
+                normalized.push(c);
+                start = offset + c.len_utf8();
+            }
+        }
+        normalized.push_str(&self.tokenizer.normalize(&word[start..]));
+        normalized
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// Wraps a tokenizer and keeps the emoji and the symbols like "♥" as words of their own, the default
+/// normalization drops them. They're split from the letters around them, "feu🔥" becomes "feu" and "🔥",
+/// but the emoji joined by a zero width joiner like "👨‍👩‍👧" stay a single word, as well as the flags
+/// and the emoji followed by a skin tone. The variation selectors are dropped, thus "♥️" matches "♥".
+/// The other words are normalized by the inner tokenizer.
+pub struct KeepSymbolsTokenizer<T> {
+    tokenizer: T,
+}
+
+impl<T: Tokenizer> KeepSymbolsTokenizer<T> {
+    pub fn new(tokenizer: T) -> Self {
+        Self { tokenizer }
+    }
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+
+/// The emoji and the symbols that aren't letters nor digits
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric()
+        && matches!(c,
+            '\u{a9}' | '\u{ae}' | '\u{2100}'..='\u{2bff}' | '\u{3030}' | '\u{303d}' | '\u{3297}'
+            | '\u{3299}' | '\u{1f000}'..='\u{1faff}'
+        )
+}
+
+/// The variation selectors, the keycap, the tags and the skin tones change the previous emoji
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c,
+        '\u{fe0e}' | '\u{fe0f}' | '\u{20e3}' | '\u{e0020}'..='\u{e007f}' | '\u{1f3fb}'..='\u{1f3ff}'
+    )
+}
+
+/// A flag is made of two regional indicators
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
+
+impl<T: Tokenizer> Tokenizer for KeepSymbolsTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut words = Vec::new();
+        for word in self.tokenizer.tokenize(text) {
+            let mut start = 0;
+            // whether the current word is made of symbols and ends with the first half of a flag
+            let (mut symbol, mut half_flag) = (false, false);
+            let mut previous = None;
+            for (offset, c) in word.char_indices() {
+                let flag = half_flag && is_regional_indicator(c);
+                let joined = symbol
+                    && (c == ZERO_WIDTH_JOINER
+                        || previous == Some(ZERO_WIDTH_JOINER)
+                        || is_emoji_modifier(c)
+                        || flag);
+                if offset > 0 && !joined && (symbol || is_symbol(c)) {
+                    words.push(&word[start..offset]);
+                    start = offset;
+                }
+                if !joined {
+                    symbol = is_symbol(c);
+                }
+                half_flag = is_regional_indicator(c) && !flag;
+                previous = Some(c);
+            }
+            words.push(&word[start..]);
+        }
+        words
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        match word.chars().next().is_some_and(is_symbol) {
+            true => word
+                .chars()
+                .filter(|c| !matches!(c, '\u{fe0e}' | '\u{fe0f}'))
+                .collect(),
+            false => self.tokenizer.normalize(word),
+        }
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// Wraps a tokenizer and normalizes its words with [`normalize_with`] instead of its own
+/// normalization, to choose whether the case and the diacritics are folded. With
+/// [`NormalizeConfig::fold_accents`] disabled "Año" matches "año" but not "ano".
+pub struct NormalizeTokenizer<T> {
+    tokenizer: T,
+    config: NormalizeConfig,
+}
+
+impl<T: Tokenizer> NormalizeTokenizer<T> {
+    pub fn new(tokenizer: T, config: NormalizeConfig) -> Self {
+        Self { tokenizer, config }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for NormalizeTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        self.tokenizer.tokenize(text)
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        normalize_with(word, self.config)
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+/// The languages of [`StemmingTokenizer`]
+#[cfg(feature = "stemming")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    French,
+}
+
+/// Wraps a tokenizer and reduces its normalized words to their stem, so "chiens" matches "chien"
+/// and "running" matches "run". The stemmer runs after the normalization of the inner tokenizer,
+/// on the lowercase words without their diacritics, and the index and the queries are stemmed alike
+/// since they share the tokenizer. The default tokenizer doesn't stem anything.
+#[cfg(feature = "stemming")]
+pub struct StemmingTokenizer<T> {
+    tokenizer: T,
+    stemmer: rust_stemmers::Stemmer,
+}
+
+#[cfg(feature = "stemming")]
+impl<T: Tokenizer> StemmingTokenizer<T> {
+    pub fn new(tokenizer: T, language: Language) -> Self {
+        let algorithm = match language {
+            Language::English => rust_stemmers::Algorithm::English,
+            Language::French => rust_stemmers::Algorithm::French,
+        };
+        Self {
+            tokenizer,
+            stemmer: rust_stemmers::Stemmer::create(algorithm),
+        }
+    }
+}
+
+#[cfg(feature = "stemming")]
+impl<T: Tokenizer> Tokenizer for StemmingTokenizer<T> {
+    fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        self.tokenizer.tokenize(text)
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        let normalized = self.tokenizer.normalize(word);
+        self.stemmer.stem(&normalized).into_owned()
+    }
+
+    fn query_syntax(&self) -> bool {
+        self.tokenizer.query_syntax()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Id, Index, Search};
+
+    /// Split on everything that's not alphanumeric, and every CJK ideogram is its own word
+    struct CjkTokenizer;
+
+    impl Tokenizer for CjkTokenizer {
+        fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+            let is_cjk = |c: char| ('\u{4e00}'..='\u{9fff}').contains(&c);
+            let mut words = Vec::new();
+            let mut start = None;
+            for (offset, c) in text.char_indices() {
+                if is_cjk(c) || !c.is_alphanumeric() {
+                    if let Some(start) = start.take() {
+                        words.push(&text[start..offset]);
+                    }
+                    if is_cjk(c) {
+                        words.push(&text[offset..offset + c.len_utf8()]);
+                    }
+                } else if start.is_none() {
+                    start = Some(offset);
+                }
+            }
+            if let Some(start) = start {
+                words.push(&text[start..]);
+            }
+            words
+        }
+    }
+
+    #[test]
+    fn test_stop_words_tokenizer() {
+        let stop_words = ["le", "Un"].map(String::from).into();
+        let tokenizer = StopWordsTokenizer::new(WhitespaceTokenizer, stop_words);
+        insta::assert_debug_snapshot!(tokenizer.tokenize("Le chien est un poney"), @r#"
+        [
+            "chien",
+            "est",
+            "poney",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_min_length_tokenizer() {
+        let names: &[&str] = &["le chien de Tamo", "un os", "kefir a un os"];
+        let mut bytes = Vec::new();
+        let tokenizer = || MinLengthTokenizer::new(WhitespaceTokenizer, 3);
+        Index::construct_with_tokenizer(names, tokenizer(), &mut bytes).unwrap();
+        let index = Index::from_bytes(&bytes)
+            .unwrap()
+            .with_tokenizer(tokenizer());
+        let terms: Vec<String> = index.terms().map(|(term, _)| term).collect();
+        // the document made only of short words keeps them
+        insta::assert_snapshot!(terms.join(" "), @"chien kefir os tamo un");
+
+        // the short words of the queries are dropped too, unless they're all short
+        let queries = ["le chien", "kefir os", "un os", "o"];
+        let results = queries.map(|query| {
+            let mut search = Search::new(query);
+            search.with_typo_tolerance(crate::TypoConfig::disabled());
+            index.search_ids(&search)
+        });
+        insta::assert_snapshot!(format!("{results:?}"), @"[[0], [2], [1], [1]]");
+    }
+
+    #[test]
+    fn test_split_digits_tokenizer() {
+        let tokenizer = SplitDigitsTokenizer::new(WhitespaceTokenizer);
+        insta::assert_debug_snapshot!(tokenizer.tokenize("iphone12 pro 4K a1b2"), @r#"
+        [
+            "iphone",
+            "12",
+            "pro",
+            "4",
+            "K",
+            "a",
+            "1",
+            "b",
+            "2",
+        ]
+        "#);
+
+        let names: &[&str] = &["iphone12 pro", "galaxy s 24"];
+        let index = Index::new_in_memory_with_tokenizer(names, tokenizer);
+        insta::assert_debug_snapshot!(index.search(&Search::new("iphone 12")), @r#"
+        [
+            "iphone12 pro",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search(&Search::new("\"galaxy s24\"")), @r#"
+        [
+            "galaxy s 24",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_keep_punctuation_tokenizer() {
+        let tokenizer = KeepPunctuationTokenizer::new(WhitespaceTokenizer, ".+@");
+        let words =
+            ["Node.JS", "C++", "@Tamo", "kefir!", "++"].map(|word| tokenizer.normalize(word));
+        insta::assert_debug_snapshot!(words, @r#"
+        [
+            "node.js",
+            "c++",
+            "@tamo",
+            "kefir",
+            "++",
+        ]
+        "#);
+
+        let names: &[&str] = &["node.js is fast", "node is a js runtime", "c++ and c"];
+        let index = Index::new_in_memory_with_tokenizer(names, tokenizer);
+        insta::assert_debug_snapshot!(index.search(&Search::new("node.js")), @r#"
+        [
+            "node.js is fast",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search(&Search::new("C++")), @r#"
+        [
+            "c++ and c",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_keep_symbols_tokenizer() {
+        let tokenizer = KeepSymbolsTokenizer::new(WhitespaceTokenizer);
+        insta::assert_debug_snapshot!(
+            tokenizer.tokenize("feu🔥🔥 t'aime ♥️ famille 👨‍👩‍👧 👋🏽 🇫🇷🇧🇪"),
+            @r#"
+        [
+            "feu",
+            "🔥",
+            "🔥",
+            "t'aime",
+            "♥\u{fe0f}",
+            "famille",
+            "👨\u{200d}👩\u{200d}👧",
+            "👋🏽",
+            "🇫🇷",
+            "🇧🇪",
+        ]
+        "#
+        );
+
+        let names: &[&str] = &[
+            "ce plat est 🔥🔥",
+            "je t'aime ♥️",
+            "la famille 👨‍👩‍👧 au complet",
+            "le feu🔥 de camp",
+            "le feu",
+        ];
+        let index = Index::new_in_memory_with_tokenizer(names, tokenizer);
+        let results = ["🔥", "♥", "👨‍👩‍👧", "feu 🔥"].map(|query| index.search(&Search::new(query)));
+        insta::assert_debug_snapshot!(results, @r#"
+        [
+            [
+                "ce plat est 🔥🔥",
+                "le feu🔥 de camp",
+            ],
+            [
+                "je t'aime ♥\u{fe0f}",
+            ],
+            [
+                "la famille 👨\u{200d}👩\u{200d}👧 au complet",
+            ],
+            [
+                "le feu🔥 de camp",
+                "le feu",
+                "ce plat est 🔥🔥",
+            ],
+        ]
+        "#);
+    }
+
+    #[test]
+    #[cfg(feature = "stemming")]
+    fn test_stemming_tokenizer() {
+        let english = StemmingTokenizer::new(WhitespaceTokenizer, Language::English);
+        let words = ["running", "runs", "Run"].map(|word| english.normalize(word));
+        insta::assert_debug_snapshot!(words, @r#"
+        [
+            "run",
+            "run",
+            "run",
+        ]
+        "#);
+
+        let names: &[&str] = &["les chiens de Tamo", "le chien", "un chat"];
+        let french = StemmingTokenizer::new(WhitespaceTokenizer, Language::French);
+        let index = Index::new_in_memory_with_tokenizer(names, french);
+        insta::assert_debug_snapshot!(index.search(&Search::new("Chien")), @r#"
+        [
+            "le chien",
+            "les chiens de Tamo",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_custom_tokenizer() {
+        insta::assert_debug_snapshot!(CjkTokenizer.tokenize("le wi-fi 北京大学"), @r#"
+        [
+            "le",
+            "wi",
+            "fi",
+            "北",
+            "京",
+            "大",
+            "学",
+        ]
+        "#);
+
+        let names: &[&str] = &["le wi-fi de tamo", "北京大学", "wifi"];
+        let index = Index::new_in_memory_with_tokenizer(names, CjkTokenizer);
+        // the tokenizer is used on the query as well
+        insta::assert_debug_snapshot!(index.search(&Search::new("fi tamo")), @r#"
+        [
+            "le wi-fi de tamo",
+        ]
+        "#);
+        insta::assert_debug_snapshot!(index.search(&Search::new("大学")), @r#"
+        [
+            "北京大学",
+        ]
+        "#);
+    }
+
+    /// Only lowercase the words, the diacritics are meaningful
+    struct AccentTokenizer;
+
+    impl Tokenizer for AccentTokenizer {
+        fn tokenize<'t>(&self, text: &'t str) -> Vec<&'t str> {
+            WhitespaceTokenizer.tokenize(text)
+        }
+
+        fn normalize(&self, word: &str) -> String {
+            word.to_lowercase()
+        }
+    }
+
+    #[test]
+    fn test_accent_sensitive_normalization() {
+        let config = NormalizeConfig {
+            fold_accents: false,
+            ..NormalizeConfig::default()
+        };
+        // the decomposed letters are composed back
+        insta::assert_debug_snapshot!(
+            ["Año", "an\u{303}o", "ÉCOLE", "l'œuvre", "ﬁn"].map(|word| normalize_with(word, config)),
+            @r#"
+        [
+            "año",
+            "año",
+            "école",
+            "lœuvre",
+            "fin",
+        ]
+        "#
+        );
+
+        let names: &[&str] = &["el año de tamo", "el ano de kefir"];
+        let index = Index::new_in_memory_with_tokenizer(
+            names,
+            NormalizeTokenizer::new(WhitespaceTokenizer, config),
+        );
+        let mut search = Search::new("AÑO");
+        search.with_typo_tolerance(crate::TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "el año de tamo",
+        ]
+        "#);
+        search.input = "ano";
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "el ano de kefir",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_language_folding() {
+        let folding = |folding| NormalizeConfig {
+            folding,
+            ..NormalizeConfig::default()
+        };
+        let words = ["Müller", "blåbærsyltetøy", "Mu\u{308}ller", "Straße"];
+        let normalized = [
+            crate::Folding::All,
+            crate::Folding::German,
+            crate::Folding::Nordic,
+            crate::Folding::Custom(&[('ß', "sz")]),
+        ]
+        .map(|config| {
+            words
+                .map(|word| normalize_with(word, folding(config)))
+                .join(" ")
+        });
+        insta::assert_debug_snapshot!(normalized, @r#"
+        [
+            "muller blabaersyltetoy muller strasse",
+            "mueller blabaersyltetoy mueller strasse",
+            "muller blåbærsyltetøy muller strasse",
+            "muller blabaersyltetoy muller strasze",
+        ]
+        "#);
+
+        let names: &[&str] = &["herr mueller", "herr muller"];
+        let tokenizer =
+            NormalizeTokenizer::new(WhitespaceTokenizer, folding(crate::Folding::German));
+        let index = Index::new_in_memory_with_tokenizer(names, tokenizer);
+        let mut search = Search::new("Müller");
+        search.with_typo_tolerance(crate::TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "herr mueller",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_pre_tokenized() {
+        let documents: &[&[&str]] = &[
+            &["Kéfir", "c++", "le chien"],
+            &["kefir", "-chat"],
+            &["tamo"],
+        ];
+        let index = Index::new_in_memory_tokens(documents);
+        // the words are neither normalized nor parsed as a phrase or a negative word
+        let search = |tokens: &[&str]| -> Vec<Id> {
+            let query = crate::join_tokens(tokens);
+            let mut search = Search::new(&query);
+            search.with_typo_tolerance(crate::TypoConfig::disabled());
+            index.search_ids(&search)
+        };
+        let queries: [&[&str]; 5] = [
+            &["Kéfir"],
+            &["kefir"],
+            &["c++", "le chien"],
+            &["-chat"],
+            &["\"tamo\""],
+        ];
+        insta::assert_snapshot!(format!("{:?}", queries.map(search)), @"[[0], [1], [0], [1], []]");
+
+        // the tokenizer must be set again to search a serialized index
+        let mut bytes = Vec::new();
+        Index::construct_tokens(documents, &mut bytes).unwrap();
+        let loaded = Index::from_bytes(&bytes)
+            .unwrap()
+            .with_tokenizer(PreTokenizedTokenizer);
+        let query = crate::join_tokens(&["le chien"]);
+        insta::assert_debug_snapshot!(loaded.search(&Search::new(&query)), @r#"
+        [
+            "Kéfir\u{1e}c++\u{1e}le chien",
+        ]
+        "#);
+    }
+
+    #[test]
+    fn test_custom_normalization() {
+        let names: &[&str] = &["le café de tamo", "le cafe de kefir"];
+        let index = Index::new_in_memory_with_tokenizer(names, AccentTokenizer);
+        // the normalization is used on the query as well
+        let mut search = Search::new("CAFÉ");
+        search.with_typo_tolerance(crate::TypoConfig::disabled());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le café de tamo",
+        ]
+        "#);
+        // and on the stop words
+        search = Search::new("le tamo");
+        search.with_stop_words(["LE"].map(String::from).into());
+        insta::assert_debug_snapshot!(index.search(&search), @r#"
+        [
+            "le café de tamo",
+        ]
+        "#);
+
+        let index = Index::new_in_memory(names);
+        insta::assert_debug_snapshot!(index.search(&Search::new("CAFÉ")), @r#"
+        [
+            "le café de tamo",
+            "le cafe de kefir",
+        ]
+        "#);
+    }
+}